@@ -0,0 +1,213 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, spanned::Spanned, Data, DeriveInput, Expr, Fields, Index};
+
+/// Derives `common::Size` by summing the size of every field.
+///
+/// Fields can be annotated with `#[size(...)]`:
+/// - `bytes = N` uses `N` (any expression, `self` is in scope) instead of the field's own `size()`.
+/// - `pad_before = N` / `pad_after = N` add padding around the field.
+/// - `skip` leaves the field out entirely.
+#[proc_macro_derive(Size, attributes(size))]
+pub fn derive_size(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+#[derive(Default)]
+struct FieldOptions {
+    bytes: Option<Expr>,
+    pad_before: Option<Expr>,
+    pad_after: Option<Expr>,
+    skip: bool,
+}
+
+impl FieldOptions {
+    fn parse(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+        let mut options = Self::default();
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("size")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("bytes") {
+                    options.bytes = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("pad_before") {
+                    options.pad_before = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("pad_after") {
+                    options.pad_after = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("skip") {
+                    options.skip = true;
+                } else {
+                    return Err(meta.error("expected `bytes`, `pad_before`, `pad_after` or `skip`"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(options)
+    }
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => return Err(syn::Error::new(input.span(), "Size can only be derived for structs")),
+    };
+
+    let mut terms = Vec::new();
+    let members: Vec<(TokenStream2, &syn::Field)> = match fields {
+        Fields::Named(fields) => fields
+            .named
+            .iter()
+            .map(|field| {
+                let ident = field.ident.as_ref().unwrap();
+                (quote!(#ident), field)
+            })
+            .collect(),
+        Fields::Unnamed(fields) => fields
+            .unnamed
+            .iter()
+            .enumerate()
+            .map(|(index, field)| {
+                let index = Index::from(index);
+                (quote!(#index), field)
+            })
+            .collect(),
+        Fields::Unit => Vec::new(),
+    };
+
+    for (member, field) in members {
+        let options = FieldOptions::parse(&field.attrs)?;
+        if options.skip {
+            continue;
+        }
+        if let Some(pad_before) = options.pad_before {
+            terms.push(quote!((#pad_before) as usize));
+        }
+        match options.bytes {
+            Some(bytes) => terms.push(quote!((#bytes) as usize)),
+            None => terms.push(quote!(::common::Size::size(&self.#member))),
+        }
+        if let Some(pad_after) = options.pad_after {
+            terms.push(quote!((#pad_after) as usize));
+        }
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::common::Size for #name #ty_generics #where_clause {
+            fn size(&self) -> usize {
+                0 #(+ #terms)*
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+
+    use super::*;
+
+    fn expanded(input: DeriveInput) -> String {
+        expand(input).unwrap().to_string()
+    }
+
+    #[test]
+    fn sums_fields() {
+        let expected = quote! {
+            impl ::common::Size for Header {
+                fn size(&self) -> usize {
+                    0 + ::common::Size::size(&self.magic) + ::common::Size::size(&self.path)
+                }
+            }
+        };
+        assert_eq!(
+            expected.to_string(),
+            expanded(parse_quote! {
+                struct Header {
+                    magic: u32,
+                    path: Path,
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn field_options() {
+        let expected = quote! {
+            impl ::common::Size for Texture {
+                fn size(&self) -> usize {
+                    0 + (4) as usize + (4) as usize + (self.data.len()) as usize + (16) as usize
+                }
+            }
+        };
+        assert_eq!(
+            expected.to_string(),
+            expanded(parse_quote! {
+                struct Texture {
+                    #[size(pad_before = 4)]
+                    #[size(bytes = 4)]
+                    format: Format,
+                    #[size(skip)]
+                    cached: bool,
+                    #[size(bytes = self.data.len(), pad_after = 16)]
+                    data: Vec<u8>,
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn tuple_and_unit_structs() {
+        let expected = quote! {
+            impl<T: Size> ::common::Size for Wrapper<T> {
+                fn size(&self) -> usize {
+                    0 + ::common::Size::size(&self.0) + ::common::Size::size(&self.1)
+                }
+            }
+        };
+        assert_eq!(
+            expected.to_string(),
+            expanded(parse_quote!(
+                struct Wrapper<T: Size>(u8, T);
+            ))
+        );
+
+        let expected = quote! {
+            impl ::common::Size for Marker {
+                fn size(&self) -> usize {
+                    0
+                }
+            }
+        };
+        assert_eq!(
+            expected.to_string(),
+            expanded(parse_quote!(
+                struct Marker;
+            ))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let err = expand(parse_quote!(
+            enum Kind {
+                A,
+            }
+        ))
+        .unwrap_err();
+        assert_eq!("Size can only be derived for structs", err.to_string());
+
+        let err = expand(parse_quote! {
+            struct Header {
+                #[size(width = 4)]
+                magic: u32,
+            }
+        })
+        .unwrap_err();
+        assert_eq!("expected `bytes`, `pad_before`, `pad_after` or `skip`", err.to_string());
+    }
+}
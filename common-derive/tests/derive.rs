@@ -0,0 +1,36 @@
+use common::{Path, Size};
+
+#[derive(Size)]
+struct Entry {
+    #[size(pad_before = 2)]
+    path: Path,
+    #[size(bytes = 4)]
+    _flags: Flags,
+    #[size(skip)]
+    _cached: Vec<u8>,
+    #[size(bytes = self.data.len(), pad_after = 8)]
+    data: Vec<u8>,
+}
+
+struct Flags;
+
+#[derive(Size)]
+struct Pair(u16, Option<u32>);
+
+#[derive(Size)]
+struct Marker;
+
+#[test]
+fn derived_sizes() {
+    let entry = Entry {
+        path: Path::new("a.dds"),
+        _flags: Flags,
+        _cached: vec![0; 100],
+        data: vec![0; 10],
+    };
+    assert_eq!(2 + (3 + 5) + 4 + 10 + 8, entry.size());
+
+    assert_eq!(6, Pair(1, Some(2)).size());
+    assert_eq!(2, Pair(1, None).size());
+    assert_eq!(0, Marker.size());
+}
@@ -1,18 +1,45 @@
-use std::fmt;
+use std::{fmt, ops::Deref};
 
-use binrw::binrw;
+use binrw::{binrw, PosValue};
+
+pub use common_derive::Size;
+
+#[cfg(feature = "logging")]
+pub mod logging;
 
 #[binrw]
 pub struct Path {
-    #[br(temp)]
+    // The length counts the null character, so even an empty path stores 1.
+    #[br(temp, assert(length != 0, "While parsing Path: Length 0 leaves no room for the null character."))]
+    #[bw(assert(path.len() < u16::MAX as usize, "While writing Path: Length {} exceeds the maximum of {}.", path.len(), u16::MAX - 1))]
     #[bw(calc = (path.len() + 1) as u16)]
     length: u16,
-    #[br(count = length.max(1) - 1, try_map = String::from_utf8)]
+    #[br(count = length - 1, try_map = |x: Vec<u8>| String::from_utf8(x).map_err(|err| format!("While parsing Path: {}.", err)))]
     #[bw(map = |x: &String| x.as_bytes())]
     pub path: String,
-    #[br(temp, assert(null_character == 0))]
-    #[bw(calc = 0)]
-    null_character: u8,
+    #[br(temp, assert(null_character == 0, "While parsing Path: Expected a null character after {:?} at {:#x}, found {:#04X}.", path, null_character.pos, *null_character))]
+    #[bw(calc = PosValue::from(0))]
+    null_character: PosValue<u8>,
+}
+
+impl Path {
+    pub fn new(path: impl Into<String>) -> Path {
+        Path { path: path.into() }
+    }
+}
+
+impl Deref for Path {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.path
+    }
+}
+
+impl AsRef<str> for Path {
+    fn as_ref(&self) -> &str {
+        &self.path
+    }
 }
 
 impl fmt::Debug for Path {
@@ -27,25 +54,72 @@ impl fmt::Display for Path {
     }
 }
 
+/// The characters of `path` with `\` read as `/` and any leading separators dropped.
+fn normalized(path: &str) -> impl Iterator<Item = char> + '_ {
+    path.trim_start_matches(['/', '\\'])
+        .chars()
+        .map(|c| if c == '\\' { '/' } else { c })
+}
+
+/// Whether two paths name the same entry, treating `\` as `/` and ignoring leading separators, so `/foo\bar.dds`
+/// matches `foo/bar.dds`. Every lookup by path goes through this, and maps keyed by path use [`normalize_path`].
+pub fn path_eq(a: &str, b: &str) -> bool {
+    normalized(a).eq(normalized(b))
+}
+
+/// `path` in the form [`path_eq`] compares, for hashing paths that should match each other.
+pub fn normalize_path(path: &str) -> String {
+    normalized(path).collect()
+}
+
+/// [`path_eq`], but also folding ASCII case the way assets written on Windows expect. The by-path lookups here are
+/// case sensitive; this is for callers matching names that came from elsewhere.
+pub fn path_eq_ignore_case(a: &str, b: &str) -> bool {
+    normalized(a)
+        .map(|c| c.to_ascii_lowercase())
+        .eq(normalized(b).map(|c| c.to_ascii_lowercase()))
+}
+
+/// The number of bytes a value takes when written.
+///
+/// Size fields in the formats are filled in from this, so `size()` must equal the length the value actually
+/// serializes to, magic and padding included.
 pub trait Size {
     fn size(&self) -> usize;
 }
 
-impl Size for u32 {
-    fn size(&self) -> usize {
-        4
-    }
+/// The version a pack format writes ahead of its contents, reported the same way whichever format it is.
+pub trait PackVersion {
+    /// The magic written just before the version number, or `None` for formats without a version.
+    const VERSION_MAGIC: Option<[u8; 2]>;
+
+    /// The version number, or `None` when the format or this particular file doesn't give one.
+    fn version_number(&self) -> Option<u16>;
 }
 
+macro_rules! impl_size {
+    ($($ty:ty),*) => {
+        $(
+            impl Size for $ty {
+                fn size(&self) -> usize {
+                    std::mem::size_of::<$ty>()
+                }
+            }
+        )*
+    };
+}
+
+impl_size!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
 impl<T: Size> Size for Option<T> {
     fn size(&self) -> usize {
         self.as_ref().map(|x| x.size()).unwrap_or(0)
     }
 }
 
-impl<const N: usize> Size for [u32; N] {
+impl<T: Size, const N: usize> Size for [T; N] {
     fn size(&self) -> usize {
-        4 * N
+        self.iter().map(|x| x.size()).sum()
     }
 }
 
@@ -60,3 +134,93 @@ impl Size for Path {
         3 + self.path.len()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use binrw::{BinReaderExt, BinWrite};
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let path = Path::new("textures/grass.dds");
+        assert_eq!("textures/grass.dds", &*path);
+        assert!(path.ends_with(".dds"));
+
+        let mut writer = Cursor::new(Vec::new());
+        path.write_le(&mut writer).unwrap();
+        let mut reader = Cursor::new(writer.into_inner());
+        let read: Path = reader.read_le().unwrap();
+
+        assert_eq!(path.as_ref(), read.as_ref());
+        assert_eq!(path.size(), reader.position() as usize);
+    }
+
+    #[test]
+    fn short_paths() {
+        for (path, bytes) in [("", &b"\x01\x00\x00"[..]), ("a", &b"\x02\x00a\x00"[..])] {
+            let path = Path::new(path);
+            let mut writer = Cursor::new(Vec::new());
+            path.write_le(&mut writer).unwrap();
+            assert_eq!(bytes, &writer.get_ref()[..]);
+            assert_eq!(path.size(), bytes.len());
+
+            let read: Path = Cursor::new(bytes).read_le().unwrap();
+            assert_eq!(&*path, &*read);
+        }
+    }
+
+    #[test]
+    fn zero_length() {
+        let mut reader = Cursor::new(b"\x00\x00\x00");
+        let err = reader.read_le::<Path>().unwrap_err();
+        assert!(err.to_string().contains("Length 0"), "{}", err);
+    }
+
+    #[test]
+    fn longest_path() {
+        let path = Path::new("a".repeat(u16::MAX as usize - 1));
+        let mut writer = Cursor::new(Vec::new());
+        path.write_le(&mut writer).unwrap();
+        assert_eq!(path.size(), writer.into_inner().len());
+    }
+
+    #[test]
+    fn over_long_path() {
+        let path = Path::new("a".repeat(u16::MAX as usize));
+        let mut writer = Cursor::new(Vec::new());
+        let err = path.write_le(&mut writer).unwrap_err();
+        assert!(err.to_string().contains("exceeds the maximum"), "{}", err);
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let mut reader = Cursor::new(b"\x03\x00\xff\xfe\x00");
+        let err = reader.read_le::<Path>().unwrap_err();
+        assert!(err.to_string().contains("invalid utf-8"), "{}", err);
+    }
+
+    #[test]
+    fn path_comparison() {
+        assert!(path_eq("/foo\\bar.dds", "foo/bar.dds"));
+        assert!(path_eq("\\\\foo/bar.dds", "//foo\\bar.dds"));
+        assert!(path_eq("", "/"));
+        assert!(!path_eq("foo/bar.dds", "foo/bar.dd"));
+        assert!(!path_eq("/foo\\Bar.DDS", "foo/bar.dds"));
+        assert!(!path_eq("foo//bar.dds", "foo/bar.dds"));
+
+        assert!(path_eq_ignore_case("/foo\\Bar.DDS", "foo/bar.dds"));
+        assert!(!path_eq_ignore_case("/foo\\Bar.DDS", "foo/baz.dds"));
+    }
+
+    #[test]
+    fn bad_null_character() {
+        let mut reader = Cursor::new(b"\x00\x00\x02\x00a\xff");
+        reader.set_position(2);
+        let err = reader.read_le::<Path>().unwrap_err();
+        // The path starts at 2, but the error names where the terminator was expected.
+        assert!(err.to_string().contains(r#"after "a" at 0x5, found 0xFF"#), "{}", err);
+    }
+}
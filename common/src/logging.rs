@@ -0,0 +1,69 @@
+//! Logging options shared by the `luadec` and `repkg` command lines.
+
+use std::{io::Write, str::FromStr};
+
+use log::LevelFilter;
+
+/// How log lines are written to stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `env_logger`'s usual timestamped lines.
+    Plain,
+    /// One JSON object per line, with `timestamp`, `level`, `target` and `message` fields.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<LogFormat, String> {
+        match value {
+            "plain" => Ok(LogFormat::Plain),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format {:?}, expected plain or json", value)),
+        }
+    }
+}
+
+#[derive(clap::Args)]
+pub struct LogArgs {
+    /// Only log errors.
+    #[clap(short = 'q', long)]
+    pub quiet: bool,
+    /// Write log lines as `plain` text or as `json` objects, one per line.
+    #[clap(long, default_value = "plain")]
+    pub log_format: LogFormat,
+}
+
+impl LogArgs {
+    /// Sets up `env_logger` at `level`, or at `Error` with `--quiet`. `RUST_LOG` still overrides either.
+    pub fn init(&self, level: LevelFilter) {
+        let mut builder = env_logger::builder();
+        builder.filter_level(if self.quiet { LevelFilter::Error } else { level });
+        match self.log_format {
+            LogFormat::Plain => builder.default_format(),
+            LogFormat::Json => builder.format(|buf, record| {
+                let line = serde_json::json!({
+                    "timestamp": buf.timestamp().to_string(),
+                    "level": record.level().as_str(),
+                    "target": record.target(),
+                    "message": record.args().to_string(),
+                });
+                writeln!(buf, "{}", line)
+            }),
+        };
+        builder.parse_default_env().init();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_format() {
+        assert_eq!(Ok(LogFormat::Plain), "plain".parse());
+        assert_eq!(Ok(LogFormat::Json), "json".parse());
+        assert!("yaml".parse::<LogFormat>().is_err());
+    }
+}
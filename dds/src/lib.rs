@@ -1,12 +1,68 @@
-use std::mem::size_of;
+#![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+use core::{fmt, mem::size_of};
+#[cfg(feature = "std")]
+use std::io::{Read, Write};
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
 
 use bitflags::bitflags;
+#[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
 pub const MAGIC: u32 = 0x20534444;
 
+#[derive(Debug)]
+pub enum Error {
+    InvalidMagic(u32),
+    /// A slice ended before the `expected` bytes of a header.
+    Truncated {
+        expected: usize,
+        found: usize,
+    },
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidMagic(magic) => write!(f, "Invalid DDS magic {:#010x}.", magic),
+            Error::Truncated { expected, found } => {
+                write!(f, "Expected at least {} bytes of header, found {}.", expected, found)
+            }
+            #[cfg(feature = "std")]
+            Error::Io(err) => err.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
 bitflags! {
-    #[derive(Default, Serialize, Deserialize)]
+    #[derive(Default)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub struct PixelFormatFlags: u32 {
         const ALPHAPIXELS = 0x00000001;
         const ALPHA = 0x00000002;
@@ -34,8 +90,11 @@ pub const PAL8A: PixelFormatFlags = PixelFormatFlags::PAL8A;
 pub const BUMPDUDV: PixelFormatFlags = PixelFormatFlags::BUMPDUDV;
 pub const BUMPLUMINANCE: PixelFormatFlags = PixelFormatFlags::BUMPLUMINANCE;
 
+/// The derived `PartialEq` and `Eq` compare `_size` too, use [`PixelFormat::same_format`] to ask whether two
+/// values describe the same format.
 #[repr(C)]
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct PixelFormat {
     pub _size: u32,
     pub flags: PixelFormatFlags,
@@ -76,6 +135,8 @@ impl PixelFormat {
     pub const G8R8_G8B8: PixelFormat = PixelFormat::from_four_cc(*b"GRGB");
     pub const YUY2: PixelFormat = PixelFormat::from_four_cc(*b"YUY2");
     pub const UYVY: PixelFormat = PixelFormat::from_four_cc(*b"UYVY");
+    /// Marks a header followed by a [`HeaderDx10`].
+    pub const DX10: PixelFormat = PixelFormat::from_four_cc(*b"DX10");
 
     pub const A8R8G8B8: PixelFormat =
         PixelFormat::from_tuple((RGBA, 32, 0x00ff0000, 0x0000ff00, 0x000000ff, 0xff000000));
@@ -119,6 +180,128 @@ impl PixelFormat {
     pub const X8L8V8U8: PixelFormat =
         PixelFormat::from_tuple((BUMPLUMINANCE, 32, 0x000000ff, 0x0000ff00, 0x00ff0000, 0));
 
+    /// Compares everything but `_size`, which says nothing about the format and is often wrong in files written
+    /// by other tools.
+    pub const fn same_format(&self, other: &PixelFormat) -> bool {
+        self.flags.bits() == other.flags.bits()
+            && u32::from_le_bytes(self.four_cc) == u32::from_le_bytes(other.four_cc)
+            && self.rgb_bit_count == other.rgb_bit_count
+            && self.r_bit_mask == other.r_bit_mask
+            && self.g_bit_mask == other.g_bit_mask
+            && self.b_bit_mask == other.b_bit_mask
+            && self.a_bit_mask == other.a_bit_mask
+    }
+
+    /// The size in bytes of each 4x4 block, for the block compressed FourCCs.
+    pub fn block_size(&self) -> Option<usize> {
+        const BLOCK_SIZES: &[(PixelFormat, usize)] = &[
+            (PixelFormat::DXT1, 8),
+            (PixelFormat::DXT2, 16),
+            (PixelFormat::DXT3, 16),
+            (PixelFormat::DXT4, 16),
+            (PixelFormat::DXT5, 16),
+            (PixelFormat::BC4_UNORM, 8),
+            (PixelFormat::BC4_SNORM, 8),
+            (PixelFormat::BC5_UNORM, 16),
+            (PixelFormat::BC5_SNORM, 16),
+        ];
+
+        BLOCK_SIZES
+            .iter()
+            .find(|(pixel_format, _)| pixel_format.same_format(self))
+            .map(|(_, block_size)| *block_size)
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.block_size().is_some()
+    }
+
+    /// Bits per texel, averaged over a block for the compressed formats. The packed YUV FourCCs use 16, anything
+    /// else without a bit count, like [`PixelFormat::DX10`], gives `0`.
+    pub fn bits_per_pixel(&self) -> u32 {
+        if let Some(block_size) = self.block_size() {
+            return block_size as u32 * 8 / 16;
+        }
+
+        let packed = [
+            PixelFormat::R8G8_B8G8,
+            PixelFormat::G8R8_G8B8,
+            PixelFormat::YUY2,
+            PixelFormat::UYVY,
+        ];
+        if packed.iter().any(|pixel_format| pixel_format.same_format(self)) {
+            16
+        } else {
+            self.rgb_bit_count
+        }
+    }
+
+    /// The DXGI format to write in a [`HeaderDx10`] instead, for the 10:10:10:2 masks that readers disagree on
+    /// because of the red and blue reversal. Only [`PixelFormat::A2R10G10B10`], with red in the low bits, has one;
+    /// DXGI has no 10-bit format with blue in the low bits.
+    #[allow(deprecated)]
+    pub fn dxgi_format(&self) -> Option<u32> {
+        PixelFormat::A2R10G10B10
+            .same_format(self)
+            .then_some(DXGI_FORMAT_R10G10B10A2_UNORM)
+    }
+
+    /// The name of the matching constant, if any.
+    #[allow(deprecated)]
+    pub fn name(&self) -> Option<&'static str> {
+        const NAMES: &[(PixelFormat, &str)] = &[
+            (PixelFormat::DXT1, "DXT1"),
+            (PixelFormat::DXT2, "DXT2"),
+            (PixelFormat::DXT3, "DXT3"),
+            (PixelFormat::DXT4, "DXT4"),
+            (PixelFormat::DXT5, "DXT5"),
+            (PixelFormat::BC4_UNORM, "BC4_UNORM"),
+            (PixelFormat::BC4_SNORM, "BC4_SNORM"),
+            (PixelFormat::BC5_UNORM, "BC5_UNORM"),
+            (PixelFormat::BC5_SNORM, "BC5_SNORM"),
+            (PixelFormat::R8G8_B8G8, "R8G8_B8G8"),
+            (PixelFormat::G8R8_G8B8, "G8R8_G8B8"),
+            (PixelFormat::YUY2, "YUY2"),
+            (PixelFormat::UYVY, "UYVY"),
+            (PixelFormat::DX10, "DX10"),
+            (PixelFormat::A8R8G8B8, "A8R8G8B8"),
+            (PixelFormat::X8R8G8B8, "X8R8G8B8"),
+            (PixelFormat::A8B8G8R8, "A8B8G8R8"),
+            (PixelFormat::X8B8G8R8, "X8B8G8R8"),
+            (PixelFormat::G16R16, "G16R16"),
+            (PixelFormat::R5G6B5, "R5G6B5"),
+            (PixelFormat::A1R5G5B5, "A1R5G5B5"),
+            (PixelFormat::X1R5G5B5, "X1R5G5B5"),
+            (PixelFormat::A4R4G4B4, "A4R4G4B4"),
+            (PixelFormat::X4R4G4B4, "X4R4G4B4"),
+            (PixelFormat::R8G8B8, "R8G8B8"),
+            (PixelFormat::A8R3G3B2, "A8R3G3B2"),
+            (PixelFormat::R3G3B2, "R3G3B2"),
+            (PixelFormat::A4L4, "A4L4"),
+            (PixelFormat::L8, "L8"),
+            (PixelFormat::L16, "L16"),
+            (PixelFormat::A8L8, "A8L8"),
+            (PixelFormat::A8L8_ALT, "A8L8_ALT"),
+            (PixelFormat::L8_NVTT1, "L8_NVTT1"),
+            (PixelFormat::L16_NVTT1, "L16_NVTT1"),
+            (PixelFormat::A8L8_NVTT1, "A8L8_NVTT1"),
+            (PixelFormat::A8, "A8"),
+            (PixelFormat::V8U8, "V8U8"),
+            (PixelFormat::Q8W8V8U8, "Q8W8V8U8"),
+            (PixelFormat::V16U16, "V16U16"),
+            (PixelFormat::A2R10G10B10, "A2R10G10B10"),
+            (PixelFormat::A2B10G10R10, "A2B10G10R10"),
+            (PixelFormat::A2W10V10U10, "A2W10V10U10"),
+            (PixelFormat::L6V5U5, "L6V5U5"),
+            (PixelFormat::X8L8V8U8, "X8L8V8U8"),
+        ];
+
+        NAMES
+            .iter()
+            .find(|(pixel_format, _)| pixel_format.same_format(self))
+            .map(|(_, name)| *name)
+    }
+
     #[inline]
     pub const fn from_tuple(
         (flags, rgb_bit_count, r_bit_mask, g_bit_mask, b_bit_mask, a_bit_mask): (
@@ -155,10 +338,25 @@ impl PixelFormat {
             a_bit_mask: 0,
         }
     }
+
+    /// The FourCC as text, or `None` without the [`FOURCC`] flag or when it isn't valid UTF-8.
+    pub fn four_cc_str(&self) -> Option<&str> {
+        if self.flags.contains(FOURCC) {
+            core::str::from_utf8(&self.four_cc).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Sets the FourCC along with the [`FOURCC`] flag, leaving the other flags, bit count and masks alone.
+    pub fn set_four_cc(&mut self, four_cc: [u8; 4]) {
+        self.four_cc = four_cc;
+        self.flags.insert(FOURCC);
+    }
 }
 
 bitflags! {
-    #[derive(Serialize, Deserialize)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub struct HeaderFlags: u32 {
         const CAPS        = 0x00000001;
         const HEIGHT      = 0x00000002;
@@ -170,14 +368,15 @@ bitflags! {
         const DEPTH       = 0x00800000;
     }
 
-    #[derive(Serialize, Deserialize)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub struct SurfaceFlags: u32 {
         const COMPLEX = 0x00000008;
         const TEXTURE = 0x00001000;
         const MIPMAP  = 0x00400000;
     }
 
-    #[derive(Default, Serialize, Deserialize)]
+    #[derive(Default)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub struct Caps2: u32 {
         const CUBEMAP   = 0x00000200;
         const POSITIVEX = 0x00000400;
@@ -243,8 +442,110 @@ pub const CUBEMAP_ALLFACES: Caps2 = Caps2 {
         | CUBEMAP_NEGATIVEZ.bits,
 };
 
+/// Each cubemap face with the name [`CapsSummary`] reports it by, in the order faces are stored.
+pub const CUBEMAP_FACES: [(Caps2, &str); 6] = [
+    (CUBEMAP_POSITIVEX, "+X"),
+    (CUBEMAP_NEGATIVEX, "-X"),
+    (CUBEMAP_POSITIVEY, "+Y"),
+    (CUBEMAP_NEGATIVEY, "-Y"),
+    (CUBEMAP_POSITIVEZ, "+Z"),
+    (CUBEMAP_NEGATIVEZ, "-Z"),
+];
+
+/// What the surface flags and caps2 of a [`Header`] say it holds, see [`Header::caps`].
+///
+/// Displays as a comma separated list, e.g. `texture, cubemap (+X -X +Y -Y +Z -Z), mipmaps`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapsSummary {
+    pub texture: bool,
+    pub mipmaps: bool,
+    pub volume: bool,
+    pub cubemap: bool,
+    /// The faces a cubemap holds, as `CUBEMAP_*` bits.
+    pub faces: Caps2,
+}
+
+impl CapsSummary {
+    /// The names of the faces present, see [`CUBEMAP_FACES`].
+    pub fn face_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        CUBEMAP_FACES
+            .iter()
+            .filter(move |(face, _)| self.faces.contains(*face))
+            .map(|(_, name)| *name)
+    }
+}
+
+impl fmt::Display for CapsSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut separator = "";
+        let mut item = |f: &mut fmt::Formatter<'_>, name: &str| {
+            let result = write!(f, "{}{}", separator, name);
+            separator = ", ";
+            result
+        };
+
+        if self.texture {
+            item(f, "texture")?;
+        }
+        if self.cubemap {
+            item(f, "cubemap (")?;
+            for (index, name) in self.face_names().enumerate() {
+                write!(f, "{}{}", if index == 0 { "" } else { " " }, name)?;
+            }
+            f.write_str(")")?;
+        }
+        if self.volume {
+            item(f, "volume")?;
+        }
+        if self.mipmaps {
+            item(f, "mipmaps")?;
+        }
+        if separator.is_empty() {
+            f.write_str("none")?;
+        }
+        Ok(())
+    }
+}
+
+/// How rows of uncompressed texels are padded, see [`Header::for_texture_with`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PitchAlignment {
+    /// Rows end on the byte after their last texel, as the DDS spec computes the pitch.
+    #[default]
+    Byte,
+    /// Rows are padded to a multiple of 4 bytes, as some loaders expect of 24-bit formats like `R8G8B8`.
+    Dword,
+}
+
+impl PitchAlignment {
+    /// The bytes from the start of one row to the next for `width` texels of `bits_per_pixel` bits, saturating at
+    /// `u32::MAX` for rows too wide for a DDS header to describe.
+    pub const fn pitch(self, width: u32, bits_per_pixel: u32) -> u32 {
+        let pitch = (width as u64 * bits_per_pixel as u64).div_ceil(8);
+        let pitch = match self {
+            PitchAlignment::Byte => pitch,
+            PitchAlignment::Dword => pitch.next_multiple_of(4),
+        };
+        if pitch > u32::MAX as u64 {
+            u32::MAX
+        } else {
+            pitch as u32
+        }
+    }
+}
+
+/// Where one mip level of the first surface lies in a DDS file's data, see [`Header::mip_layout`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MipLevel {
+    pub offset: usize,
+    pub size: usize,
+    pub width: u32,
+    pub height: u32,
+}
+
 #[repr(C)]
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Header {
     pub _size: u32,
     pub header_flags: HeaderFlags,
@@ -283,9 +584,376 @@ impl Default for Header {
     }
 }
 
+impl Header {
+    /// A header for a 2D texture or, with `cubemap`, one with all six faces. The pitch of uncompressed formats or
+    /// the linear size of the top level of block compressed ones is filled in, along with the flags saying which.
+    pub fn for_texture(
+        pixel_format: PixelFormat,
+        width: u32,
+        height: u32,
+        mip_map_count: u32,
+        cubemap: bool,
+    ) -> Header {
+        Header::for_texture_with(
+            pixel_format,
+            width,
+            height,
+            mip_map_count,
+            cubemap,
+            PitchAlignment::Byte,
+        )
+    }
+
+    /// Like [`Header::for_texture`], with the pitch of uncompressed formats aligned as `alignment` says. The data
+    /// written after the header has to be padded to match.
+    pub fn for_texture_with(
+        pixel_format: PixelFormat,
+        width: u32,
+        height: u32,
+        mip_map_count: u32,
+        cubemap: bool,
+        alignment: PitchAlignment,
+    ) -> Header {
+        let mut header = Header {
+            height,
+            width,
+            depth: 1,
+            mip_map_count,
+            pixel_format,
+            ..Default::default()
+        };
+        if cubemap {
+            header.caps2 = CUBEMAP_ALLFACES;
+        }
+        header.pitch_or_linear_size = match pixel_format.block_size() {
+            Some(block_size) => width
+                .div_ceil(4)
+                .max(1)
+                .saturating_mul(height.div_ceil(4).max(1))
+                .saturating_mul(block_size as u32),
+            None => alignment.pitch(width, pixel_format.bits_per_pixel()),
+        };
+
+        header.canonicalize();
+        header
+    }
+
+    /// Sets the header and surface flags that follow from the other fields, clearing any that don't, so the flags
+    /// agree with the header whatever was set before. [`Dds::write`] writes headers this way.
+    ///
+    /// The flags every texture needs are always set. `MIPMAPCOUNT` and the `MIPMAP` surface flag follow a
+    /// `mip_map_count` above 1, `DEPTH` follows the volume caps, and `COMPLEX` follows any of mipmaps, cubemaps or
+    /// volumes. A non-zero `pitch_or_linear_size` is flagged as a linear size for block compressed formats and as a
+    /// pitch otherwise, except for [`Header::is_dx10`] headers whose format isn't known here. Other bits are kept.
+    pub fn canonicalize(&mut self) {
+        let mipmaps = self.mip_map_count > 1;
+        let complex = mipmaps || self.is_cubemap() || self.is_volume();
+
+        self.header_flags.insert(HEADER_FLAGS_TEXTURE);
+        self.header_flags.set(HeaderFlags::MIPMAPCOUNT, mipmaps);
+        self.header_flags.set(HeaderFlags::DEPTH, self.is_volume());
+        if !self.is_dx10() {
+            let sized = self.pitch_or_linear_size != 0;
+            let compressed = self.pixel_format.is_compressed();
+            self.header_flags.set(HeaderFlags::LINEARSIZE, sized && compressed);
+            self.header_flags.set(HeaderFlags::PITCH, sized && !compressed);
+        }
+
+        self.surface_flags.insert(SurfaceFlags::TEXTURE);
+        self.surface_flags.set(SurfaceFlags::MIPMAP, mipmaps);
+        self.surface_flags.set(SurfaceFlags::COMPLEX, complex);
+    }
+
+    pub fn is_cubemap(&self) -> bool {
+        self.caps2.contains(Caps2::CUBEMAP)
+    }
+
+    pub fn is_volume(&self) -> bool {
+        self.caps2.contains(Caps2::VOLUME)
+    }
+
+    /// Summarizes the surface flags and caps2, including which faces a cubemap has.
+    pub fn caps(&self) -> CapsSummary {
+        let cubemap = self.is_cubemap();
+        CapsSummary {
+            texture: self.surface_flags.contains(SurfaceFlags::TEXTURE),
+            mipmaps: self.surface_flags.contains(SurfaceFlags::MIPMAP),
+            volume: self.is_volume(),
+            cubemap,
+            faces: if cubemap {
+                self.caps2 & CUBEMAP_ALLFACES
+            } else {
+                Caps2::empty()
+            },
+        }
+    }
+
+    /// The mip levels of the first surface, which is the first face of a cubemap, largest first. Block compressed
+    /// levels round up to whole 4x4 blocks, each level of a volume holds all of its slices, and a `mip_map_count`
+    /// of 0 means a single level. No more than 32 levels are listed, as a `u32` dimension is 1 by then, and sizes
+    /// and offsets too large for the header to describe saturate.
+    ///
+    /// Sizes come from the pixel format's bit count, so they're 0 for [`Header::is_dx10`] headers.
+    #[cfg(feature = "alloc")]
+    pub fn mip_layout(&self) -> Vec<MipLevel> {
+        let pixel_format = &self.pixel_format;
+        let mut offset: usize = 0;
+        (0..self.mip_map_count.clamp(1, 32))
+            .map(|level| {
+                let (width, height) = ((self.width >> level).max(1), (self.height >> level).max(1));
+                let mut size = match pixel_format.block_size() {
+                    Some(block_size) => (width.div_ceil(4) as u64)
+                        .saturating_mul(height.div_ceil(4) as u64)
+                        .saturating_mul(block_size as u64),
+                    None => (width as u64 * pixel_format.bits_per_pixel() as u64)
+                        .div_ceil(8)
+                        .saturating_mul(height as u64),
+                };
+                if self.is_volume() {
+                    size = size.saturating_mul((self.depth >> level).max(1) as u64);
+                }
+                let size = core::convert::TryFrom::try_from(size).unwrap_or(usize::MAX);
+
+                let mip = MipLevel {
+                    offset,
+                    size,
+                    width,
+                    height,
+                };
+                offset = offset.saturating_add(size);
+                mip
+            })
+            .collect()
+    }
+
+    pub fn is_dx10(&self) -> bool {
+        self.pixel_format.flags.contains(FOURCC) && self.pixel_format.four_cc == *b"DX10"
+    }
+
+    /// Parses the magic and header at the start of `data`, returning the header and everything after it. A
+    /// [`HeaderDx10`] isn't parsed, so when [`Header::is_dx10`] is set the rest starts with one, see
+    /// [`HeaderDx10::from_bytes`].
+    pub fn from_bytes(data: &[u8]) -> Result<(Header, &[u8]), Error> {
+        if data.len() < 128 {
+            return Err(Error::Truncated {
+                expected: 128,
+                found: data.len(),
+            });
+        }
+        let (magic, rest) = data.split_at(4);
+        let magic = u32::from_le_bytes([magic[0], magic[1], magic[2], magic[3]]);
+        if magic != MAGIC {
+            return Err(Error::InvalidMagic(magic));
+        }
+
+        let (header, rest) = rest.split_at(124);
+        let mut bytes = [0; 124];
+        bytes.copy_from_slice(header);
+        Ok((Header::from_le_bytes(&bytes), rest))
+    }
+
+    fn from_le_bytes(bytes: &[u8; 124]) -> Header {
+        let mut words = bytes.chunks_exact(4).map(|word| [word[0], word[1], word[2], word[3]]);
+        let mut next = || u32::from_le_bytes(words.next().unwrap());
+
+        Header {
+            _size: next(),
+            header_flags: HeaderFlags::from_bits_truncate(next()),
+            height: next(),
+            width: next(),
+            pitch_or_linear_size: next(),
+            depth: next(),
+            mip_map_count: next(),
+            _reserved1: [(); 11].map(|_| next()),
+            pixel_format: PixelFormat {
+                _size: next(),
+                flags: PixelFormatFlags::from_bits_truncate(next()),
+                four_cc: next().to_le_bytes(),
+                rgb_bit_count: next(),
+                r_bit_mask: next(),
+                g_bit_mask: next(),
+                b_bit_mask: next(),
+                a_bit_mask: next(),
+            },
+            surface_flags: SurfaceFlags::from_bits_truncate(next()),
+            caps2: Caps2::from_bits_truncate(next()),
+            _caps3: next(),
+            _caps4: next(),
+            _reserved2: next(),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn to_le_bytes(self) -> [u8; 124] {
+        let pixel_format = &self.pixel_format;
+        // Spelled out so edition 2018 iterates the arrays by value.
+        let words = IntoIterator::into_iter([
+            self._size,
+            self.header_flags.bits(),
+            self.height,
+            self.width,
+            self.pitch_or_linear_size,
+            self.depth,
+            self.mip_map_count,
+        ])
+        .chain(IntoIterator::into_iter(self._reserved1))
+        .chain(IntoIterator::into_iter([
+            pixel_format._size,
+            pixel_format.flags.bits(),
+            u32::from_le_bytes(pixel_format.four_cc),
+            pixel_format.rgb_bit_count,
+            pixel_format.r_bit_mask,
+            pixel_format.g_bit_mask,
+            pixel_format.b_bit_mask,
+            pixel_format.a_bit_mask,
+            self.surface_flags.bits(),
+            self.caps2.bits(),
+            self._caps3,
+            self._caps4,
+            self._reserved2,
+        ]));
+
+        let mut bytes = [0; 124];
+        for (chunk, word) in bytes.chunks_exact_mut(4).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+pub const DXGI_FORMAT_R10G10B10A2_UNORM: u32 = 24;
+
+pub const RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+pub const RESOURCE_DIMENSION_TEXTURE3D: u32 = 4;
+pub const RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+/// The extended header that follows a [`Header`] whose pixel format is [`PixelFormat::DX10`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HeaderDx10 {
+    pub dxgi_format: u32,
+    pub resource_dimension: u32,
+    pub misc_flag: u32,
+    pub array_size: u32,
+    pub misc_flags2: u32,
+}
+
+impl HeaderDx10 {
+    /// An extended header describing the texture `header` does, as `dxgi_format`.
+    pub fn for_header(header: &Header, dxgi_format: u32) -> HeaderDx10 {
+        HeaderDx10 {
+            dxgi_format,
+            resource_dimension: if header.is_volume() {
+                RESOURCE_DIMENSION_TEXTURE3D
+            } else {
+                RESOURCE_DIMENSION_TEXTURE2D
+            },
+            misc_flag: if header.is_cubemap() {
+                RESOURCE_MISC_TEXTURECUBE
+            } else {
+                0
+            },
+            array_size: 1,
+            misc_flags2: 0,
+        }
+    }
+
+    /// Parses the extended header at the start of `data`, the rest [`Header::from_bytes`] returns for a DX10 file,
+    /// returning it and the pixel data after it.
+    pub fn from_bytes(data: &[u8]) -> Result<(HeaderDx10, &[u8]), Error> {
+        if data.len() < 20 {
+            return Err(Error::Truncated {
+                expected: 20,
+                found: data.len(),
+            });
+        }
+        let (header, rest) = data.split_at(20);
+        let mut words = header
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]));
+        let mut next = || words.next().unwrap();
+        let header = HeaderDx10 {
+            dxgi_format: next(),
+            resource_dimension: next(),
+            misc_flag: next(),
+            array_size: next(),
+            misc_flags2: next(),
+        };
+        Ok((header, rest))
+    }
+}
+
+/// A whole DDS file.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone)]
+pub struct Dds {
+    pub header: Header,
+    pub header10: Option<HeaderDx10>,
+    pub data: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl Dds {
+    pub fn read<R: Read>(reader: &mut R) -> Result<Dds, Error> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        let magic = u32::from_le_bytes(magic);
+        if magic != MAGIC {
+            return Err(Error::InvalidMagic(magic));
+        }
+
+        let mut header = [0; 124];
+        reader.read_exact(&mut header)?;
+        let header = Header::from_le_bytes(&header);
+
+        let header10 = if header.is_dx10() {
+            let mut words = [0; 20];
+            reader.read_exact(&mut words)?;
+            Some(HeaderDx10::from_bytes(&words)?.0)
+        } else {
+            None
+        };
+
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Ok(Dds { header, header10, data })
+    }
+
+    /// Writes the file as [`Dds::read`] expects it, `header10` only when the header asks for one. Every field is
+    /// written little-endian whatever the host's byte order, so the output is the same on every target.
+    ///
+    /// Pixel formats with a [`PixelFormat::dxgi_format`] are written as [`PixelFormat::DX10`] with an extended
+    /// header for that format, rather than with masks other readers may take the wrong way round.
+    pub fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut header = self.header;
+        let mut header10 = self.header10;
+        if let Some(dxgi_format) = header.pixel_format.dxgi_format() {
+            header10 = Some(HeaderDx10::for_header(&header, dxgi_format));
+            header.pixel_format = PixelFormat::DX10;
+        }
+        header.canonicalize();
+        writer.write_all(&MAGIC.to_le_bytes())?;
+        writer.write_all(&header.to_le_bytes())?;
+        if header.is_dx10() {
+            let header10 = header10.unwrap_or_default();
+            for word in [
+                header10.dxgi_format,
+                header10.resource_dimension,
+                header10.misc_flag,
+                header10.array_size,
+                header10.misc_flags2,
+            ] {
+                writer.write_all(&word.to_le_bytes())?;
+            }
+        }
+        writer.write_all(&self.data)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::mem::size_of;
+    use core::mem::size_of;
 
     use super::*;
 
@@ -293,5 +961,357 @@ mod tests {
     fn proper_size() {
         assert_eq!(32, size_of::<PixelFormat>(), "PixelFormat size mismatch.");
         assert_eq!(124, size_of::<Header>(), "Header size mismatch.");
+        assert_eq!(20, size_of::<HeaderDx10>(), "HeaderDx10 size mismatch.");
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(Some("DXT5"), PixelFormat::DXT5.name());
+        assert_eq!(Some("A8R8G8B8"), PixelFormat::A8R8G8B8.name());
+        assert_eq!(None, PixelFormat::from_four_cc(*b"ABCD").name());
+    }
+
+    #[test]
+    fn four_cc_str() {
+        assert_eq!(Some("DXT5"), PixelFormat::DXT5.four_cc_str());
+        assert_eq!(None, PixelFormat::A8R8G8B8.four_cc_str());
+        assert_eq!(None, PixelFormat::from_four_cc([0xFF, 0, 0, 0]).four_cc_str());
+
+        // The FourCC is only read with its flag set.
+        let mut pixel_format = PixelFormat {
+            four_cc: *b"DXT1",
+            ..PixelFormat::default()
+        };
+        assert_eq!(None, pixel_format.four_cc_str());
+        pixel_format.set_four_cc(*b"DXT1");
+        assert_eq!(Some("DXT1"), pixel_format.four_cc_str());
+        assert!(pixel_format.same_format(&PixelFormat::DXT1));
+    }
+
+    #[test]
+    fn same_format() {
+        let stale = PixelFormat {
+            _size: 0,
+            ..PixelFormat::DXT5
+        };
+        assert_ne!(PixelFormat::DXT5, stale);
+        assert!(PixelFormat::DXT5.same_format(&stale));
+        assert!(!PixelFormat::DXT5.same_format(&PixelFormat::DXT1));
+        assert_eq!(Some("DXT5"), stale.name());
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(Some(8), PixelFormat::DXT1.block_size());
+        assert_eq!(4, PixelFormat::DXT1.bits_per_pixel());
+        assert_eq!(Some(16), PixelFormat::BC5_UNORM.block_size());
+        assert_eq!(8, PixelFormat::BC5_UNORM.bits_per_pixel());
+        assert!(PixelFormat::BC5_UNORM.is_compressed());
+
+        assert!(!PixelFormat::A8R8G8B8.is_compressed());
+        assert_eq!(None, PixelFormat::A8R8G8B8.block_size());
+        assert_eq!(32, PixelFormat::A8R8G8B8.bits_per_pixel());
+        assert_eq!(16, PixelFormat::YUY2.bits_per_pixel());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn read() {
+        let mut data = std::vec::Vec::new();
+        data.extend_from_slice(&MAGIC.to_le_bytes());
+        for word in [124, HEADER_FLAGS_TEXTURE.bits(), 8, 16, 0, 0, 1] {
+            data.extend_from_slice(&u32::to_le_bytes(word));
+        }
+        data.extend_from_slice(&[0; 44]);
+        for word in [32, FOURCC.bits(), u32::from_le_bytes(*b"DX10"), 0, 0, 0, 0, 0] {
+            data.extend_from_slice(&u32::to_le_bytes(word));
+        }
+        // Cubemaps are complex surfaces, so the header reads back as written.
+        for word in [
+            (SURFACE_FLAGS_TEXTURE | SURFACE_FLAGS_CUBEMAP).bits(),
+            Caps2::CUBEMAP.bits(),
+            0,
+            0,
+            0,
+        ] {
+            data.extend_from_slice(&u32::to_le_bytes(word));
+        }
+        for word in [98, 3, 0, 1, 0] {
+            data.extend_from_slice(&u32::to_le_bytes(word));
+        }
+        data.extend_from_slice(&[1, 2, 3]);
+
+        let dds = Dds::read(&mut std::io::Cursor::new(&data)).unwrap();
+        assert_eq!((16, 8), (dds.header.width, dds.header.height));
+        assert_eq!(Some("DX10"), dds.header.pixel_format.name());
+        assert!(dds.header.is_cubemap() && !dds.header.is_volume());
+        assert_eq!(98, dds.header10.unwrap().dxgi_format);
+        assert_eq!(&[1, 2, 3], &dds.data[..]);
+
+        let mut written = std::vec::Vec::new();
+        dds.write(&mut written).unwrap();
+        assert_eq!(data, written);
+
+        data[0] = 0;
+        assert!(matches!(
+            Dds::read(&mut std::io::Cursor::new(&data)),
+            Err(Error::InvalidMagic(0x20534400))
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn write_little_endian() {
+        let dds = Dds {
+            header: Header {
+                width: 0x0102,
+                pixel_format: PixelFormat::DXT5,
+                ..Header::default()
+            },
+            header10: None,
+            data: std::vec::Vec::new(),
+        };
+        let mut written = std::vec::Vec::new();
+        dds.write(&mut written).unwrap();
+
+        assert_eq!(128, written.len());
+        assert_eq!(b"DDS ", &written[..4]);
+        assert_eq!([124, 0, 0, 0], written[4..8]);
+        assert_eq!([0x02, 0x01, 0, 0], written[16..20]);
+        // The pixel format starts 72 bytes into the header.
+        assert_eq!([32, 0, 0, 0], written[76..80]);
+        assert_eq!(b"DXT5", &written[84..88]);
+    }
+
+    #[test]
+    fn for_texture() {
+        let header = Header::for_texture(PixelFormat::DXT5, 8, 8, 4, false);
+        assert_eq!(
+            HEADER_FLAGS_TEXTURE | HEADER_FLAGS_MIPMAP | HEADER_FLAGS_LINEARSIZE,
+            header.header_flags
+        );
+        assert_eq!(64, header.pitch_or_linear_size);
+        assert_eq!(SURFACE_FLAGS_TEXTURE | SURFACE_FLAGS_MIPMAP, header.surface_flags);
+
+        let header = Header::for_texture(PixelFormat::R8G8B8, 3, 2, 1, true);
+        assert_eq!(HEADER_FLAGS_TEXTURE | HEADER_FLAGS_PITCH, header.header_flags);
+        assert_eq!(9, header.pitch_or_linear_size);
+        assert!(header.is_cubemap());
+        assert_eq!(CUBEMAP_ALLFACES, header.caps2);
+    }
+
+    #[test]
+    fn pitch_alignment() {
+        for (alignment, pitch) in [(PitchAlignment::Byte, 51), (PitchAlignment::Dword, 52)] {
+            let header = Header::for_texture_with(PixelFormat::R8G8B8, 17, 4, 1, false, alignment);
+            assert_eq!(pitch, header.pitch_or_linear_size, "{:?}", alignment);
+            assert!(header.header_flags.contains(HEADER_FLAGS_PITCH));
+        }
+        assert_eq!(
+            Header::for_texture(PixelFormat::R8G8B8, 17, 4, 1, false).pitch_or_linear_size,
+            51
+        );
+
+        // Already aligned rows and block compressed formats are unaffected.
+        assert_eq!(68, PitchAlignment::Dword.pitch(17, 32));
+        assert_eq!(u32::MAX, PitchAlignment::Byte.pitch(u32::MAX, 32));
+        assert_eq!(
+            u32::MAX,
+            Header::for_texture(PixelFormat::DXT5, u32::MAX, u32::MAX, 1, false).pitch_or_linear_size
+        );
+        let header = Header::for_texture_with(PixelFormat::DXT1, 17, 4, 1, false, PitchAlignment::Dword);
+        assert_eq!(40, header.pitch_or_linear_size);
+    }
+
+    #[test]
+    fn canonicalize() {
+        let mut header = Header {
+            width: 16,
+            height: 16,
+            mip_map_count: 4,
+            pixel_format: PixelFormat::DXT1,
+            pitch_or_linear_size: 128,
+            header_flags: HeaderFlags::CAPS | HeaderFlags::PITCH,
+            surface_flags: SurfaceFlags::empty(),
+            ..Header::default()
+        };
+        header.canonicalize();
+        assert_eq!(
+            HEADER_FLAGS_TEXTURE | HEADER_FLAGS_MIPMAP | HEADER_FLAGS_LINEARSIZE,
+            header.header_flags
+        );
+        assert_eq!(SURFACE_FLAGS_TEXTURE | SURFACE_FLAGS_MIPMAP, header.surface_flags);
+
+        header.mip_map_count = 1;
+        header.depth = 4;
+        header.caps2 = Caps2::VOLUME;
+        header.canonicalize();
+        assert_eq!(
+            HEADER_FLAGS_TEXTURE | HEADER_FLAGS_VOLUME | HEADER_FLAGS_LINEARSIZE,
+            header.header_flags
+        );
+        assert_eq!(SURFACE_FLAGS_TEXTURE | SurfaceFlags::COMPLEX, header.surface_flags);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn mip_layout() {
+        let layout = Header::for_texture(PixelFormat::DXT1, 64, 64, 7, false).mip_layout();
+        assert_eq!(
+            [0, 2048, 2560, 2688, 2720, 2728, 2736],
+            layout.iter().map(|mip| mip.offset).collect::<Vec<_>>()[..]
+        );
+        assert_eq!(
+            MipLevel {
+                offset: 2736,
+                size: 8,
+                width: 1,
+                height: 1
+            },
+            layout[6]
+        );
+
+        let layout = Header::for_texture(PixelFormat::R8G8B8, 3, 2, 0, false).mip_layout();
+        assert_eq!(
+            [MipLevel {
+                offset: 0,
+                size: 18,
+                width: 3,
+                height: 2
+            }],
+            layout[..]
+        );
+
+        // Header values a file can hold, however unlikely, mustn't overflow.
+        let layout = Header::for_texture(PixelFormat::A8R8G8B8, 4, 4, 40, false).mip_layout();
+        assert_eq!(32, layout.len());
+        assert_eq!((1, 1, 4), (layout[31].width, layout[31].height, layout[31].size));
+
+        let mut header = Header::for_texture(PixelFormat::A8R8G8B8, u32::MAX, u32::MAX, 2, false);
+        header.caps2 |= Caps2::VOLUME;
+        header.depth = u32::MAX;
+        let layout = header.mip_layout();
+        assert_eq!(usize::MAX, layout[0].size);
+        assert_eq!(usize::MAX, layout[1].offset);
+
+        let layout = Header::for_texture(PixelFormat::DXT5, u32::MAX, u32::MAX, 1, false).mip_layout();
+        assert_eq!((u32::MAX, u32::MAX), (layout[0].width, layout[0].height));
+    }
+
+    #[test]
+    fn caps_summary() {
+        let caps = Header::for_texture(PixelFormat::DXT1, 8, 8, 4, true).caps();
+        assert!(caps.texture && caps.cubemap && caps.mipmaps && !caps.volume);
+        assert_eq!(6, caps.face_names().count());
+        assert!(caps
+            .face_names()
+            .eq(["+X", "-X", "+Y", "-Y", "+Z", "-Z"].iter().copied()));
+
+        let mut header = Header::for_texture(PixelFormat::DXT1, 8, 8, 1, false);
+        header.caps2 = CUBEMAP_POSITIVEX | CUBEMAP_NEGATIVEZ;
+        assert!(header.caps().face_names().eq(["+X", "-Z"].iter().copied()));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn caps_display() {
+        use std::string::ToString;
+
+        let header = Header::for_texture(PixelFormat::DXT1, 8, 8, 4, true);
+        assert_eq!(
+            "texture, cubemap (+X -X +Y -Y +Z -Z), mipmaps",
+            header.caps().to_string()
+        );
+        assert_eq!("texture", Header::default().caps().to_string());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn header_from_bytes() {
+        let header = Header::for_texture(PixelFormat::DXT1, 8, 4, 2, false);
+        let dds = Dds {
+            header,
+            header10: None,
+            data: std::vec![7; 24],
+        };
+        let mut data = std::vec::Vec::new();
+        dds.write(&mut data).unwrap();
+
+        let (read, rest) = Header::from_bytes(&data).unwrap();
+        assert_eq!(header.to_le_bytes(), read.to_le_bytes());
+        assert_eq!(&dds.data[..], rest);
+
+        assert!(matches!(
+            Header::from_bytes(&data[..127]),
+            Err(Error::Truncated {
+                expected: 128,
+                found: 127
+            })
+        ));
+        data[0] = b'X';
+        assert!(matches!(Header::from_bytes(&data), Err(Error::InvalidMagic(_))));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn header10_from_bytes() {
+        let header = Header {
+            pixel_format: PixelFormat::DX10,
+            ..Default::default()
+        };
+        let header10 = HeaderDx10 {
+            dxgi_format: 71,
+            resource_dimension: 3,
+            misc_flag: 0,
+            array_size: 1,
+            misc_flags2: 0,
+        };
+        let dds = Dds {
+            header,
+            header10: Some(header10),
+            data: std::vec![1, 2, 3],
+        };
+        let mut data = std::vec::Vec::new();
+        dds.write(&mut data).unwrap();
+
+        let (read, rest) = Header::from_bytes(&data).unwrap();
+        assert!(read.is_dx10());
+        assert_eq!((header10, &[1, 2, 3][..]), HeaderDx10::from_bytes(rest).unwrap());
+    }
+
+    #[cfg(feature = "std")]
+    #[allow(deprecated)]
+    #[test]
+    fn ten_bit_formats_use_dx10() {
+        assert_eq!(
+            Some(DXGI_FORMAT_R10G10B10A2_UNORM),
+            PixelFormat::A2R10G10B10.dxgi_format()
+        );
+        assert_eq!(None, PixelFormat::A2B10G10R10.dxgi_format());
+        assert_eq!(None, PixelFormat::A8R8G8B8.dxgi_format());
+
+        let dds = Dds {
+            header: Header::for_texture(PixelFormat::A2R10G10B10, 4, 4, 1, true),
+            header10: None,
+            data: std::vec![0; 6 * 64],
+        };
+        let mut data = std::vec::Vec::new();
+        dds.write(&mut data).unwrap();
+
+        let read = Dds::read(&mut &data[..]).unwrap();
+        assert_eq!(*b"DX10", read.header.pixel_format.four_cc);
+        assert_eq!(Some("DX10"), read.header.pixel_format.name());
+        assert_eq!(
+            Some(HeaderDx10 {
+                dxgi_format: DXGI_FORMAT_R10G10B10A2_UNORM,
+                resource_dimension: RESOURCE_DIMENSION_TEXTURE2D,
+                misc_flag: RESOURCE_MISC_TEXTURECUBE,
+                array_size: 1,
+                misc_flags2: 0,
+            }),
+            read.header10
+        );
+        assert_eq!(16, read.header.pitch_or_linear_size);
+        assert_eq!(dds.data, read.data);
     }
 }
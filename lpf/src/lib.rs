@@ -1,19 +1,22 @@
-use std::fmt;
+use std::{collections::HashMap, fmt};
 
 use binrw::binrw;
-use common::Path;
+use common::{normalize_path, path_eq, PackVersion, Path};
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[binrw]
 #[brw(repr = u16, magic = b"\xFC\xFC")]
 pub enum Version {
+    #[default]
     V0 = 0,
     V1,
 }
 
-impl Default for Version {
-    fn default() -> Self {
-        Self::V0
+impl PackVersion for Version {
+    const VERSION_MAGIC: Option<[u8; 2]> = Some(*b"\xFC\xFC");
+
+    fn version_number(&self) -> Option<u16> {
+        Some(*self as u16)
     }
 }
 
@@ -21,6 +24,7 @@ impl Default for Version {
 pub struct Global {
     pub path: Path,
     #[br(temp)]
+    #[bw(assert(data.len() <= u32::MAX as usize, "While writing Global: Size {} exceeds the maximum of {}.", data.len(), u32::MAX))]
     #[bw(calc = data.len() as u32)]
     size: u32,
     #[br(count = size)]
@@ -42,6 +46,7 @@ pub mod v0 {
     #[binrw]
     pub struct Script {
         #[br(temp)]
+        #[bw(assert(data.len() <= u32::MAX as usize, "While writing Script: Size {} exceeds the maximum of {}.", data.len(), u32::MAX))]
         #[bw(calc = data.len() as u32)]
         size: u32,
 
@@ -92,12 +97,14 @@ pub struct LuaPackFile {
     pub version: Option<Version>,
 
     #[br(temp)]
+    #[bw(assert(globals.len() <= u16::MAX as usize, "While writing LuaPackFile: {} globals exceed the maximum of {}.", globals.len(), u16::MAX))]
     #[bw(calc = globals.len() as u16)]
     global_count: u16,
     #[br(count = global_count)]
     pub globals: Vec<Global>,
 
     #[br(temp)]
+    #[bw(assert(scripts.len() <= u16::MAX as usize, "While writing LuaPackFile: {} scripts exceed the maximum of {}.", scripts.len(), u16::MAX))]
     #[bw(calc = scripts.len() as u16)]
     script_count: u16,
 
@@ -114,3 +121,145 @@ impl fmt::Debug for LuaPackFile {
             .finish()
     }
 }
+
+impl PackVersion for LuaPackFile {
+    const VERSION_MAGIC: Option<[u8; 2]> = Version::VERSION_MAGIC;
+
+    /// `None` for files written before versions were added.
+    fn version_number(&self) -> Option<u16> {
+        self.version.as_ref().and_then(Version::version_number)
+    }
+}
+
+impl LuaPackFile {
+    /// Data of the global at `path`, compared with [`path_eq`].
+    pub fn global(&self, path: &str) -> Option<&[u8]> {
+        self.globals
+            .iter()
+            .find(|global| path_eq(&global.path, path))
+            .map(|global| global.data.as_slice())
+    }
+
+    /// Every global keyed by its path as [`normalize_path`] gives it, so the keys match what [`LuaPackFile::global`]
+    /// finds. The first of any duplicate paths wins, as it does there.
+    pub fn globals_map(&self) -> HashMap<String, &[u8]> {
+        let mut map = HashMap::with_capacity(self.globals.len());
+        for global in &self.globals {
+            map.entry(normalize_path(&global.path))
+                .or_insert(global.data.as_slice());
+        }
+        map
+    }
+
+    /// Decompiles every script with [`luadec::decompile`], pairing each result with the script's path, or `None` for
+    /// [`Script::V0`] scripts, which have none. A script that fails to decompile doesn't stop the rest.
+    #[cfg(feature = "luadec")]
+    pub fn decompile_all(&self) -> Vec<(Option<String>, Result<String, luadec::Error>)> {
+        self.scripts
+            .iter()
+            .map(|script| match script {
+                Script::V0(script) => (None, luadec::decompile(&script.data)),
+                Script::V1(script) => (Some(script.path.to_string()), luadec::decompile(&script.script.data)),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use binrw::{BinReaderExt, BinWrite};
+
+    use super::*;
+
+    fn path(path: &str) -> Vec<u8> {
+        let mut data = ((path.len() + 1) as u16).to_le_bytes().to_vec();
+        data.extend_from_slice(path.as_bytes());
+        data.push(0);
+        data
+    }
+
+    #[test]
+    fn global_lookup() {
+        let mut data = b"\xFC\xFC\x00\x00".to_vec();
+        data.extend_from_slice(&3u16.to_le_bytes());
+        for (name, value) in [
+            ("/config/speed", &b"fast"[..]),
+            ("debug", b"\x01"),
+            ("\\config\\speed", b"slow"),
+        ] {
+            data.extend(path(name));
+            data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            data.extend_from_slice(value);
+        }
+        data.extend_from_slice(&0u16.to_le_bytes());
+        let lpf: LuaPackFile = Cursor::new(data).read_le().unwrap();
+
+        assert_eq!(Some(&b"fast"[..]), lpf.global("config/speed"));
+        assert_eq!(Some(&b"fast"[..]), lpf.global("/config/speed"));
+        assert_eq!(Some(&b"\x01"[..]), lpf.global("/debug"));
+        assert_eq!(None, lpf.global("config"));
+
+        let map = lpf.globals_map();
+        assert_eq!(2, map.len(), "{:?}", map);
+        assert_eq!(Some(&&b"fast"[..]), map.get("config/speed"));
+    }
+
+    #[test]
+    fn pack_version() {
+        let lpf: LuaPackFile = Cursor::new(b"\xFC\xFC\x01\x00\x00\x00\x00\x00").read_le().unwrap();
+        assert_eq!(Some(1), lpf.version_number());
+        assert_eq!(Some(*b"\xFC\xFC"), LuaPackFile::VERSION_MAGIC);
+
+        let lpf: LuaPackFile = Cursor::new(b"\x00\x00\x00\x00").read_le().unwrap();
+        assert_eq!(None, lpf.version_number());
+    }
+
+    #[cfg(feature = "luadec")]
+    #[test]
+    fn decompile_all() {
+        let script = |name: &str, data: &[u8]| {
+            Script::V1(v1::Script {
+                path: Path::new(name),
+                script: v0::Script { data: data.to_vec() },
+            })
+        };
+        let lpf = LuaPackFile {
+            version: Some(Version::V1),
+            globals: Vec::new(),
+            scripts: vec![
+                script(
+                    "/scripts/assignments.lua",
+                    include_bytes!("../../luadec/tests/fixtures/assignments.luac"),
+                ),
+                script("/scripts/opaque.lua", b"not bytecode"),
+            ],
+        };
+
+        match &lpf.decompile_all()[..] {
+            [(Some(first), Ok(source)), (Some(second), Err(_))] => {
+                assert_eq!("/scripts/assignments.lua", first);
+                assert_eq!(include_str!("../../luadec/tests/fixtures/assignments.lua"), source);
+                assert_eq!("/scripts/opaque.lua", second);
+            }
+            results => panic!("unexpected results {:?}", results),
+        }
+    }
+
+    #[test]
+    fn too_many_scripts() {
+        let script = || Script::V0(v0::Script { data: Vec::new() });
+        let mut lpf = LuaPackFile {
+            version: Some(Version::V0),
+            globals: Vec::new(),
+            scripts: (0..u16::MAX).map(|_| script()).collect(),
+        };
+        lpf.write_le(&mut Cursor::new(Vec::new())).unwrap();
+
+        lpf.scripts.push(script());
+        let err = lpf.write_le(&mut Cursor::new(Vec::new())).unwrap_err();
+        assert!(matches!(err, binrw::Error::AssertFail { .. }), "{:?}", err);
+        assert!(err.to_string().contains("65536 scripts exceed"), "{}", err);
+    }
+}
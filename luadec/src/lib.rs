@@ -0,0 +1,1455 @@
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Unable to parse the chunk at 0x{offset:X}: {message}")]
+    Parse { offset: usize, message: String },
+    #[error("{0}")]
+    Decompile(String),
+}
+
+// num-derive 0.3 puts its impls inside a `const _`, which newer compilers flag.
+#[allow(non_local_definitions)]
+mod parser {
+    use std::{convert::TryFrom, fmt::Debug};
+
+    use num_derive::{FromPrimitive, ToPrimitive};
+    #[allow(unused_imports)]
+    use num_traits::{FromPrimitive, ToPrimitive};
+
+    use nom::{
+        bytes::complete::take,
+        combinator::{eof, map_res, verify},
+        error::ErrorKind,
+        multi::many_m_n,
+        number::complete::{
+            be_f32, be_f64, be_i16, be_i32, be_i64, be_u16, be_u32, be_u64, le_f32, le_f64, le_i16, le_i32, le_i64,
+            le_u16, le_u32, le_u64, le_u8,
+        },
+        IResult,
+    };
+
+    use super::BoxError;
+
+    type InfallibleResult<T> = Result<T, std::convert::Infallible>;
+
+    const TEST_NUMBER: f64 = 3.141_592_653_589_793E8;
+
+    fn unsupported<T>(input: &[u8]) -> IResult<&[u8], T> {
+        Err(nom::Err::Failure(nom::error::Error::new(input, ErrorKind::Verify)))
+    }
+
+    #[allow(dead_code)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct Header<'a> {
+        pub id_chunk: u8,
+        pub signature: &'a str,
+        pub version: u8,
+        pub endianess: u8,
+        pub sizeof_int: u8,
+        pub sizeof_size_t: u8,
+        pub sizeof_instruction: u8,
+        pub size_instruction: u8,
+        pub size_op: u8,
+        pub size_b: u8,
+        pub sizeof_number: u8,
+        pub test_number: &'a [u8],
+    }
+
+    #[allow(dead_code)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct Local<'a> {
+        pub name: &'a str,
+        pub start: i32,
+        pub end: i32,
+    }
+
+    /// Lua 4.0 keeps strings, numbers and nested functions apart; it has no boolean constants and nil is only
+    /// ever pushed by `PushNil`.
+    #[derive(Debug, Clone)]
+    pub struct Constants<'a> {
+        pub strings: Vec<&'a str>,
+        pub numbers: Vec<f64>,
+        pub functions: Vec<Function<'a>>,
+    }
+
+    impl<'a> Constants<'a> {
+        fn get<'b, T>(kind: &str, values: &'b [T], index: usize) -> Result<&'b T, BoxError> {
+            values.get(index).ok_or_else(|| {
+                format!(
+                    "{} constant {} is out of range, the function has {}",
+                    kind,
+                    index,
+                    values.len()
+                )
+                .into()
+            })
+        }
+
+        pub fn string(&self, index: usize) -> Result<&'a str, BoxError> {
+            Self::get("String", &self.strings, index).copied()
+        }
+
+        pub fn number(&self, index: usize) -> Result<f64, BoxError> {
+            Self::get("Number", &self.numbers, index).copied()
+        }
+
+        pub fn function(&self, index: usize) -> Result<&Function<'a>, BoxError> {
+            Self::get("Function", &self.functions, index)
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, FromPrimitive, ToPrimitive)]
+    pub enum OpCode {
+        End,
+        Return,
+        Call,
+        TailCall,
+        PushNil,
+        Pop,
+        PushInt,
+        PushString,
+        PushNumber,
+        PushNegativeNumber,
+        PushUpValue,
+        GetLocal,
+        GetGlobal,
+        GetTable,
+        GetDotted,
+        GetIndexed,
+        PushSelf,
+        CreateTable,
+        SetLocal,
+        SetGlobal,
+        SetTable,
+        SetList,
+        SetMap,
+        Add,
+        AddInt,
+        Subtract,
+        Multiply,
+        Divide,
+        Power,
+        Concat,
+        Minus,
+        Not,
+        JumpNotEqual,
+        JumpEqual,
+        JumpLessThan,
+        JumpLessThanEqual,
+        JumpGreaterThan,
+        JumpGreaterThanEqual,
+        JumpIfTrue,
+        JumpIfFalse,
+        JumpOnTrue,
+        JumpOnFalse,
+        Jump,
+        PushNilJump,
+        ForPrep,
+        ForLoop,
+        LForPrep,
+        LForLoop,
+        Closure,
+    }
+
+    #[allow(unused)]
+    impl OpCode {
+        pub fn is_jump(&self) -> bool {
+            *self >= OpCode::JumpNotEqual && *self <= OpCode::Jump
+        }
+
+        pub fn is_conditional_jump(&self) -> bool {
+            self.is_jump() && *self != OpCode::Jump
+        }
+
+        /// The mnemonic `lopcodes.h` gives the opcode, without its `OP_` prefix.
+        pub fn name(&self) -> &'static str {
+            use OpCode::*;
+            match *self {
+                End => "END",
+                Return => "RETURN",
+                Call => "CALL",
+                TailCall => "TAILCALL",
+                PushNil => "PUSHNIL",
+                Pop => "POP",
+                PushInt => "PUSHINT",
+                PushString => "PUSHSTRING",
+                PushNumber => "PUSHNUM",
+                PushNegativeNumber => "PUSHNEGNUM",
+                PushUpValue => "PUSHUPVALUE",
+                GetLocal => "GETLOCAL",
+                GetGlobal => "GETGLOBAL",
+                GetTable => "GETTABLE",
+                GetDotted => "GETDOTTED",
+                GetIndexed => "GETINDEXED",
+                PushSelf => "PUSHSELF",
+                CreateTable => "CREATETABLE",
+                SetLocal => "SETLOCAL",
+                SetGlobal => "SETGLOBAL",
+                SetTable => "SETTABLE",
+                SetList => "SETLIST",
+                SetMap => "SETMAP",
+                Add => "ADD",
+                AddInt => "ADDI",
+                Subtract => "SUB",
+                Multiply => "MULT",
+                Divide => "DIV",
+                Power => "POW",
+                Concat => "CONCAT",
+                Minus => "MINUS",
+                Not => "NOT",
+                JumpNotEqual => "JMPNE",
+                JumpEqual => "JMPEQ",
+                JumpLessThan => "JMPLT",
+                JumpLessThanEqual => "JMPLE",
+                JumpGreaterThan => "JMPGT",
+                JumpGreaterThanEqual => "JMPGE",
+                JumpIfTrue => "JMPT",
+                JumpIfFalse => "JMPF",
+                JumpOnTrue => "JMPONT",
+                JumpOnFalse => "JMPONF",
+                Jump => "JMP",
+                PushNilJump => "PUSHNILJMP",
+                ForPrep => "FORPREP",
+                ForLoop => "FORLOOP",
+                LForPrep => "LFORPREP",
+                LForLoop => "LFORLOOP",
+                Closure => "CLOSURE",
+            }
+        }
+    }
+
+    pub enum OpCodeMode {
+        Unsigned,
+        Signed,
+        AB,
+        None,
+    }
+
+    #[derive(PartialEq, Eq, PartialOrd, Ord)]
+    pub enum StackChange {
+        Constant(u8),
+        Delta,
+        None,
+    }
+
+    impl Debug for StackChange {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                Self::Constant(u) => write!(f, "Constant({})", u),
+                Self::Delta => write!(f, "Delta"),
+                Self::None => write!(f, "None"),
+            }
+        }
+    }
+
+    impl OpCode {
+        pub const fn mode(self) -> OpCodeMode {
+            use OpCode::*;
+            use OpCodeMode::*;
+            match self {
+                End => None,
+                Return => Unsigned,
+                Call | TailCall => AB,
+                PushNil | Pop => Unsigned,
+                PushInt => Signed,
+                PushString | PushNumber | PushNegativeNumber | PushUpValue | GetLocal | GetGlobal => Unsigned,
+                GetTable => None,
+                GetDotted | GetIndexed | PushSelf | CreateTable | SetLocal | SetGlobal => Unsigned,
+                SetTable | SetList => AB,
+                SetMap => Unsigned,
+                Add => None,
+                AddInt => Signed,
+                Subtract | Multiply | Divide | Power => None,
+                Concat => Unsigned,
+                Minus | Not => None,
+                JumpNotEqual | JumpEqual | JumpLessThan | JumpLessThanEqual | JumpGreaterThan
+                | JumpGreaterThanEqual | JumpIfTrue | JumpIfFalse | JumpOnTrue | JumpOnFalse | Jump => Signed,
+                PushNilJump => None,
+                ForPrep | ForLoop | LForPrep | LForLoop => Signed,
+                Closure => AB,
+            }
+        }
+
+        pub const fn push_count(self) -> StackChange {
+            use OpCode::*;
+            use StackChange::*;
+            match self {
+                End | Return => None,
+                Call => Delta,
+                TailCall => None,
+                PushNil => Delta,
+                Pop => None,
+                PushInt | PushString | PushNumber | PushNegativeNumber | PushUpValue | GetLocal | GetGlobal
+                | GetTable | GetDotted | GetIndexed => Constant(1),
+                PushSelf => Constant(2),
+                CreateTable => Constant(1),
+                SetLocal | SetGlobal => None,
+                SetTable | SetList | SetMap => None,
+                Add | AddInt | Subtract | Multiply | Divide | Power => Constant(1),
+                Concat => Constant(1),
+                Minus | Not => Constant(1),
+                JumpNotEqual | JumpEqual | JumpLessThan | JumpLessThanEqual | JumpGreaterThan
+                | JumpGreaterThanEqual | JumpIfTrue | JumpIfFalse | JumpOnTrue | JumpOnFalse | Jump | PushNilJump
+                | ForPrep | ForLoop => None,
+                LForPrep => Constant(2),
+                LForLoop => None,
+                Closure => Constant(1),
+            }
+        }
+
+        pub const fn pop_count(self) -> StackChange {
+            use OpCode::*;
+            use StackChange::*;
+            match self {
+                End => None,
+                Return | Call | TailCall => Delta,
+                PushNil => None,
+                Pop => Delta,
+                PushInt | PushString | PushNumber | PushNegativeNumber | PushUpValue | GetLocal | GetGlobal => None,
+                GetTable => Constant(2),
+                GetDotted | GetIndexed | PushSelf => Constant(1),
+                CreateTable => None,
+                SetLocal | SetGlobal => Constant(1),
+                SetTable | SetList | SetMap => Delta,
+                Add => Constant(2),
+                AddInt => Constant(1),
+                Subtract | Multiply | Divide | Power => Constant(2),
+                Concat => Delta,
+                Minus | Not => Constant(1),
+                JumpNotEqual | JumpEqual | JumpLessThan | JumpLessThanEqual | JumpGreaterThan
+                | JumpGreaterThanEqual => Constant(2),
+                JumpIfTrue | JumpIfFalse | JumpOnTrue | JumpOnFalse => Constant(1),
+                Jump => None,
+                PushNilJump => None,
+                ForPrep => None,
+                ForLoop => Constant(3),
+                LForPrep => None,
+                LForLoop => Constant(3),
+                Closure => Delta,
+            }
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    pub struct Instruction {
+        instruction: usize,
+        size_instruction: u8,
+        size_op: u8,
+        size_b: u8,
+    }
+
+    #[allow(unused)]
+    impl Instruction {
+        #[inline]
+        pub fn try_op(&self) -> Option<OpCode> {
+            FromPrimitive::from_usize(self.instruction & !((!0) << self.size_op))
+        }
+
+        #[inline]
+        pub fn op(&self) -> OpCode {
+            self.try_op().expect("Invalid Instruction!")
+        }
+
+        #[inline]
+        pub const fn u(&self) -> usize {
+            self.instruction >> self.size_op
+        }
+
+        #[inline]
+        pub const fn s(&self) -> isize {
+            (self.u() as isize) - (((1 << (self.size_instruction - self.size_op)) - 1) >> 1)
+        }
+
+        #[inline]
+        pub const fn a(&self) -> usize {
+            self.instruction >> (self.size_op + self.size_b)
+        }
+
+        #[inline]
+        pub const fn b(&self) -> usize {
+            (self.instruction >> self.size_op) & !((!0) << self.size_b)
+        }
+
+        pub fn push_count(&self) -> usize {
+            match self.op().push_count() {
+                StackChange::Constant(r) => r as usize,
+                StackChange::None => 0,
+                StackChange::Delta => match self.op() {
+                    OpCode::PushNil => self.u(),
+                    OpCode::Call => self.b(),
+                    _ => unreachable!(),
+                },
+                _ => unreachable!(),
+            }
+        }
+
+        pub fn pop_count(&self) -> usize {
+            match self.op().pop_count() {
+                StackChange::Constant(r) => r as usize,
+                StackChange::None => 0,
+                StackChange::Delta => match self.op() {
+                    OpCode::Pop => self.u(),
+                    OpCode::SetTable => self.b(),
+                    // `a()` only picks which batch of list indices the values land at.
+                    OpCode::SetList => self.b(),
+                    // A key and a value for each entry.
+                    OpCode::SetMap => self.u() * 2,
+                    OpCode::Concat => self.u(),
+                    OpCode::Closure => self.b(),
+                    OpCode::Call => self.a(),
+                    OpCode::Return => self.u(),
+                    _ => unreachable!(),
+                },
+            }
+        }
+    }
+
+    impl Instruction {
+        /// The arguments as the opcode's mode reads them, empty for opcodes without any.
+        pub fn operands(&self) -> String {
+            match self.op().mode() {
+                OpCodeMode::Unsigned => format!("{}", self.u()),
+                OpCodeMode::Signed => format!("{}", self.s()),
+                OpCodeMode::AB => format!("{}, {}", self.a(), self.b()),
+                OpCodeMode::None => "".to_string(),
+            }
+        }
+    }
+
+    impl Debug for Instruction {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}({})", self.op(), self.operands())
+        }
+    }
+
+    #[allow(dead_code)]
+    #[derive(Debug, Clone)]
+    pub struct Function<'a> {
+        pub source: &'a str,
+        pub line: i32,
+        pub param_count: i32,
+        pub is_vararg: bool,
+        pub max_stack_size: i32,
+        pub locals: Vec<Local<'a>>,
+        pub lines: Vec<i32>,
+        pub constants: Constants<'a>,
+        pub code: Vec<Instruction>,
+    }
+
+    fn header(input: &[u8]) -> IResult<&[u8], Header<'_>> {
+        let (input, id_chunk) = verify(le_u8, |x| *x == 0x1b)(input)?;
+        let (input, signature) = verify(map_res(take(3usize), std::str::from_utf8), |x: &str| x == "Lua")(input)?;
+        let (input, version) = verify(le_u8, |x| *x == 0x40)(input)?;
+        let (input, endianess) = verify(le_u8, |x| *x <= 1)(input)?;
+        let (input, sizeof_int) = verify(le_u8, |x| matches!(*x, 2 | 4 | 8))(input)?;
+        let (input, sizeof_size_t) = verify(le_u8, |x| matches!(*x, 2 | 4 | 8))(input)?;
+        let (input, sizeof_instruction) = verify(le_u8, |x| matches!(*x, 2 | 4 | 8))(input)?;
+        let (input, size_instruction) = verify(le_u8, |x| *x <= sizeof_instruction * 8)(input)?;
+        let (input, size_op) = verify(le_u8, |x| *x > 0 && *x < size_instruction)(input)?;
+        let (input, size_b) = verify(le_u8, |x| (size_op as usize + *x as usize) < size_instruction as usize)(input)?;
+        let (input, sizeof_number) = verify(le_u8, |x| matches!(*x, 4 | 8))(input)?;
+        let (input, test_number) = take(sizeof_number)(input)?;
+
+        let header = Header {
+            id_chunk,
+            signature,
+            version,
+            endianess,
+            sizeof_int,
+            sizeof_size_t,
+            sizeof_instruction,
+            size_instruction,
+            size_op,
+            size_b,
+            sizeof_number,
+            test_number,
+        };
+
+        // Lua checks the number format (and thereby the endianess) by truncating both sides to an integer.
+        let expected = if sizeof_number == 4 {
+            TEST_NUMBER as f32 as f64
+        } else {
+            TEST_NUMBER
+        };
+        verify(|input| number(input, header), |x| *x as i64 == expected as i64)(test_number)?;
+
+        Ok((input, header))
+    }
+
+    fn number<'a>(input: &'a [u8], header: Header<'a>) -> IResult<&'a [u8], f64> {
+        match (header.sizeof_number, header.endianess) {
+            (0x04, 0) => map_res(be_f32, |x| InfallibleResult::Ok(x as f64))(input),
+            (0x04, 1) => map_res(le_f32, |x| InfallibleResult::Ok(x as f64))(input),
+            (0x08, 0) => be_f64(input),
+            (0x08, 1) => le_f64(input),
+            _ => unsupported(input),
+        }
+    }
+
+    fn instruction<'a>(start: &'a [u8], header: Header<'a>) -> IResult<&'a [u8], Instruction> {
+        let input = start;
+        let (input, instruction) = match (header.sizeof_instruction, header.endianess) {
+            (0x02, 0) => map_res(be_u16, |x| InfallibleResult::Ok(x as u64))(input),
+            (0x02, 1) => map_res(le_u16, |x| InfallibleResult::Ok(x as u64))(input),
+            (0x04, 0) => map_res(be_u32, |x| InfallibleResult::Ok(x as u64))(input),
+            (0x04, 1) => map_res(le_u32, |x| InfallibleResult::Ok(x as u64))(input),
+            (0x08, 0) => be_u64(input),
+            (0x08, 1) => le_u64(input),
+            _ => unsupported(input),
+        }?;
+
+        let instruction = Instruction {
+            instruction: instruction as usize,
+            size_instruction: header.size_instruction,
+            size_op: header.size_op,
+            size_b: header.size_b,
+        };
+
+        match instruction.try_op() {
+            Some(_) => Ok((input, instruction)),
+            None => unsupported(start),
+        }
+    }
+
+    fn int<'a>(input: &'a [u8], header: Header<'a>) -> IResult<&'a [u8], i32> {
+        match (header.sizeof_int, header.endianess) {
+            (0x02, 0) => map_res(be_i16, |x| InfallibleResult::Ok(x as i32))(input),
+            (0x02, 1) => map_res(le_i16, |x| InfallibleResult::Ok(x as i32))(input),
+            (0x04, 0) => be_i32(input),
+            (0x04, 1) => le_i32(input),
+            (0x08, 0) => map_res(be_i64, i32::try_from)(input),
+            (0x08, 1) => map_res(le_i64, i32::try_from)(input),
+            _ => unsupported(input),
+        }
+    }
+
+    fn size_t<'a>(input: &'a [u8], header: Header<'a>) -> IResult<&'a [u8], usize> {
+        match (header.sizeof_size_t, header.endianess) {
+            (0x02, 0) => map_res(be_u16, |x| InfallibleResult::Ok(x as usize))(input),
+            (0x02, 1) => map_res(le_u16, |x| InfallibleResult::Ok(x as usize))(input),
+            (0x04, 0) => map_res(be_u32, |x| InfallibleResult::Ok(x as usize))(input),
+            (0x04, 1) => map_res(le_u32, |x| InfallibleResult::Ok(x as usize))(input),
+            (0x08, 0) => map_res(be_u64, |x| InfallibleResult::Ok(x as usize))(input),
+            (0x08, 1) => map_res(le_u64, |x| InfallibleResult::Ok(x as usize))(input),
+            _ => unsupported(input),
+        }
+    }
+
+    fn string<'a>(input: &'a [u8], header: Header<'a>) -> IResult<&'a [u8], &'a str> {
+        let (input, length) = size_t(input, header)?;
+        let (input, str) = map_res(take(length), std::str::from_utf8)(input)?;
+        Ok((input, if length > 0 { &str[..str.len() - 1] } else { str }))
+    }
+
+    fn local<'a>(input: &'a [u8], header: Header<'a>) -> IResult<&'a [u8], Local<'a>> {
+        let (input, name) = string(input, header)?;
+        let (input, start) = int(input, header)?;
+        let (input, end) = int(input, header)?;
+        Ok((input, Local { name, start, end }))
+    }
+
+    fn locals<'a>(input: &'a [u8], header: Header<'a>) -> IResult<&'a [u8], Vec<Local<'a>>> {
+        let (input, count) = int(input, header)?;
+        many_m_n(count as usize, count as usize, |input| local(input, header))(input)
+    }
+
+    fn lines<'a>(input: &'a [u8], header: Header<'a>) -> IResult<&'a [u8], Vec<i32>> {
+        let (input, count) = int(input, header)?;
+        many_m_n(count as usize, count as usize, |input| int(input, header))(input)
+    }
+
+    fn constants<'a>(input: &'a [u8], header: Header<'a>) -> IResult<&'a [u8], Constants<'a>> {
+        let (input, count) = int(input, header)?;
+        let (input, strings) = many_m_n(count as usize, count as usize, |input| string(input, header))(input)?;
+        let (input, count) = int(input, header)?;
+        let (input, numbers) = many_m_n(count as usize, count as usize, |input| number(input, header))(input)?;
+        let (input, count) = int(input, header)?;
+        let (input, functions) = many_m_n(count as usize, count as usize, |input| function(input, header))(input)?;
+
+        Ok((
+            input,
+            Constants {
+                strings,
+                numbers,
+                functions,
+            },
+        ))
+    }
+
+    fn code<'a>(input: &'a [u8], header: Header<'a>) -> IResult<&'a [u8], Vec<Instruction>> {
+        let (input, count) = int(input, header)?;
+        let (input, code) = verify(
+            many_m_n(count as usize, count as usize, |input| instruction(input, header)),
+            |code: &[Instruction]| matches!(code.last(), Some(instruction) if instruction.op() == OpCode::End),
+        )(input)?;
+        Ok((input, code))
+    }
+
+    fn function<'a>(input: &'a [u8], header: Header<'a>) -> IResult<&'a [u8], Function<'a>> {
+        let (input, source) = string(input, header)?;
+        let (input, line) = int(input, header)?;
+        let (input, param_count) = int(input, header)?;
+        let (input, is_vararg) = map_res(le_u8, |x| InfallibleResult::Ok(x == 1))(input)?;
+        let (input, max_stack_size) = int(input, header)?;
+
+        let (input, locals) = locals(input, header)?;
+        let (input, lines) = lines(input, header)?;
+        let (input, constants) = constants(input, header)?;
+        let (input, code) = code(input, header)?;
+
+        Ok((
+            input,
+            Function {
+                source,
+                line,
+                param_count,
+                is_vararg,
+                max_stack_size,
+                locals,
+                lines,
+                constants,
+                code,
+            },
+        ))
+    }
+
+    pub fn lua(input: &[u8]) -> IResult<&[u8], (Header<'_>, Function<'_>)> {
+        let (input, header) = header(input)?;
+        let (input, function) = function(input, header)?;
+        let (input, _) = eof(input)?;
+
+        Ok((input, (header, function)))
+    }
+}
+
+mod code_generation {
+    use std::{collections::VecDeque, fmt::Debug};
+
+    use super::{parser::*, BoxError};
+
+    #[derive(Clone)]
+    pub struct Node {
+        instruction: Instruction,
+        children: Vec<Node>,
+    }
+
+    impl Debug for Node {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            if !self.children.is_empty() {
+                write!(f, "Node({:?}, {:#?})", self.instruction, self.children)
+            } else {
+                write!(f, "Node({:?})", self.instruction)
+            }
+        }
+    }
+
+    impl Node {
+        #[allow(unused)]
+        pub fn instruction_count(&self) -> usize {
+            self.children.iter().map(|node| node.instruction_count()).sum::<usize>() + 1
+        }
+    }
+
+    /// Builds the tree of nodes, failing on code that pops values nothing pushed or jumps past its end.
+    pub fn to_nodes(instructions: Vec<Instruction>, _constants: &Constants) -> Result<Vec<Node>, BoxError> {
+        let mut queue: VecDeque<Instruction> = instructions.into_iter().rev().collect();
+        let mut unused: VecDeque<Node> = VecDeque::new();
+        let mut terminated = Vec::new();
+        let length = queue.len();
+        // (position, terminated.len()) before each instruction, so backward jumps can find their body.
+        let mut marks: Vec<(usize, usize)> = Vec::with_capacity(length);
+
+        while !queue.is_empty() {
+            let instruction = queue.pop_back().unwrap();
+            let position = length - queue.len() - 1;
+            marks.push((position, terminated.len()));
+            log::debug!(
+                "{: <30?} {} {} {:?}",
+                instruction,
+                instruction.pop_count(),
+                instruction.push_count(),
+                unused.iter().map(|node| node.instruction).collect::<Vec<Instruction>>()
+            );
+
+            let push_count = instruction.push_count();
+            let pop_count = match instruction.op() {
+                // `a()` is the function's stack slot, and the call consumes it along with every argument above.
+                OpCode::Call => {
+                    let depth: usize = unused.iter().map(|node| node.instruction.push_count()).sum();
+                    depth.saturating_sub(instruction.a())
+                }
+                _ => instruction.pop_count(),
+            };
+
+            let mut children = Vec::new();
+            let mut needed = pop_count;
+
+            while needed > 0 {
+                let next_unused = unused
+                    .pop_back()
+                    .ok_or_else(|| format!("{:?} at {} pops more values than were pushed", instruction, position))?;
+                needed = needed.saturating_sub(next_unused.instruction.push_count());
+                children.push(next_unused);
+            }
+
+            if instruction.op().is_jump() && instruction.s() > 0 {
+                let offset = instruction.s() as usize;
+                if offset > queue.len() {
+                    return Err(format!(
+                        "{:?} at {} jumps {} past the end of the code",
+                        instruction,
+                        position,
+                        offset - queue.len()
+                    )
+                    .into());
+                }
+                let jump: Vec<Instruction> = queue.split_off(queue.len() - offset).into_iter().rev().collect();
+                children.extend(to_nodes(jump, _constants)?);
+            } else if instruction.op().is_conditional_jump() && instruction.s() < 0 {
+                // A backward conditional jump closes a `repeat` loop; its body starts at the jump target.
+                let target = position as isize + 1 + instruction.s();
+                if let Some((_, mark)) = marks.iter().find(|(position, _)| *position as isize == target) {
+                    children.extend(terminated.split_off(*mark));
+                }
+            }
+
+            let node = Node { instruction, children };
+
+            if push_count != 0 {
+                unused.push_back(node);
+            } else {
+                terminated.push(node);
+            }
+        }
+
+        if !unused.is_empty() {
+            return Err(format!("{} values are left unused", unused.len()).into());
+        }
+        Ok(terminated)
+    }
+
+    /// Lua 4.0's reserved words, which can't follow a `.` even though they look like names.
+    const RESERVED: &[&str] = &[
+        "and", "break", "do", "else", "elseif", "end", "for", "function", "if", "in", "local", "nil", "not", "or",
+        "repeat", "return", "then", "until", "while",
+    ];
+
+    fn is_identifier(name: &str) -> bool {
+        let mut chars = name.chars();
+        chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !RESERVED.contains(&name)
+    }
+
+    #[allow(unused)]
+    pub fn process_node(node: &Node, locals: &mut Vec<Local>, constants: &Constants) -> Result<String, BoxError> {
+        // A forward conditional jump whose body ends by jumping back is a `while` loop.
+        let looping = node.instruction.op().is_conditional_jump()
+            && node.instruction.s() > 0
+            && node
+                .children
+                .last()
+                .is_some_and(|child| child.instruction.op() == OpCode::Jump && child.instruction.s() < 0);
+        let children = if looping {
+            &node.children[..node.children.len() - 1]
+        } else {
+            &node.children[..]
+        };
+        let children: Vec<String> = children
+            .iter()
+            .map(|node| process_node(node, locals, constants))
+            .collect::<Result<_, _>>()?;
+        let instruction = node.instruction;
+
+        use OpCode::*;
+        Ok(match instruction.op() {
+            End => "".to_string(),
+            Return => format!("return {}", children.into_iter().collect::<Vec<String>>().join(", ")),
+            Call => {
+                // Children come off the stack last pushed first, so the callee is last and the arguments reversed.
+                // A `PushSelf` callee fills two slots and already reads `obj:method`, so `obj` isn't repeated as
+                // an argument.
+                let (callee, args) = children.split_last().ok_or("Call without a function")?;
+                let args: Vec<&str> = args.iter().rev().map(String::as_str).collect();
+                format!("{}({})", callee, args.join(", "))
+            }
+            //TailCall
+            PushNil => (0..instruction.u()).map(|_| "nil".to_owned()).collect::<String>(),
+            //Pop
+            PushInt => instruction.s().to_string(),
+            PushString => format!("\"{}\"", constants.string(instruction.u())?),
+            PushNumber => constants.number(instruction.u())?.to_string(),
+            PushNegativeNumber => (-constants.number(instruction.u())?).to_string(),
+            //PushUpValue
+            GetLocal => locals
+                .get(instruction.u())
+                .map(|l| l.name.to_string())
+                .unwrap_or(format!("local_{}", instruction.u())),
+            GetGlobal => constants.string(instruction.u())?.to_string(),
+            // Both come off the stack, the key first since it was pushed last.
+            GetTable => match &children[..] {
+                [key, table] => format!("{}[{}]", table, key),
+                _ => return Err("GetTable without a table and key".into()),
+            },
+            GetDotted => {
+                let name = constants.string(instruction.u())?;
+                let table = children.first().ok_or("GetDotted without a table")?;
+                if is_identifier(name) {
+                    format!("{}.{}", table, name)
+                } else {
+                    format!("{}[\"{}\"]", table, name)
+                }
+            }
+            // The key is a local, the table the only child.
+            GetIndexed => format!(
+                "{}[{}]",
+                children.first().ok_or("GetIndexed without a table")?,
+                locals
+                    .get(instruction.u())
+                    .map(|l| l.name.to_string())
+                    .unwrap_or(format!("local_{}", instruction.u()))
+            ),
+            PushSelf => format!(
+                "{}:{}",
+                children.first().ok_or("PushSelf without an object")?,
+                constants.string(instruction.u())?
+            ),
+            CreateTable => {
+                if instruction.u() > 0 {
+                    format!("{{n={}}}", instruction.u())
+                } else {
+                    "{}".to_string()
+                }
+            }
+            //SetLocal,
+            SetGlobal => format!(
+                "{} = {}",
+                constants.string(instruction.u())?,
+                children.first().ok_or("SetGlobal without a value")?
+            ),
+            // The value was pushed last, so it comes off the stack first, then the key and the table.
+            SetTable => match &children[..] {
+                [value, key, table, ..] => format!("{}[{}] = {}", table, key, value),
+                _ => return Err("SetTable without a table, key and value".into()),
+            },
+            //SetList,
+            //SetMap,
+            //Add,
+            AddInt => format!(
+                "{} + {}",
+                children.first().ok_or("AddInt without an operand")?,
+                instruction.s()
+            ),
+            //Subtract,
+            //Multiply,
+            //Divide,
+            //Power,
+            //Concat,
+            Minus => {
+                let operand = children.first().ok_or("Minus without an operand")?;
+                match node.children[0].instruction.op() {
+                    // Negating a literal folds into it, the way the compiler emits `PushNegativeNumber` for `-1.5`.
+                    PushInt | PushNumber | PushNegativeNumber => match operand.strip_prefix('-') {
+                        Some(positive) => positive.to_string(),
+                        None => format!("-{}", operand),
+                    },
+                    // `--` starts a comment, and anything with an operator in it needs grouping.
+                    _ if operand.starts_with('-') || operand.contains(' ') => format!("-({})", operand),
+                    _ => format!("-{}", operand),
+                }
+            }
+            //Not,
+            op if op >= JumpNotEqual && op <= JumpIfFalse => {
+                let (condition, body) = if op <= JumpGreaterThanEqual {
+                    let op = match op {
+                        JumpNotEqual => "==",
+                        JumpEqual => "~=",
+                        JumpLessThan => ">=",
+                        JumpLessThanEqual => ">",
+                        JumpGreaterThan => "<=",
+                        JumpGreaterThanEqual => "<",
+                        _ => unreachable!(),
+                    };
+                    let (params, body) = children
+                        .split_at_checked(2)
+                        .ok_or_else(|| format!("{:?} without two operands", instruction.op()))?;
+                    (format!("{} {} {}", params[1], op, params[0]), body)
+                } else {
+                    let op = if op == JumpIfTrue { "not " } else { "" };
+                    let (params, body) = children
+                        .split_at_checked(1)
+                        .ok_or_else(|| format!("{:?} without an operand", instruction.op()))?;
+                    (format!("{} {}", op, params[0]), body)
+                };
+                let body: Vec<&str> = body.iter().flat_map(|line| line.split('\n')).collect();
+
+                if instruction.s() < 0 {
+                    format!("repeat\n  {}\nuntil ({})", body.join("\n  "), condition)
+                } else if looping {
+                    format!("while ({}) do\n  {}\nend", condition, body.join("\n  "))
+                } else {
+                    format!("if ({}) then\n  {}\nend", condition, body.join("\n  "))
+                }
+            }
+
+            //JumpOnTrue,
+            //JumpOnFalse,
+            //Jump,
+            //PushNilJump,
+            //ForPrep,
+            //ForLoop,
+            //LForPrep,
+            //LForLoop,
+            Closure => {
+                let mut args = Vec::new();
+                let function = constants.function(instruction.a())?;
+                for i in 0..function.param_count {
+                    args.push(format!("local_{}", i));
+                }
+                format!("function({})\n{}\nend", args.join(", "), children.join("\n"))
+            }
+            _ => return Err(format!("Unimplemented instruction {:?} ({:?})", instruction, children).into()),
+        })
+    }
+}
+
+pub use parser::OpCode;
+
+fn parse(input: &[u8]) -> Result<parser::Function<'_>, Error> {
+    let (_, (_header, function)) = parser::lua(input).map_err(|err| match err {
+        nom::Err::Error(err) | nom::Err::Failure(err) => Error::Parse {
+            offset: input.len() - err.input.len(),
+            message: format!("{:?}", err.code),
+        },
+        nom::Err::Incomplete(_) => Error::Parse {
+            offset: input.len(),
+            message: "Unexpected end of input".to_string(),
+        },
+    })?;
+    Ok(function)
+}
+
+/// Lists every instruction of a Lua 4.0 chunk as its index, [`OpCode::name`] and operands, without decompiling.
+/// Nested functions follow their parent under a `function <index>` line, indented one more level.
+pub fn disassemble(input: &[u8]) -> Result<Vec<String>, Error> {
+    fn lines(function: &parser::Function, indent: &str, output: &mut Vec<String>) {
+        for (index, instruction) in function.code.iter().enumerate() {
+            let line = format!(
+                "{}{:04} {} {}",
+                indent,
+                index,
+                instruction.op().name(),
+                instruction.operands()
+            );
+            output.push(line.trim_end().to_string());
+        }
+        for (index, function) in function.constants.functions.iter().enumerate() {
+            output.push(format!("{}function {}", indent, index));
+            lines(function, &format!("{}  ", indent), output);
+        }
+    }
+
+    let mut output = Vec::new();
+    lines(&parse(input)?, "", &mut output);
+    Ok(output)
+}
+
+/// Decompiles a Lua 4.0 chunk back into source.
+pub fn decompile(input: &[u8]) -> Result<String, Error> {
+    let function = parse(input)?;
+
+    log::debug!("\n{:#?}", function);
+
+    let nodes = code_generation::to_nodes(function.code.clone(), &function.constants)
+        .map_err(|err| Error::Decompile(err.to_string()))?;
+    log::debug!("AST Tree\n{:#?}", nodes);
+
+    let code: Vec<String> = nodes
+        .into_iter()
+        .map(|node| code_generation::process_node(&node, &mut vec![], &function.constants.clone()))
+        .collect::<Result<_, _>>()
+        .map_err(|err| Error::Decompile(err.to_string()))?;
+
+    Ok(code.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use parser::OpCode;
+
+    const SIZE_OP: u32 = 6;
+    const SIZE_B: u32 = 9;
+    const MAXARG_S: i64 = ((1 << (32 - SIZE_OP)) - 1) >> 1;
+
+    /// Writes Lua 4.0 chunks for a given host layout, mirroring `ldump.c`.
+    struct Chunk {
+        big_endian: bool,
+        sizeof_int: u8,
+        sizeof_size_t: u8,
+        sizeof_instruction: u8,
+        sizeof_number: u8,
+        bytes: Vec<u8>,
+    }
+
+    impl Chunk {
+        fn new(big_endian: bool, sizeof_int: u8, sizeof_size_t: u8, sizeof_instruction: u8, sizeof_number: u8) -> Self {
+            Self {
+                big_endian,
+                sizeof_int,
+                sizeof_size_t,
+                sizeof_instruction,
+                sizeof_number,
+                bytes: Vec::new(),
+            }
+        }
+
+        fn little() -> Self {
+            Self::new(false, 4, 4, 4, 8)
+        }
+
+        fn raw(&mut self, value: u64, size: u8) {
+            let bytes = value.to_le_bytes();
+            let bytes = &bytes[..size as usize];
+            if self.big_endian {
+                self.bytes.extend(bytes.iter().rev());
+            } else {
+                self.bytes.extend(bytes);
+            }
+        }
+
+        fn int(&mut self, value: i32) {
+            self.raw(value as i64 as u64, self.sizeof_int);
+        }
+
+        fn number(&mut self, value: f64) {
+            match self.sizeof_number {
+                4 => self.raw((value as f32).to_bits() as u64, 4),
+                _ => self.raw(value.to_bits(), 8),
+            }
+        }
+
+        fn string(&mut self, value: &str) {
+            self.raw(value.len() as u64 + 1, self.sizeof_size_t);
+            self.bytes.extend(value.as_bytes());
+            self.bytes.push(0);
+        }
+
+        fn header(&mut self) {
+            self.bytes.push(0x1b);
+            self.bytes.extend(b"Lua");
+            self.bytes.push(0x40);
+            self.bytes.push(!self.big_endian as u8);
+            self.bytes.extend([
+                self.sizeof_int,
+                self.sizeof_size_t,
+                self.sizeof_instruction,
+                32,
+                SIZE_OP as u8,
+                SIZE_B as u8,
+                self.sizeof_number,
+            ]);
+            self.number(3.141_592_653_589_793E8);
+        }
+
+        fn function(&mut self, strings: &[&str], numbers: &[f64], code: &[u64]) {
+            self.string("=test");
+            self.int(0); // line
+            self.int(0); // param_count
+            self.bytes.push(0); // is_vararg
+            self.int(2); // max_stack_size
+            self.int(0); // locals
+            self.int(0); // lines
+            self.int(strings.len() as i32);
+            for string in strings {
+                self.string(string);
+            }
+            self.int(numbers.len() as i32);
+            for number in numbers {
+                self.number(*number);
+            }
+            self.int(0); // functions
+            self.int(code.len() as i32);
+            for instruction in code {
+                self.raw(*instruction, self.sizeof_instruction);
+            }
+        }
+
+        fn build(mut self, strings: &[&str], numbers: &[f64], code: &[u64]) -> Vec<u8> {
+            self.header();
+            self.function(strings, numbers, code);
+            self.bytes
+        }
+    }
+
+    fn u(op: OpCode, u: u64) -> u64 {
+        op as u64 | u << SIZE_OP
+    }
+
+    fn s(op: OpCode, s: i64) -> u64 {
+        u(op, (s + MAXARG_S) as u64)
+    }
+
+    fn ab(op: OpCode, a: u64, b: u64) -> u64 {
+        op as u64 | b << SIZE_OP | a << (SIZE_OP + SIZE_B)
+    }
+
+    fn assignments(chunk: Chunk) -> Vec<u8> {
+        chunk.build(
+            &["x", "y", "z", "w", "s", "hello"],
+            &[3.5, 1.25],
+            &[
+                s(OpCode::PushInt, 1),
+                u(OpCode::SetGlobal, 0),
+                u(OpCode::GetGlobal, 0),
+                s(OpCode::AddInt, -2),
+                u(OpCode::SetGlobal, 1),
+                u(OpCode::PushNumber, 0),
+                u(OpCode::SetGlobal, 2),
+                u(OpCode::PushNegativeNumber, 1),
+                u(OpCode::SetGlobal, 3),
+                u(OpCode::PushString, 5),
+                u(OpCode::SetGlobal, 4),
+                u(OpCode::End, 0),
+            ],
+        )
+    }
+
+    const ASSIGNMENTS: &str = "x = 1\ny = x + -2\nz = 3.5\nw = -1.25\ns = \"hello\"\n";
+
+    #[test]
+    fn little_endian() {
+        assert_eq!(ASSIGNMENTS, decompile(&assignments(Chunk::little())).unwrap());
+    }
+
+    #[test]
+    fn big_endian() {
+        let chunk = assignments(Chunk::new(true, 4, 4, 4, 8));
+        assert_eq!(ASSIGNMENTS, decompile(&chunk).unwrap());
+    }
+
+    #[test]
+    fn wide_sizes() {
+        for big_endian in [false, true] {
+            let chunk = assignments(Chunk::new(big_endian, 8, 8, 8, 8));
+            assert_eq!(ASSIGNMENTS, decompile(&chunk).unwrap());
+        }
+    }
+
+    #[test]
+    fn narrow_number() {
+        for big_endian in [false, true] {
+            let chunk = assignments(Chunk::new(big_endian, 4, 4, 4, 4));
+            assert_eq!(ASSIGNMENTS, decompile(&chunk).unwrap());
+        }
+    }
+
+    #[test]
+    fn mismatched_endianess() {
+        let mut chunk = assignments(Chunk::new(true, 4, 4, 4, 8));
+        chunk[5] = 1;
+        assert!(decompile(&chunk).is_err());
+    }
+
+    #[test]
+    fn while_loop() {
+        let chunk = Chunk::little().build(
+            &["i"],
+            &[],
+            &[
+                s(OpCode::PushInt, 0),
+                u(OpCode::SetGlobal, 0),
+                u(OpCode::GetGlobal, 0),
+                s(OpCode::PushInt, 10),
+                s(OpCode::JumpGreaterThanEqual, 4),
+                u(OpCode::GetGlobal, 0),
+                s(OpCode::AddInt, 1),
+                u(OpCode::SetGlobal, 0),
+                s(OpCode::Jump, -7),
+                u(OpCode::End, 0),
+            ],
+        );
+        assert_eq!(
+            "i = 0\nwhile (i < 10) do\n  i = i + 1\nend\n",
+            decompile(&chunk).unwrap()
+        );
+    }
+
+    #[test]
+    fn repeat_loop() {
+        let chunk = Chunk::little().build(
+            &["i"],
+            &[],
+            &[
+                s(OpCode::PushInt, 0),
+                u(OpCode::SetGlobal, 0),
+                u(OpCode::GetGlobal, 0),
+                s(OpCode::AddInt, 1),
+                u(OpCode::SetGlobal, 0),
+                u(OpCode::GetGlobal, 0),
+                s(OpCode::PushInt, 10),
+                s(OpCode::JumpLessThan, -6),
+                u(OpCode::End, 0),
+            ],
+        );
+        assert_eq!(
+            "i = 0\nrepeat\n  i = i + 1\nuntil (i >= 10)\n",
+            decompile(&chunk).unwrap()
+        );
+    }
+
+    #[test]
+    fn table_constructor_pop_counts() {
+        let chunk = Chunk::little().build(
+            &["t", "k"],
+            &[],
+            &[
+                u(OpCode::CreateTable, 3),
+                s(OpCode::PushInt, 1),
+                s(OpCode::PushInt, 2),
+                s(OpCode::PushInt, 3),
+                ab(OpCode::SetList, 0, 3),
+                u(OpCode::PushString, 1),
+                s(OpCode::PushInt, 4),
+                u(OpCode::SetMap, 1),
+                u(OpCode::SetGlobal, 0),
+                u(OpCode::End, 0),
+            ],
+        );
+        let (_, (_, function)) = parser::lua(&chunk).unwrap();
+        assert_eq!(OpCode::SetList, function.code[4].op());
+        assert_eq!(3, function.code[4].pop_count());
+        assert_eq!(OpCode::SetMap, function.code[7].op());
+        assert_eq!(2, function.code[7].pop_count());
+    }
+
+    #[test]
+    fn method_call() {
+        let chunk = Chunk::little().build(
+            &["obj", "foo", "f"],
+            &[],
+            &[
+                u(OpCode::GetGlobal, 0),
+                u(OpCode::PushSelf, 1),
+                s(OpCode::PushInt, 1),
+                s(OpCode::PushInt, 2),
+                ab(OpCode::Call, 0, 0),
+                u(OpCode::GetGlobal, 2),
+                s(OpCode::PushInt, 3),
+                ab(OpCode::Call, 0, 0),
+                u(OpCode::End, 0),
+            ],
+        );
+        assert_eq!("obj:foo(1, 2)\nf(3)\n", decompile(&chunk).unwrap());
+    }
+
+    #[test]
+    fn table_indexing() {
+        let chunk = Chunk::little().build(
+            &["x", "t", "name", "end", "two words"],
+            &[],
+            &[
+                u(OpCode::GetGlobal, 1),
+                u(OpCode::GetDotted, 2),
+                u(OpCode::SetGlobal, 0),
+                u(OpCode::GetGlobal, 1),
+                u(OpCode::GetDotted, 3),
+                u(OpCode::SetGlobal, 0),
+                u(OpCode::GetGlobal, 1),
+                u(OpCode::GetDotted, 4),
+                u(OpCode::SetGlobal, 0),
+                u(OpCode::GetGlobal, 1),
+                s(OpCode::PushInt, 1),
+                u(OpCode::GetTable, 0),
+                u(OpCode::SetGlobal, 0),
+                u(OpCode::GetGlobal, 1),
+                u(OpCode::GetIndexed, 0),
+                u(OpCode::SetGlobal, 0),
+                u(OpCode::End, 0),
+            ],
+        );
+        assert_eq!(
+            "x = t.name\nx = t[\"end\"]\nx = t[\"two words\"]\nx = t[1]\nx = t[local_0]\n",
+            decompile(&chunk).unwrap()
+        );
+    }
+
+    #[test]
+    fn table_indexed_by_expression() {
+        let chunk = Chunk::little().build(
+            &["x", "t", "items", "k", "index"],
+            &[],
+            &[
+                u(OpCode::GetGlobal, 1),
+                u(OpCode::GetDotted, 2),
+                u(OpCode::GetGlobal, 3),
+                u(OpCode::GetDotted, 4),
+                u(OpCode::GetTable, 0),
+                u(OpCode::SetGlobal, 0),
+                u(OpCode::GetGlobal, 1),
+                u(OpCode::GetGlobal, 3),
+                s(OpCode::AddInt, 1),
+                u(OpCode::GetTable, 0),
+                u(OpCode::GetGlobal, 3),
+                u(OpCode::GetTable, 0),
+                u(OpCode::SetGlobal, 0),
+                u(OpCode::End, 0),
+            ],
+        );
+        assert_eq!(
+            "x = t.items[k.index]
+x = t[k + 1][k]
+",
+            decompile(&chunk).unwrap()
+        );
+    }
+
+    #[test]
+    fn disassembly() {
+        let chunk = Chunk::little().build(
+            &["x", "f"],
+            &[],
+            &[
+                s(OpCode::PushInt, -3),
+                u(OpCode::SetGlobal, 0),
+                u(OpCode::GetGlobal, 1),
+                ab(OpCode::Call, 0, 0),
+                s(OpCode::Jump, 1),
+                u(OpCode::End, 0),
+            ],
+        );
+        assert_eq!(
+            vec![
+                "0000 PUSHINT -3",
+                "0001 SETGLOBAL 0",
+                "0002 GETGLOBAL 1",
+                "0003 CALL 0, 0",
+                "0004 JMP 1",
+                "0005 END",
+            ],
+            disassemble(&chunk).unwrap()
+        );
+        assert_eq!("PUSHNEGNUM", OpCode::PushNegativeNumber.name());
+        assert!(matches!(disassemble(&chunk[..10]), Err(Error::Parse { .. })));
+    }
+
+    #[test]
+    fn constant_out_of_range() {
+        let chunk = Chunk::little().build(
+            &["x"],
+            &[],
+            &[u(OpCode::PushString, 3), u(OpCode::SetGlobal, 0), u(OpCode::End, 0)],
+        );
+        assert!(matches!(
+            decompile(&chunk),
+            Err(Error::Decompile(message)) if message == "String constant 3 is out of range, the function has 1"
+        ));
+
+        let chunk = Chunk::little().build(
+            &["x"],
+            &[1.5],
+            &[u(OpCode::PushNumber, 1), u(OpCode::SetGlobal, 0), u(OpCode::End, 0)],
+        );
+        assert!(matches!(decompile(&chunk), Err(Error::Decompile(_))));
+    }
+
+    #[test]
+    fn other_version() {
+        // Lua 5.0 moved booleans and nil into the constants, so only 4.0's layout is read.
+        let mut chunk = assignments(Chunk::little());
+        chunk[4] = 0x50;
+        assert!(matches!(decompile(&chunk), Err(Error::Parse { offset: 4, .. })));
+    }
+
+    #[test]
+    fn empty_code() {
+        let chunk = Chunk::little().build(&[], &[], &[]);
+        assert!(parser::lua(&chunk).is_err());
+    }
+
+    #[test]
+    fn missing_end() {
+        let chunk = Chunk::little().build(&["x"], &[], &[s(OpCode::PushInt, 1), u(OpCode::SetGlobal, 0)]);
+        assert!(parser::lua(&chunk).is_err());
+    }
+
+    #[test]
+    fn invalid_opcode() {
+        let chunk = Chunk::little().build(&[], &[], &[0x3f, u(OpCode::End, 0)]);
+        assert!(parser::lua(&chunk).is_err());
+    }
+
+    #[test]
+    fn unsupported_sizes() {
+        let mut chunk = assignments(Chunk::little());
+        chunk[6] = 3;
+        assert!(decompile(&chunk).is_err());
+    }
+
+    #[test]
+    fn malformed_code() {
+        let overrun = Chunk::little().build(&["x"], &[], &[s(OpCode::Jump, 3), u(OpCode::End, 0)]);
+        let err = decompile(&overrun).unwrap_err();
+        assert!(matches!(err, Error::Decompile(_)), "{:?}", err);
+        assert!(err.to_string().contains("jumps 2 past the end"), "{}", err);
+
+        let underflow = Chunk::little().build(&["x"], &[], &[u(OpCode::SetGlobal, 0), u(OpCode::End, 0)]);
+        let err = decompile(&underflow).unwrap_err();
+        assert!(err.to_string().contains("pops more values than were pushed"), "{}", err);
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        let mut chunk = assignments(Chunk::little());
+        let end = chunk.len();
+        chunk.push(0);
+        assert!(matches!(decompile(&chunk), Err(Error::Parse { offset, .. }) if offset == end));
+    }
+
+    #[test]
+    fn missing_operands() {
+        // `PushNil` pushes as many values as asked in one node, so these pop enough values but too few children.
+        for code in [
+            [u(OpCode::PushNil, 2), s(OpCode::JumpNotEqual, 0), u(OpCode::End, 0)],
+            [u(OpCode::PushNil, 3), ab(OpCode::SetTable, 0, 3), u(OpCode::End, 0)],
+        ] {
+            let chunk = Chunk::little().build(&[], &[], &code);
+            let err = decompile(&chunk).unwrap_err();
+            assert!(matches!(err, Error::Decompile(_)), "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn negation() {
+        let chunk = Chunk::little().build(
+            &["x", "y"],
+            &[1.25],
+            &[
+                s(OpCode::PushInt, 5),
+                u(OpCode::Minus, 0),
+                u(OpCode::SetGlobal, 0),
+                u(OpCode::GetGlobal, 1),
+                u(OpCode::Minus, 0),
+                u(OpCode::SetGlobal, 0),
+                u(OpCode::GetGlobal, 1),
+                u(OpCode::Minus, 0),
+                u(OpCode::Minus, 0),
+                u(OpCode::SetGlobal, 0),
+                u(OpCode::PushNegativeNumber, 0),
+                u(OpCode::Minus, 0),
+                u(OpCode::SetGlobal, 0),
+                u(OpCode::GetGlobal, 1),
+                s(OpCode::AddInt, 1),
+                u(OpCode::Minus, 0),
+                u(OpCode::SetGlobal, 0),
+                u(OpCode::End, 0),
+            ],
+        );
+        assert_eq!(
+            "x = -5\nx = -y\nx = -(-y)\nx = 1.25\nx = -(y + 1)\n",
+            decompile(&chunk).unwrap()
+        );
+    }
+}
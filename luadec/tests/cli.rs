@@ -0,0 +1,68 @@
+use std::{fs, path::Path, process::Command};
+
+fn fixture(name: &str) -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
+
+#[test]
+fn writes_output_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("assignments.lua");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_luadec"))
+        .arg(fixture("assignments.luac"))
+        .arg("-o")
+        .arg(&output)
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+    assert_eq!(
+        fs::read_to_string(fixture("assignments.lua")).unwrap(),
+        fs::read_to_string(output).unwrap()
+    );
+}
+
+#[test]
+fn writes_stdout() {
+    let output = Command::new(env!("CARGO_BIN_EXE_luadec"))
+        .arg(fixture("assignments.luac"))
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(fixture("assignments.lua")).unwrap(),
+        String::from_utf8(output.stdout).unwrap()
+    );
+}
+
+#[test]
+fn unimplemented_instruction_fails() {
+    let output = Command::new(env!("CARGO_BIN_EXE_luadec"))
+        .arg(fixture("unimplemented.luac"))
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unimplemented instruction"));
+}
+
+#[test]
+fn disassembles_what_cannot_be_decompiled() {
+    let output = Command::new(env!("CARGO_BIN_EXE_luadec"))
+        .arg(fixture("unimplemented.luac"))
+        .arg("--disassemble")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        "0000 GETGLOBAL 0\n0001 NOT\n0002 SETGLOBAL 1\n0003 END\n",
+        String::from_utf8(output.stdout).unwrap()
+    );
+}
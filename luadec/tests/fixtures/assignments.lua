@@ -0,0 +1,3 @@
+x = 1
+y = x + 2
+s = "hello"
@@ -1,13 +1,19 @@
-use std::fmt;
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt,
+    io::{Seek, SeekFrom, Write},
+};
 
-use binrw::binrw;
+use binrw::{binrw, BinResult, BinWrite};
 
-use common::Path;
+use common::{normalize_path, path_eq, PackVersion, Path};
 
 #[binrw]
+#[brw(little)]
 pub struct Mesh {
-    #[brw(pad_after = 2)]
     pub path: Path,
+    /// Kept as read rather than treated as padding, so meshes are written back byte for byte.
+    pub unknown0: u16,
     #[br(temp)]
     #[bw(calc = data.len() as u32)]
     size: u32,
@@ -40,3 +46,162 @@ impl fmt::Debug for MeshPackFile {
         Vec::fmt(&self.meshes, f)
     }
 }
+
+impl MeshPackFile {
+    /// The first mesh at `path`, compared with [`path_eq`].
+    pub fn mesh(&self, path: &str) -> Option<&Mesh> {
+        self.meshes.iter().find(|mesh| path_eq(&mesh.path, path))
+    }
+
+    /// The paths of meshes that share their path with an earlier mesh, compared with [`path_eq`]. Only the first of
+    /// them can be found with [`MeshPackFile::mesh`], and they'd overwrite each other on disk.
+    pub fn duplicate_paths(&self) -> Vec<&str> {
+        let mut seen = HashSet::with_capacity(self.meshes.len());
+        let duplicates: BTreeSet<&str> = self
+            .meshes
+            .iter()
+            .map(|mesh| &*mesh.path)
+            .filter(|path| !seen.insert(normalize_path(path)))
+            .collect();
+        duplicates.into_iter().collect()
+    }
+}
+
+/// Mesh pack files have no version, only their `MPAK` magic.
+impl PackVersion for MeshPackFile {
+    const VERSION_MAGIC: Option<[u8; 2]> = None;
+
+    fn version_number(&self) -> Option<u16> {
+        None
+    }
+}
+
+/// Writes a [`MeshPackFile`] one mesh at a time, so the meshes never all have to be in memory at once.
+///
+/// The count goes in front of the meshes, so it's written as zero and filled in by [`MeshPackFileWriter::finish`].
+pub struct MeshPackFileWriter<W: Write + Seek> {
+    writer: W,
+    count_position: u64,
+    count: u16,
+}
+
+impl<W: Write + Seek> MeshPackFileWriter<W> {
+    /// Writes the magic and a placeholder count at the current position of `writer`.
+    pub fn new(mut writer: W) -> BinResult<Self> {
+        writer.write_all(b"MPAK")?;
+        let count_position = writer.stream_position()?;
+        writer.write_all(&0u16.to_le_bytes())?;
+        Ok(MeshPackFileWriter {
+            writer,
+            count_position,
+            count: 0,
+        })
+    }
+
+    pub fn write_mesh(&mut self, mesh: &Mesh) -> BinResult<()> {
+        if self.count == u16::MAX {
+            return Err(binrw::Error::AssertFail {
+                pos: self.writer.stream_position()?,
+                message: format!("While writing MeshPackFile: More than {} meshes.", u16::MAX),
+            });
+        }
+        mesh.write_le(&mut self.writer)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Fills in the count, leaving `writer` after the last mesh, and returns it.
+    pub fn finish(mut self) -> BinResult<W> {
+        let end = self.writer.stream_position()?;
+        self.writer.seek(SeekFrom::Start(self.count_position))?;
+        self.writer.write_all(&self.count.to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use binrw::BinRead;
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut data = b"MPAK".to_vec();
+        data.extend_from_slice(&2u16.to_le_bytes());
+        for (path, unknown0, mesh) in [("meshes/a", 0u16, &b"abc"[..]), ("meshes/b", 0xBEEF, b"")] {
+            data.extend_from_slice(&(path.len() as u16 + 1).to_le_bytes());
+            data.extend_from_slice(path.as_bytes());
+            data.push(0);
+            data.extend_from_slice(&unknown0.to_le_bytes());
+            data.extend_from_slice(&(mesh.len() as u32).to_le_bytes());
+            data.extend_from_slice(mesh);
+        }
+
+        let meshes = MeshPackFile::read(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(2, meshes.meshes.len());
+        assert_eq!("meshes/b", &*meshes.meshes[1].path);
+        assert_eq!(0xBEEF, meshes.meshes[1].unknown0);
+        assert_eq!(b"abc", &meshes.meshes[0].data[..]);
+        assert_eq!(0xBEEF, meshes.mesh("/meshes\\b").unwrap().unknown0);
+        assert!(meshes.mesh("meshes/c").is_none());
+        assert!(meshes.duplicate_paths().is_empty());
+
+        let mut output = Cursor::new(Vec::new());
+        meshes.write_le(&mut output).unwrap();
+        assert_eq!(data, output.into_inner());
+    }
+
+    #[test]
+    fn duplicate_paths() {
+        let mesh = |path: &str| Mesh {
+            path: Path::new(path),
+            unknown0: 0,
+            data: vec![],
+        };
+        let meshes = MeshPackFile {
+            meshes: vec![mesh("meshes/a"), mesh("meshes/b"), mesh("/meshes\\a"), mesh("meshes/a")],
+        };
+        assert_eq!(vec!["/meshes\\a", "meshes/a"], meshes.duplicate_paths());
+    }
+
+    #[test]
+    fn pack_version() {
+        let meshes = MeshPackFile::read(&mut Cursor::new(b"MPAK\x00\x00")).unwrap();
+        assert_eq!(None, meshes.version_number());
+        assert_eq!(None, MeshPackFile::VERSION_MAGIC);
+    }
+
+    #[test]
+    fn streamed_matches_eager() {
+        let mesh = |path: &str, unknown0, data: &[u8]| Mesh {
+            path: Path::new(path),
+            unknown0,
+            data: data.to_vec(),
+        };
+        let meshes = MeshPackFile {
+            meshes: vec![
+                mesh("meshes/a", 0, b"abc"),
+                mesh("meshes/b", 0xBEEF, b""),
+                mesh("c", 1, &[7; 300]),
+            ],
+        };
+        let mut eager = Cursor::new(Vec::new());
+        meshes.write_le(&mut eager).unwrap();
+
+        // After something else, as a ppf would have the textures first.
+        let mut streamed = Cursor::new(b"PPAK".to_vec());
+        streamed.set_position(4);
+        let mut writer = MeshPackFileWriter::new(&mut streamed).unwrap();
+        for mesh in &meshes.meshes {
+            writer.write_mesh(mesh).unwrap();
+        }
+        writer.finish().unwrap();
+
+        assert_eq!(streamed.position() as usize, streamed.get_ref().len());
+        assert_eq!(eager.into_inner(), streamed.into_inner()[4..]);
+    }
+}
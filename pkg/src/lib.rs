@@ -1,6 +1,48 @@
-use std::collections::HashMap;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
-type BoxError = Box<dyn std::error::Error + Send + Sync>;
+use common::{normalize_path, path_eq};
+#[cfg(feature = "tokio")]
+use futures_util::future::try_join_all;
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Unable to parse {0}.")]
+    Parse(&'static str),
+    #[error("Expected {expected} {what}, found {found}.")]
+    CountMismatch {
+        what: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    #[error("Invalid {0}.")]
+    Invalid(&'static str),
+    #[error(
+        "The {what} offset {offset:#x} comes before the previous region or past the end of the data it points into ({length:#x})."
+    )]
+    OffsetOutOfBounds {
+        what: &'static str,
+        offset: usize,
+        length: usize,
+    },
+    #[error("Data for {path} at {offset:#x} ({size} bytes) lies outside the file data region.")]
+    DataOutOfBounds { path: String, offset: usize, size: usize },
+    #[error("Unable to store {0:?} in a pkg.")]
+    UnsupportedPath(String),
+    #[error("Too many {0} to store in a pkg.")]
+    TooLarge(&'static str),
+    #[error("More than one file would be written to {0:?}.")]
+    DuplicatePaths(Vec<String>),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
 
 mod parser {
     use nom::{
@@ -35,6 +77,7 @@ mod parser {
         pub file_data_size: usize,
     }
 
+    #[allow(dead_code)]
     #[derive(Debug, Clone)]
     pub struct DirectoryRecord {
         pub characters: Vec<char>,
@@ -142,18 +185,217 @@ mod parser {
 pub struct ZpkgFile {
     pub path: String,
     pub data: Vec<u8>,
+    /// Whether [`Zpkg::inflate`] inflated `data`, so [`Zpkg::write`] has to deflate it again.
+    compressed: bool,
+    /// The `(offset, size)` of the record this file was read from, if it reached outside the file data region and
+    /// `data` was clamped, see [`Zpkg::verify`].
+    out_of_bounds: Option<(usize, usize)>,
+}
+
+impl ZpkgFile {
+    pub fn new(path: impl Into<String>, data: Vec<u8>) -> ZpkgFile {
+        ZpkgFile {
+            path: path.into(),
+            data,
+            compressed: false,
+            out_of_bounds: None,
+        }
+    }
+
+    /// Whether `data` was stored as a zlib stream and inflated by [`Zpkg::inflate`].
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// The bytes [`Zpkg::write`] stores, deflating `data` again if it was inflated.
+    fn stored_data(&self) -> Result<Cow<'_, [u8]>, Error> {
+        #[cfg(feature = "flate2")]
+        if self.compressed {
+            use flate2::{write::ZlibEncoder, Compression};
+
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&self.data)?;
+            return Ok(Cow::Owned(encoder.finish()?));
+        }
+        Ok(Cow::Borrowed(&self.data))
+    }
+
+    /// The path without its leading `/`, ready to be joined onto an output directory.
+    pub fn relative_path(&self) -> &str {
+        self.path.strip_prefix('/').unwrap_or(&self.path)
+    }
+
+    /// The last component of the path.
+    pub fn file_name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+
+    /// What follows the last `.` of the file name, if it has one.
+    pub fn extension(&self) -> Option<&str> {
+        self.file_name().rsplit_once('.').map(|(_, ext)| ext)
+    }
+}
+
+/// A directory of a [`Zpkg`], see [`Zpkg::tree`].
+#[derive(Debug, Default)]
+pub struct Directory<'a> {
+    pub directories: BTreeMap<&'a str, Directory<'a>>,
+    pub files: Vec<&'a ZpkgFile>,
+}
+
+impl<'a> Directory<'a> {
+    fn entry(&mut self, path: &'a str) -> &mut Directory<'a> {
+        path.split('/')
+            .filter(|name| !name.is_empty())
+            .fold(self, |directory, name| directory.directories.entry(name).or_default())
+    }
+
+    /// Collects the deepest directories below `path`; creating those creates the whole tree.
+    fn leaves(&self, path: &Path, leaves: &mut Vec<PathBuf>) {
+        for (name, directory) in &self.directories {
+            let path = path.join(name);
+            if directory.directories.is_empty() {
+                leaves.push(path);
+            } else {
+                directory.leaves(&path, leaves);
+            }
+        }
+    }
+}
+
+/// How far an extraction has got, reported after each file is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub files: usize,
+    pub total_files: usize,
+    pub bytes: usize,
+    pub total_bytes: usize,
 }
 
 #[derive(Debug)]
 pub struct Zpkg {
     pub version: u32,
     pub files: Vec<ZpkgFile>,
+    /// Every directory named by the directory records, including those without files.
+    directories: Vec<String>,
+}
+
+/// Splits a file path into its directory (with a leading `/`, or empty for the root), name and extension.
+fn split_path(path: &str) -> Result<(String, &str, &str), Error> {
+    let unsupported = || Error::UnsupportedPath(path.to_string());
+    if !path.is_ascii() || path.contains(['\0', '\x02']) {
+        return Err(unsupported());
+    }
+
+    let relative = path.strip_prefix('/').unwrap_or(path);
+    let (directory, file_name) = match relative.rsplit_once('/') {
+        Some((directory, file_name)) => (format!("/{}", directory), file_name),
+        None => (String::new(), relative),
+    };
+    let (name, ext) = file_name.rsplit_once('.').ok_or_else(unsupported)?;
+    if directory == "/" || name.is_empty() {
+        return Err(unsupported());
+    }
+    Ok((directory, name, ext))
+}
+
+/// The bytes of the name or file type directory from `offset` on, where a file record says its entry starts.
+fn directory_entry<'a>(what: &'static str, directory: &'a [u8], offset: usize) -> Result<&'a [u8], Error> {
+    directory.get(offset..).ok_or(Error::OffsetOutOfBounds {
+        what,
+        offset,
+        length: directory.len(),
+    })
+}
+
+/// Inflates `data` if it holds exactly one zlib stream.
+#[cfg(feature = "flate2")]
+fn inflate(data: &[u8]) -> Option<Vec<u8>> {
+    use std::io::Read;
+
+    // Deflate with at most a 32 KiB window, no preset dictionary and a valid header checksum.
+    let is_zlib = data.len() >= 6
+        && data[0] & 0x0F == 8
+        && data[0] >> 4 <= 7
+        && data[1] & 0x20 == 0
+        && u16::from_be_bytes([data[0], data[1]]).is_multiple_of(31);
+    if !is_zlib {
+        return None;
+    }
+
+    let mut decoder = flate2::bufread::ZlibDecoder::new(data);
+    let mut inflated = Vec::new();
+    match decoder.read_to_end(&mut inflated) {
+        Ok(_) if decoder.total_in() == data.len() as u64 => Some(inflated),
+        _ => None,
+    }
 }
 
 impl Zpkg {
-    pub fn from_slice(input: &[u8]) -> Result<Zpkg, BoxError> {
-        let (input, header) =
-            parser::parse_header(input).map_err::<BoxError, _>(|_err| "Unable to parse pkg header.".into())?;
+    pub fn new(version: u32, files: Vec<ZpkgFile>) -> Zpkg {
+        Zpkg {
+            version,
+            files,
+            directories: Vec::new(),
+        }
+    }
+
+    /// Reads every file below `dir` into a version 1 archive, ready to be written with [`Zpkg::write`].
+    ///
+    /// Paths are relative to `dir` with `/` separators and a leading `/`, as [`Zpkg::from_slice`] gives them, and
+    /// directories without files are kept. Fails with [`Error::UnsupportedPath`] for anything a pkg can't hold.
+    pub fn from_directory<P: AsRef<Path>>(dir: P) -> Result<Zpkg, Error> {
+        fn walk(dir: &Path, path: &str, files: &mut Vec<ZpkgFile>, directories: &mut Vec<String>) -> Result<(), Error> {
+            let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+            entries.sort_by_key(|entry| entry.file_name());
+            for entry in entries {
+                let name = entry.file_name();
+                let path = format!("{}/{}", path, name.to_string_lossy());
+                if !name
+                    .to_str()
+                    .is_some_and(|name| name.is_ascii() && !name.contains(['\0', '\x02']))
+                {
+                    return Err(Error::UnsupportedPath(path));
+                }
+                if entry.file_type()?.is_dir() {
+                    walk(&entry.path(), &path, files, directories)?;
+                    directories.push(path);
+                } else {
+                    split_path(&path)?;
+                    let data = fs::read(entry.path())?;
+                    files.push(ZpkgFile::new(path, data));
+                }
+            }
+            Ok(())
+        }
+
+        let (mut files, mut directories) = (Vec::new(), Vec::new());
+        walk(dir.as_ref(), "", &mut files, &mut directories)?;
+        directories.sort();
+
+        let mut zpkg = Zpkg::new(1, files);
+        zpkg.directories = directories;
+        Ok(zpkg)
+    }
+
+    /// Parses a whole archive held in memory. File data is kept as stored, see [`Zpkg::inflate`].
+    pub fn from_slice(input: &[u8]) -> Result<Zpkg, Error> {
+        let length = input.len();
+        let (input, header) = parser::parse_header(input).map_err(|_err| Error::Parse("pkg header"))?;
+
+        // Each region runs up to the next one's offset, so the offsets must be in order and within the file.
+        let mut previous = length - input.len();
+        for (what, offset) in [
+            ("directory records", header.directory_records_offset),
+            ("name directory", header.name_directory_offset),
+            ("file type directory", header.file_type_directory_offset),
+            ("file data", header.file_data_offset),
+        ] {
+            if offset < previous || offset > length {
+                return Err(Error::OffsetOutOfBounds { what, offset, length });
+            }
+            previous = offset;
+        }
 
         let (file_records, input) = input.split_at(header.directory_records_offset - 512);
         let (directory_records, input) = input.split_at(header.name_directory_offset - header.directory_records_offset);
@@ -161,74 +403,963 @@ impl Zpkg {
         let (file_type_directory, file_data) =
             input.split_at(header.file_data_offset - header.file_type_directory_offset);
 
-        let (remaining, file_records) = parser::parse_file_records(file_records)
-            .map_err::<BoxError, _>(|_err| "Unable to parse file records.".into())?;
-        assert_eq!(0, remaining.len());
-        assert_eq!(header.number_of_files, file_records.len());
+        let (remaining, file_records) =
+            parser::parse_file_records(file_records).map_err(|_err| Error::Parse("file records"))?;
+        if !remaining.is_empty() {
+            return Err(Error::Parse("file records"));
+        }
+        if header.number_of_files != file_records.len() {
+            return Err(Error::CountMismatch {
+                what: "file records",
+                expected: header.number_of_files,
+                found: file_records.len(),
+            });
+        }
 
-        let (remaining, mut directory_records) = parser::parse_directory_records(directory_records)
-            .map_err::<BoxError, _>(|_err| "Unable to parse directory records.".into())?;
-        assert_eq!(0, remaining.len());
-        assert_eq!(header.number_of_directory_records, directory_records.len());
+        let (remaining, mut directory_records) =
+            parser::parse_directory_records(directory_records).map_err(|_err| Error::Parse("directory records"))?;
+        if !remaining.is_empty() {
+            return Err(Error::Parse("directory records"));
+        }
+        if header.number_of_directory_records != directory_records.len() {
+            return Err(Error::CountMismatch {
+                what: "directory records",
+                expected: header.number_of_directory_records,
+                found: directory_records.len(),
+            });
+        }
 
         let mut directory_map: HashMap<usize, String> = HashMap::with_capacity(file_records.len());
+        let mut directories = BTreeSet::new();
         let mut directory_name = vec!['\x02', '/'];
         for index in 0..directory_records.len() {
             let record = directory_records.get(index).unwrap().clone();
 
             for link in [record.link_1, record.link_2] {
                 if link != 0 {
-                    let other = directory_records.get_mut(link).unwrap();
+                    let other = directory_records
+                        .get_mut(link)
+                        .ok_or(Error::Invalid("directory record link"))?;
                     if !directory_name.is_empty() {
                         other.characters.splice(0..0, directory_name.iter().cloned());
                     } else {
-                        other.characters.splice(
-                            0..0,
-                            (&record.characters[..record.characters.len() - 1]).iter().cloned(),
-                        );
+                        other
+                            .characters
+                            .splice(0..0, record.characters[..record.characters.len() - 1].iter().cloned());
                     }
                 }
             }
 
             directory_name.extend(record.characters);
+            let directory: String = directory_name.iter().skip(1).collect();
 
             if record.end_file_index != 0 {
                 for index in record.start_file_index..record.end_file_index {
-                    assert!(!directory_map.contains_key(&index));
-                    directory_map.insert(index, directory_name.iter().skip(1).collect());
+                    if directory_map.insert(index, directory.clone()).is_some() {
+                        return Err(Error::Invalid("directory record file range"));
+                    }
                 }
+                directories.insert(directory.clone());
+            }
 
-                if let Some(parser::DirectoryRecord { characters, .. }) = directory_records.get(index + 1) {
-                    if characters.contains(&'\x02') {
-                        directory_name.clear();
-                    }
+            // The path restarts at the next record holding a `\x02`, so the one built up to here is a directory even
+            // without any files. A lone empty record stands for the root.
+            let next = directory_records.get(index + 1);
+            if next.is_none_or(|next| next.characters.contains(&'\x02')) {
+                if !directory.contains('\0') {
+                    directories.insert(directory);
                 }
+                directory_name.clear();
             }
         }
 
         let mut files = Vec::with_capacity(file_records.len());
         for (index, file_record) in file_records.into_iter().enumerate() {
-            let data_offset = file_record.file_data_offset - header.file_data_offset;
-            let file_name = parser::parse_zstr(&name_directory[file_record.file_name_offset..])
-                .map_err::<BoxError, _>(|_err| "Unable to parse file name.".into())?
-                .1;
-            let file_ext = parser::parse_zstr(&file_type_directory[file_record.file_type_offset..])
-                .map_err::<BoxError, _>(|_err| "Unable to parse file extension.".into())?
-                .1;
+            let file_name = parser::parse_zstr(directory_entry(
+                "file name",
+                name_directory,
+                file_record.file_name_offset,
+            )?)
+            .map_err(|_err| Error::Parse("file name"))?
+            .1;
+            let file_ext = parser::parse_zstr(directory_entry(
+                "file extension",
+                file_type_directory,
+                file_record.file_type_offset,
+            )?)
+            .map_err(|_err| Error::Parse("file extension"))?
+            .1;
             let path = format!(
                 "{}/{}.{}",
                 directory_map.get(&index).unwrap_or(&"".to_string()),
                 file_name,
                 file_ext
             );
-            let data = (&file_data[data_offset..data_offset + file_record.file_data_size]).to_vec();
+            // Out of bounds data is clamped here and reported by `verify`, so a damaged archive can still be salvaged.
+            let start = file_record.file_data_offset.saturating_sub(header.file_data_offset);
+            let end = start.saturating_add(file_record.file_data_size);
+            let data = file_data[start.min(file_data.len())..end.min(file_data.len())].to_vec();
 
-            files.push(ZpkgFile { path, data });
+            let (offset, size) = (file_record.file_data_offset, file_record.file_data_size);
+            let in_bounds = offset >= header.file_data_offset && end <= file_data.len();
+            files.push(ZpkgFile {
+                out_of_bounds: (!in_bounds).then_some((offset, size)),
+                ..ZpkgFile::new(path, data)
+            });
         }
 
         Ok(Zpkg {
             version: header.version,
             files,
+            directories: directories.into_iter().collect(),
         })
     }
+
+    /// Inflates every file that holds exactly one zlib stream, returning how many did. [`Zpkg::write`] deflates them
+    /// again.
+    ///
+    /// No known archive marks compressed files in its header or records, and none of the game's own archives seen so
+    /// far use compression, so each file is checked for a zlib header whose stream inflates cleanly and ends with the
+    /// file. A file that only happens to look like that is changed too, which is why this isn't done when reading.
+    #[cfg(feature = "flate2")]
+    pub fn inflate(&mut self) -> usize {
+        let mut inflated = 0;
+        for file in self.files.iter_mut().filter(|file| !file.compressed) {
+            if let Some(data) = inflate(&file.data) {
+                file.data = data;
+                file.compressed = true;
+                inflated += 1;
+            }
+        }
+        inflated
+    }
+
+    /// The first file at `path`, compared with [`path_eq`].
+    pub fn file(&self, path: &str) -> Option<&ZpkgFile> {
+        self.files.iter().find(|file| path_eq(&file.path, path))
+    }
+
+    /// Every file, for editing paths or data before [`Zpkg::write`], which lays out the directories from the paths as
+    /// they are then.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut ZpkgFile> {
+        self.files.iter_mut()
+    }
+
+    /// Moves the first file at `from`, compared with [`path_eq`], to `to`, returning whether there was one. `to` is
+    /// stored normalized with a leading `/` like every other path, and only checked when the archive is written. Directories
+    /// left without files are kept, as [`Zpkg::from_directory`] keeps empty ones.
+    pub fn rename(&mut self, from: &str, to: &str) -> bool {
+        let file = match self.files.iter_mut().find(|file| path_eq(&file.path, from)) {
+            Some(file) => file,
+            None => return false,
+        };
+        file.path = format!("/{}", normalize_path(to));
+        true
+    }
+
+    /// The files with extension `ext`, given with or without its leading `.`, in archive order.
+    pub fn files_by_extension<'a>(&'a self, ext: &'a str) -> impl Iterator<Item = &'a ZpkgFile> + 'a {
+        let ext = ext.strip_prefix('.').unwrap_or(ext);
+        self.files.iter().filter(move |file| file.extension() == Some(ext))
+    }
+
+    /// Checks that every file read from an archive had its data within the file data region, returning an error for
+    /// each one that didn't. Files added since are always fine.
+    pub fn verify(&self) -> Result<(), Vec<Error>> {
+        let errors: Vec<Error> = self
+            .files
+            .iter()
+            .filter_map(|file| {
+                let (offset, size) = file.out_of_bounds?;
+                Some(Error::DataOutOfBounds {
+                    path: file.path.clone(),
+                    offset,
+                    size,
+                })
+            })
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Serializes the archive, keeping its `version`.
+    ///
+    /// Files are grouped by directory, so they come back in that order when the archive is read again. Each
+    /// directory is written as a chain of directory records, one per character, that [`Zpkg::from_slice`]
+    /// walks back into a path; the sibling links found in the game's own archives aren't produced.
+    ///
+    /// No layout differences between `version`s are known, every archive is read and written the same way.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let mut files = self
+            .files
+            .iter()
+            .map(|file| {
+                let (directory, name, ext) = split_path(&file.path)?;
+                Ok((directory, name, ext, file.stored_data()?))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        if files.is_empty() {
+            return Err(Error::Invalid("pkg without files"));
+        }
+
+        let directories: BTreeSet<&str> = files
+            .iter()
+            .map(|(directory, ..)| directory.as_str())
+            .chain(self.directories.iter().map(String::as_str))
+            .filter(|directory| !directory.is_empty())
+            .collect();
+
+        // (character, start file index, end file index)
+        let mut directory_records: Vec<(u8, usize, usize)> = Vec::new();
+        let mut current: Option<&str> = None;
+        for directory in directories {
+            // A subdirectory continues the previous chain, anything else restarts at the root with `\x02`. Empty
+            // directories get a chain of their own, so they are kept too.
+            let characters = match current {
+                None => &directory[1..],
+                Some(current) if directory.starts_with(current) && directory[current.len()..].starts_with('/') => {
+                    &directory[current.len()..]
+                }
+                Some(_) => {
+                    directory_records.extend([(b'\x02', 0, 0), (b'/', 0, 0)]);
+                    &directory[1..]
+                }
+            };
+            directory_records.extend(characters.bytes().map(|character| (character, 0, 0)));
+
+            let start = files.partition_point(|(other, ..)| other.as_str() < directory);
+            let end = files.partition_point(|(other, ..)| other.as_str() <= directory);
+            if start != end {
+                let last = directory_records.last_mut().unwrap();
+                last.1 = start;
+                last.2 = end;
+            }
+            current = Some(directory);
+        }
+        if directory_records.is_empty() {
+            // A lone empty record keeps every file in the root.
+            directory_records.push((0, 0, 0));
+        }
+        if files.len() > u16::MAX as usize || directory_records.len() > u16::MAX as usize {
+            return Err(Error::TooLarge("files"));
+        }
+
+        let mut names = Vec::new();
+        let mut types: Vec<u8> = Vec::new();
+        let mut type_offsets = HashMap::new();
+        let mut file_records = Vec::with_capacity(files.len());
+        for (_, name, ext, data) in &files {
+            let type_offset = *type_offsets.entry(*ext).or_insert_with(|| {
+                let offset = types.len();
+                types.extend_from_slice(ext.as_bytes());
+                types.push(0);
+                offset
+            });
+            file_records.push((type_offset, names.len(), data.len()));
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+        if types.len() > u16::MAX as usize {
+            return Err(Error::TooLarge("file types"));
+        }
+
+        let directory_records_offset = 512 + file_records.len() * 16;
+        let name_directory_offset = directory_records_offset + directory_records.len() * 12;
+        let file_type_directory_offset = name_directory_offset + names.len();
+        let file_data_offset = file_type_directory_offset + types.len();
+        let size = file_data_offset + files.iter().map(|(.., data)| data.len()).sum::<usize>();
+        if size > u32::MAX as usize {
+            return Err(Error::TooLarge("bytes"));
+        }
+
+        writer.write_all(b"ZPKG")?;
+        writer.write_all(&self.version.to_le_bytes())?;
+        for value in [
+            file_data_offset,
+            files.len(),
+            directory_records_offset,
+            directory_records.len(),
+            name_directory_offset,
+            file_type_directory_offset,
+        ] {
+            writer.write_all(&(value as u32).to_le_bytes())?;
+        }
+        // The parser insists on exactly this much zeroed padding.
+        writer.write_all(&[0; 480])?;
+
+        let mut data_offset = file_data_offset;
+        for (type_offset, name_offset, size) in file_records {
+            writer.write_all(&[0])?;
+            writer.write_all(&(type_offset as u16).to_le_bytes())?;
+            writer.write_all(&[0])?;
+            for value in [name_offset, data_offset, size] {
+                writer.write_all(&(value as u32).to_le_bytes())?;
+            }
+            data_offset += size;
+        }
+        for (character, start, end) in directory_records {
+            writer.write_all(&[character, 0])?;
+            // link 1, link 2, record id, start file index, end file index
+            for value in [0, 0, 0, start, end] {
+                writer.write_all(&(value as u16).to_le_bytes())?;
+            }
+        }
+        writer.write_all(&names)?;
+        writer.write_all(&types)?;
+        for (.., data) in files {
+            writer.write_all(&data)?;
+        }
+        Ok(())
+    }
+
+    /// The output paths, as given by [`ZpkgFile::relative_path`], that more than one file resolves to. Directory
+    /// names are rebuilt heuristically, so a damaged or unusual archive can make files collide.
+    pub fn duplicate_paths(&self) -> Vec<&str> {
+        let mut seen = HashSet::with_capacity(self.files.len());
+        let duplicates: BTreeSet<&str> = self
+            .files
+            .iter()
+            .map(ZpkgFile::relative_path)
+            .filter(|path| !seen.insert(*path))
+            .collect();
+        duplicates.into_iter().collect()
+    }
+
+    /// Renames every file whose path is already taken by an earlier one, adding `_1`, `_2`, ... to its name.
+    /// Returns how many files were renamed.
+    pub fn dedupe_paths(&mut self) -> usize {
+        let mut taken: HashSet<String> = self.files.iter().map(|file| file.path.clone()).collect();
+        let mut seen = HashSet::with_capacity(self.files.len());
+        let mut renamed = 0;
+        for file in &mut self.files {
+            if seen.insert(file.path.clone()) {
+                continue;
+            }
+
+            let name_start = file.path.rfind('/').map_or(0, |slash| slash + 1);
+            let (stem, ext) = match file.path[name_start..].rfind('.') {
+                Some(dot) if dot > 0 => file.path.split_at(name_start + dot),
+                _ => (file.path.as_str(), ""),
+            };
+            let path = (1..)
+                .map(|n| format!("{}_{}{}", stem, n, ext))
+                .find(|path| !taken.contains(path))
+                .unwrap();
+            taken.insert(path.clone());
+            seen.insert(path.clone());
+            file.path = path;
+            renamed += 1;
+        }
+        renamed
+    }
+
+    fn check_duplicates(&self) -> Result<(), Error> {
+        let duplicates = self.duplicate_paths();
+        if duplicates.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::DuplicatePaths(
+                duplicates.into_iter().map(String::from).collect(),
+            ))
+        }
+    }
+
+    /// Writes every file below `output`, creating directories as needed. Fails without writing anything if two
+    /// files share a path, see [`Zpkg::duplicate_paths`].
+    pub fn extract_to<P: AsRef<Path>>(&self, output: P) -> Result<(), Error> {
+        self.extract_to_with_progress(output, |_| {})
+    }
+
+    /// The directories and files of the archive as a tree, including directories without any files.
+    pub fn tree(&self) -> Directory<'_> {
+        let mut root = Directory::default();
+        for directory in &self.directories {
+            root.entry(directory);
+        }
+        for file in &self.files {
+            let directory = file.path.rsplit_once('/').map_or("", |(directory, _)| directory);
+            root.entry(directory).files.push(file);
+        }
+        root
+    }
+
+    fn directory_paths(&self, output: &Path) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        self.tree().leaves(output, &mut paths);
+        paths
+    }
+
+    /// Like [`Zpkg::extract_to`], calling `progress` after each file.
+    pub fn extract_to_with_progress<P: AsRef<Path>, F: FnMut(Progress)>(
+        &self,
+        output: P,
+        mut progress: F,
+    ) -> Result<(), Error> {
+        self.check_duplicates()?;
+        let mut state = Progress {
+            files: 0,
+            total_files: self.files.len(),
+            bytes: 0,
+            total_bytes: self.files.iter().map(|file| file.data.len()).sum(),
+        };
+
+        for path in self.directory_paths(output.as_ref()) {
+            fs::create_dir_all(path)?;
+        }
+
+        for file in &self.files {
+            let path = output.as_ref().join(file.relative_path());
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, &file.data)?;
+
+            state.files += 1;
+            state.bytes += file.data.len();
+            progress(state);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Zpkg {
+    /// Reads the rest of `reader` into memory, then parses it like [`Zpkg::from_slice`].
+    pub async fn from_async_reader<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Zpkg, Error> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await?;
+        Zpkg::from_slice(&data)
+    }
+
+    /// Like [`Zpkg::extract_to`], but writes the files concurrently through `tokio::fs`.
+    pub async fn extract_to_async<P: AsRef<Path>>(&self, output: P) -> Result<(), Error> {
+        self.check_duplicates()?;
+        let output = output.as_ref();
+        try_join_all(self.directory_paths(output).into_iter().map(tokio::fs::create_dir_all)).await?;
+        try_join_all(self.files.iter().map(|file| async move {
+            let path = output.join(file.relative_path());
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(path, &file.data).await
+        }))
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(number_of_files: u32) -> Vec<u8> {
+        let mut data = b"ZPKG".to_vec();
+        // version, file data, file count, directory records, directory count, names, types
+        for value in [1, 528, number_of_files, 528, 0, 528, 528] {
+            data.extend_from_slice(&u32::to_le_bytes(value));
+        }
+        data.resize(512, 0);
+        data
+    }
+
+    /// An archive holding `(name, extension, data)` files in its root directory.
+    fn archive(files: &[(&str, &str, &[u8])]) -> Vec<u8> {
+        let (mut names, mut types, mut data, mut records) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        for (name, ext, contents) in files {
+            records.push((names.len(), types.len(), data.len(), contents.len()));
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+            types.extend_from_slice(ext.as_bytes());
+            types.push(0);
+            data.extend_from_slice(contents);
+        }
+
+        let directory_records_offset = 512 + files.len() * 16;
+        let name_directory_offset = directory_records_offset + 12;
+        let file_type_directory_offset = name_directory_offset + names.len();
+        let file_data_offset = file_type_directory_offset + types.len();
+
+        let mut output = b"ZPKG".to_vec();
+        for value in [
+            1,
+            file_data_offset,
+            files.len(),
+            directory_records_offset,
+            1,
+            name_directory_offset,
+            file_type_directory_offset,
+        ] {
+            output.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+        output.resize(512, 0);
+        for (name_offset, type_offset, data_offset, size) in records {
+            output.push(0);
+            output.extend_from_slice(&(type_offset as u16).to_le_bytes());
+            output.push(0);
+            for value in [name_offset, file_data_offset + data_offset, size] {
+                output.extend_from_slice(&(value as u32).to_le_bytes());
+            }
+        }
+        // A single directory record without files keeps everything in the root.
+        output.extend_from_slice(&[0; 12]);
+        output.extend(names);
+        output.extend(types);
+        output.extend(data);
+        output
+    }
+
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn compressed_files() {
+        use flate2::{write::ZlibEncoder, Compression};
+
+        let original = b"x = 1\n".repeat(100);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&original).unwrap();
+        let compressed = encoder.finish().unwrap();
+        // A zlib header on its own, or followed by more than the stream, is kept as it is.
+        let header_only = &compressed[..2];
+        let mut trailing = compressed.clone();
+        trailing.push(0);
+
+        let input = archive(&[
+            ("compressed", "lua", &compressed),
+            ("plain", "lua", &original),
+            ("header", "bin", header_only),
+            ("trailing", "bin", &trailing),
+        ]);
+        let mut zpkg = Zpkg::from_slice(&input).unwrap();
+        // Nothing is inflated unless asked.
+        assert_eq!(&compressed[..], files(&zpkg)[0].1);
+
+        assert_eq!(1, zpkg.inflate());
+        assert_eq!(
+            vec![
+                ("/compressed.lua", &original[..]),
+                ("/plain.lua", &original[..]),
+                ("/header.bin", header_only),
+                ("/trailing.bin", &trailing[..]),
+            ],
+            files(&zpkg)
+        );
+        assert_eq!(
+            vec![true, false, false, false],
+            zpkg.files.iter().map(ZpkgFile::is_compressed).collect::<Vec<_>>()
+        );
+        assert!(zpkg.verify().is_ok());
+        // Already inflated files aren't inflated twice.
+        assert_eq!(0, zpkg.inflate());
+
+        // Writing deflates the file again.
+        let mut output = Vec::new();
+        zpkg.write(&mut output).unwrap();
+        let mut written = Zpkg::from_slice(&output).unwrap();
+        let stored = written.file("/compressed.lua").unwrap();
+        assert_ne!(&original[..], &stored.data[..]);
+        assert_eq!(1, written.inflate());
+        assert_eq!(&original[..], &written.file("/compressed.lua").unwrap().data[..]);
+    }
+
+    fn files(zpkg: &Zpkg) -> Vec<(&str, &[u8])> {
+        zpkg.files
+            .iter()
+            .map(|file| (file.path.as_str(), &file.data[..]))
+            .collect()
+    }
+
+    fn read_dir(dir: &Path) -> Vec<(String, Vec<u8>)> {
+        let mut files: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                (
+                    entry.file_name().into_string().unwrap(),
+                    fs::read(entry.path()).unwrap(),
+                )
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    const FILES: &[(&str, &str, &[u8])] = &[("first", "txt", b"hello"), ("second", "lua", b"x = 1\n")];
+
+    #[test]
+    fn extract_to() {
+        let dir = tempfile::tempdir().unwrap();
+        let zpkg = Zpkg::from_slice(&archive(FILES)).unwrap();
+        assert_eq!(
+            vec![("/first.txt", &b"hello"[..]), ("/second.lua", b"x = 1\n")],
+            files(&zpkg)
+        );
+
+        zpkg.extract_to(dir.path()).unwrap();
+        assert_eq!(
+            vec![
+                ("first.txt".to_string(), b"hello".to_vec()),
+                ("second.lua".to_string(), b"x = 1\n".to_vec())
+            ],
+            read_dir(dir.path())
+        );
+    }
+
+    #[test]
+    fn extract_progress() {
+        let dir = tempfile::tempdir().unwrap();
+        let zpkg = Zpkg::from_slice(&archive(FILES)).unwrap();
+
+        let mut calls = Vec::new();
+        zpkg.extract_to_with_progress(dir.path(), |progress| calls.push(progress))
+            .unwrap();
+        assert_eq!(
+            vec![
+                Progress {
+                    files: 1,
+                    total_files: 2,
+                    bytes: 5,
+                    total_bytes: 11
+                },
+                Progress {
+                    files: 2,
+                    total_files: 2,
+                    bytes: 11,
+                    total_bytes: 11
+                },
+            ],
+            calls
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn async_matches_sync() {
+        let data = archive(FILES);
+        let sync = Zpkg::from_slice(&data).unwrap();
+        let async_ = Zpkg::from_async_reader(&mut std::io::Cursor::new(data)).await.unwrap();
+        assert_eq!(files(&sync), files(&async_));
+
+        let (sync_dir, async_dir) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        sync.extract_to(sync_dir.path()).unwrap();
+        async_.extract_to_async(async_dir.path()).await.unwrap();
+        assert_eq!(read_dir(sync_dir.path()), read_dir(async_dir.path()));
+    }
+
+    #[test]
+    fn verify() {
+        let data = archive(FILES);
+        assert!(Zpkg::from_slice(&data).unwrap().verify().is_ok());
+
+        // Cut the last byte of `second.lua`.
+        let mut zpkg = Zpkg::from_slice(&data[..data.len() - 1]).unwrap();
+        assert_eq!(b"x = 1", &zpkg.files[1].data[..]);
+        let errors = zpkg.verify().unwrap_err();
+        assert_eq!(1, errors.len());
+        assert!(matches!(
+            &errors[0],
+            Error::DataOutOfBounds { path, size: 6, .. } if path == "/second.lua"
+        ));
+
+        // The check stays with the file when `files` changes.
+        zpkg.files.remove(0);
+        zpkg.files.push(ZpkgFile::new("/third.txt", b"new".to_vec()));
+        let errors = zpkg.verify().unwrap_err();
+        assert_eq!(1, errors.len());
+        assert!(matches!(&errors[0], Error::DataOutOfBounds { path, .. } if path == "/second.lua"));
+        zpkg.files.retain(|file| file.path != "/second.lua");
+        assert!(zpkg.verify().is_ok());
+    }
+
+    fn write(zpkg: &Zpkg) -> Vec<u8> {
+        let mut output = Vec::new();
+        zpkg.write(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn rename_and_rewrite() {
+        let mut zpkg = Zpkg::from_slice(&archive(FILES)).unwrap();
+        assert!(zpkg.rename("first.txt", "docs/readme.md"));
+        assert!(!zpkg.rename("/first.txt", "other.txt"));
+        for file in zpkg.iter_mut().filter(|file| file.path == "/second.lua") {
+            file.path = "/scripts/second.lua".to_string();
+        }
+        assert_eq!(1, zpkg.files_by_extension("md").count());
+        assert_eq!(0, zpkg.files_by_extension("txt").count());
+
+        let zpkg = Zpkg::from_slice(&write(&zpkg)).unwrap();
+        let mut paths: Vec<&str> = zpkg.files.iter().map(|file| file.path.as_str()).collect();
+        paths.sort_unstable();
+        assert_eq!(vec!["/docs/readme.md", "/scripts/second.lua"], paths);
+        assert_eq!(b"hello", &zpkg.file("docs/readme.md").unwrap().data[..]);
+    }
+
+    #[test]
+    fn rewrite() {
+        let data = archive(FILES);
+        let mut zpkg = Zpkg::from_slice(&data).unwrap();
+        assert_eq!(data, write(&zpkg));
+
+        zpkg.version = 3;
+        let output = write(&zpkg);
+        assert_eq!(&[0; 480][..], &output[32..512]);
+        assert_eq!(3, Zpkg::from_slice(&output).unwrap().version);
+    }
+
+    #[test]
+    fn write_directories() {
+        let file = |path: &str, data: &[u8]| ZpkgFile::new(path, data.to_vec());
+        let zpkg = Zpkg::new(
+            1,
+            vec![
+                file("/scripts/ui/menu.lua", b"menu"),
+                file("/root.txt", b"root"),
+                file("/textures/ui.dds", b"ui"),
+                file("/scripts/main.lua", b"main"),
+                file("/scripts/ui/hud.lua", b"hud"),
+                file("/scripts/uix/extra.lua", b"extra"),
+            ],
+        );
+
+        let zpkg = Zpkg::from_slice(&write(&zpkg)).unwrap();
+        assert!(zpkg.verify().is_ok());
+        assert_eq!(
+            vec![
+                ("/root.txt", &b"root"[..]),
+                ("/scripts/main.lua", b"main"),
+                ("/scripts/ui/menu.lua", b"menu"),
+                ("/scripts/ui/hud.lua", b"hud"),
+                ("/scripts/uix/extra.lua", b"extra"),
+                ("/textures/ui.dds", b"ui"),
+            ],
+            files(&zpkg)
+        );
+    }
+
+    #[test]
+    fn empty_directories() {
+        let mut zpkg = Zpkg::from_slice(&archive(FILES)).unwrap();
+        zpkg.files[1].path = "/scripts/second.lua".to_string();
+        zpkg.directories = vec!["/empty/nested".to_string(), "/scripts/empty".to_string()];
+
+        let zpkg = Zpkg::from_slice(&write(&zpkg)).unwrap();
+        assert_eq!(vec!["/empty/nested", "/scripts", "/scripts/empty"], zpkg.directories);
+
+        let tree = zpkg.tree();
+        assert_eq!(
+            vec!["first.txt"],
+            tree.files.iter().map(|file| file.file_name()).collect::<Vec<_>>()
+        );
+        let scripts = &tree.directories["scripts"];
+        assert_eq!("second.lua", scripts.files[0].file_name());
+        assert!(scripts.directories["empty"].files.is_empty());
+        assert!(tree.directories["empty"].directories["nested"].directories.is_empty());
+
+        let dir = tempfile::tempdir().unwrap();
+        zpkg.extract_to(dir.path()).unwrap();
+        assert!(dir.path().join("empty/nested").is_dir());
+        assert!(dir.path().join("scripts/empty").is_dir());
+        assert_eq!(
+            b"x = 1\n",
+            &fs::read(dir.path().join("scripts/second.lua")).unwrap()[..]
+        );
+    }
+
+    #[test]
+    fn from_directory() {
+        let input = tempfile::tempdir().unwrap();
+        for (path, data) in [
+            ("root.txt", &b"root"[..]),
+            ("scripts/main.lua", b"main"),
+            ("scripts/ui/hud.lua", b"hud"),
+            ("textures/ui.dds", b"ui"),
+        ] {
+            let path = input.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, data).unwrap();
+        }
+        fs::create_dir_all(input.path().join("empty/nested")).unwrap();
+
+        let zpkg = Zpkg::from_directory(input.path()).unwrap();
+        assert_eq!(
+            vec![
+                ("/root.txt", &b"root"[..]),
+                ("/scripts/main.lua", b"main"),
+                ("/scripts/ui/hud.lua", b"hud"),
+                ("/textures/ui.dds", b"ui"),
+            ],
+            files(&zpkg)
+        );
+
+        let output = tempfile::tempdir().unwrap();
+        Zpkg::from_slice(&write(&zpkg))
+            .unwrap()
+            .extract_to(output.path())
+            .unwrap();
+        assert!(output.path().join("empty/nested").is_dir());
+        for path in ["root.txt", "scripts/main.lua", "scripts/ui/hud.lua", "textures/ui.dds"] {
+            assert_eq!(
+                fs::read(input.path().join(path)).unwrap(),
+                fs::read(output.path().join(path)).unwrap()
+            );
+        }
+
+        fs::write(input.path().join("scripts/README"), b"no extension").unwrap();
+        assert!(matches!(
+            Zpkg::from_directory(input.path()),
+            Err(Error::UnsupportedPath(path)) if path == "/scripts/README"
+        ));
+    }
+
+    #[test]
+    fn files_by_extension() {
+        let zpkg = Zpkg::from_slice(&archive(&[
+            ("level1", "ppf", b"a"),
+            ("first", "txt", b"hello"),
+            ("level2", "ppf", b"b"),
+        ]))
+        .unwrap();
+
+        let paths = |ext| {
+            zpkg.files_by_extension(ext)
+                .map(|file| file.path.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["/level1.ppf", "/level2.ppf"], paths("ppf"));
+        assert_eq!(vec!["/first.txt"], paths(".txt"));
+        assert!(paths("dds").is_empty());
+
+        assert_eq!(b"hello", &zpkg.file("first.txt").unwrap().data[..]);
+        assert_eq!(b"b", &zpkg.file("\\level2.ppf").unwrap().data[..]);
+        assert!(zpkg.file("/First.txt").is_none());
+
+        let mut built = Zpkg::new(1, zpkg.files);
+        assert_eq!(2, built.files_by_extension("ppf").count());
+
+        // Changes made straight to `files` are followed too.
+        built.files.retain(|file| file.path != "/level1.ppf");
+        built.files.push(ZpkgFile::new("/level3.ppf", b"c".to_vec()));
+        assert_eq!(
+            vec!["/level2.ppf", "/level3.ppf"],
+            built
+                .files_by_extension("ppf")
+                .map(|file| file.path.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn duplicate_paths() {
+        let mut zpkg = Zpkg::from_slice(&archive(&[
+            ("first", "txt", b"a"),
+            ("first", "txt", b"b"),
+            ("first_1", "txt", b"c"),
+            ("first", "txt", b"d"),
+        ]))
+        .unwrap();
+        assert_eq!(vec!["first.txt"], zpkg.duplicate_paths());
+
+        let dir = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            zpkg.extract_to(dir.path()),
+            Err(Error::DuplicatePaths(paths)) if paths == ["first.txt"]
+        ));
+        assert!(!dir.path().join("first.txt").exists());
+
+        assert_eq!(2, zpkg.dedupe_paths());
+        let paths: Vec<&str> = zpkg.files.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(
+            vec!["/first.txt", "/first_2.txt", "/first_1.txt", "/first_3.txt"],
+            paths
+        );
+        assert!(zpkg.duplicate_paths().is_empty());
+        zpkg.extract_to(dir.path()).unwrap();
+        assert_eq!(b"d", &fs::read(dir.path().join("first_3.txt")).unwrap()[..]);
+    }
+
+    #[test]
+    fn write_unsupported_path() {
+        for path in ["/no_extension", "/dir/.lua", "/caf\u{e9}.txt"] {
+            let zpkg = Zpkg::new(1, vec![ZpkgFile::new(path, Vec::new())]);
+            assert!(matches!(zpkg.write(&mut Vec::new()), Err(Error::UnsupportedPath(_))));
+        }
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut data = header(0);
+        data[..4].copy_from_slice(b"ZPAK");
+        assert!(matches!(Zpkg::from_slice(&data), Err(Error::Parse("pkg header"))));
+    }
+
+    #[test]
+    fn file_count_mismatch() {
+        let mut data = header(2);
+        data.extend_from_slice(&[0; 16]);
+        assert!(matches!(
+            Zpkg::from_slice(&data),
+            Err(Error::CountMismatch {
+                what: "file records",
+                expected: 2,
+                found: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn offsets_out_of_order() {
+        let mut data = archive(FILES);
+        // Swap the name and file type directory offsets.
+        let (names, types) = (data[24..28].to_vec(), data[28..32].to_vec());
+        data[24..28].copy_from_slice(&types);
+        data[28..32].copy_from_slice(&names);
+        assert!(matches!(
+            Zpkg::from_slice(&data),
+            Err(Error::OffsetOutOfBounds {
+                what: "file type directory",
+                ..
+            })
+        ));
+
+        let mut data = header(0);
+        data[16..20].copy_from_slice(&100u32.to_le_bytes());
+        assert!(matches!(
+            Zpkg::from_slice(&data),
+            Err(Error::OffsetOutOfBounds {
+                what: "directory records",
+                offset: 100,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn entry_offsets_past_end() {
+        // The first file record's name offset, then its file type offset.
+        for (what, field) in [("file name", 516..520), ("file extension", 513..515)] {
+            let mut data = archive(FILES);
+            let mut offset = 1000u32.to_le_bytes().to_vec();
+            offset.truncate(field.len());
+            data[field].copy_from_slice(&offset);
+            assert!(matches!(
+                Zpkg::from_slice(&data),
+                Err(Error::OffsetOutOfBounds { what: found, offset: 1000, .. }) if found == what
+            ));
+        }
+    }
+
+    #[test]
+    fn offsets_past_end() {
+        let mut data = archive(FILES);
+        let length = data.len();
+        data[8..12].copy_from_slice(&(length as u32 + 1).to_le_bytes());
+        assert!(matches!(
+            Zpkg::from_slice(&data),
+            Err(Error::OffsetOutOfBounds { what: "file data", offset, length: found }) if offset == length + 1 && found == length
+        ));
+    }
 }
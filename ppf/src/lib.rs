@@ -1,15 +1,38 @@
-use std::fmt;
+use std::{
+    fmt, io,
+    io::{Read, Seek},
+};
 
-use binrw::{binrw, until_eof};
+use binrw::{binrw, helpers::until_eof, BinRead};
 
-use lpf::LuaPackFile;
-use mpf::MeshPackFile;
-use tpf::TexturePackFile;
+pub use common::{PackVersion, Path};
+pub use lpf::{v0::Script as ScriptV0, v1::Script as ScriptV1, Global, LuaPackFile, Script};
+pub use mpf::{Mesh, MeshPackFile};
+pub use tpf::{
+    v0::GameTexture as GameTextureV0, v1::GameTexture as GameTextureV1, DdsCompat, DecodeCache, Error as TextureError,
+    GameTexture, GameTextureFlags, LanguageId, Palette, Texture, TextureFlags, TextureFormat, TextureMetadata,
+    TexturePackFile, TextureType, DEFAULT_LANGUAGE,
+};
 
-pub use common::Path;
-pub use lpf::{v0::Script as ScriptV0, v1::Script as ScriptV1, Global, Script};
-pub use mpf::Mesh;
-pub use tpf::{GameTexture, Palette, Texture, TextureFormat, TextureType};
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Parse error at 0x{pos:X}: {message}")]
+    Parse { pos: u64, message: String },
+    #[error(transparent)]
+    Texture(TextureError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl From<binrw::Error> for Error {
+    fn from(err: binrw::Error) -> Self {
+        match TextureError::from(err) {
+            TextureError::Parse { pos, message } => Error::Parse { pos, message },
+            TextureError::Io(err) => Error::Io(err),
+            err => Error::Texture(err),
+        }
+    }
+}
 
 #[binrw]
 #[brw(little, magic = b"PPAK")]
@@ -21,6 +44,180 @@ pub struct Ppf {
     pub level: Vec<u8>,
 }
 
+impl Ppf {
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Ppf, Error> {
+        Ok(Ppf::read(reader)?)
+    }
+
+    /// Reads the rest of `reader` into memory, then parses it like [`Ppf::from_reader`].
+    #[cfg(feature = "tokio")]
+    pub async fn read_async<R: tokio::io::AsyncRead + Unpin>(reader: &mut R) -> Result<Ppf, Error> {
+        use tokio::io::AsyncReadExt;
+
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await?;
+        Ppf::from_reader(&mut io::Cursor::new(data))
+    }
+}
+
+/// One section of a ppf, see [`read_sections`].
+#[derive(Debug)]
+pub enum PpfSection {
+    Textures(TexturePackFile),
+    Meshes(MeshPackFile),
+    Scripts(LuaPackFile),
+    Level(Vec<u8>),
+}
+
+/// Reads the sections of a ppf one at a time, in file order, instead of building a whole [`Ppf`].
+///
+/// Stopping early leaves `reader` just past the last section read. Iteration ends after the first error.
+pub fn read_sections<R: Read + Seek>(reader: &mut R) -> Sections<'_, R> {
+    Sections { reader, next: 0 }
+}
+
+/// The iterator returned by [`read_sections`].
+pub struct Sections<'a, R> {
+    reader: &'a mut R,
+    next: usize,
+}
+
+impl<R: Read + Seek> Sections<'_, R> {
+    fn read_section(&mut self) -> Result<PpfSection, Error> {
+        let reader = &mut *self.reader;
+        Ok(match self.next {
+            0 => {
+                let pos = reader.stream_position()?;
+                let mut magic = [0; 4];
+                reader.read_exact(&mut magic)?;
+                if &magic != b"PPAK" {
+                    return Err(Error::Parse {
+                        pos,
+                        message: format!("bad magic {:?}", magic),
+                    });
+                }
+                PpfSection::Textures(TexturePackFile::read(reader)?)
+            }
+            1 => PpfSection::Meshes(MeshPackFile::read(reader)?),
+            2 => PpfSection::Scripts(LuaPackFile::read(reader)?),
+            _ => {
+                let mut level = Vec::new();
+                reader.read_to_end(&mut level)?;
+                PpfSection::Level(level)
+            }
+        })
+    }
+}
+
+impl<R: Read + Seek> Iterator for Sections<'_, R> {
+    type Item = Result<PpfSection, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next > 3 {
+            return None;
+        }
+
+        let section = self.read_section();
+        self.next = if section.is_ok() { self.next + 1 } else { usize::MAX };
+        Some(section)
+    }
+}
+
+/// A section read by [`Ppf::read_lenient`], with where it starts and, if it parsed, how long it is.
+#[derive(Debug)]
+pub struct Recovered<T> {
+    pub offset: u64,
+    pub size: u64,
+    pub value: Result<T, Error>,
+}
+
+impl<T> Recovered<T> {
+    /// Where the next section starts, if this one parsed.
+    fn end(&self) -> Option<u64> {
+        self.value.as_ref().ok().map(|_| self.offset + self.size)
+    }
+}
+
+/// Parses a section at `offset` with `read`, or reports it lost when there's no offset to start from.
+fn read_recovered<T>(
+    reader: &mut io::Cursor<&[u8]>,
+    offset: Option<u64>,
+    read: impl FnOnce(&mut io::Cursor<&[u8]>) -> Result<T, Error>,
+) -> Recovered<T> {
+    let offset = match offset {
+        Some(offset) => offset,
+        None => {
+            return Recovered {
+                offset: reader.position(),
+                size: 0,
+                value: Err(Error::Parse {
+                    pos: reader.position(),
+                    message: "lost the start of this section after the one before it failed".to_string(),
+                }),
+            }
+        }
+    };
+    reader.set_position(offset);
+    let value = read(reader);
+    let size = match value {
+        Ok(_) => reader.position() - offset,
+        Err(_) => 0,
+    };
+    Recovered { offset, size, value }
+}
+
+/// Every section of a ppf, each parsed on its own so a corrupt one doesn't hide the rest, see [`Ppf::read_lenient`].
+#[derive(Debug)]
+pub struct LenientPpf {
+    pub textures: Recovered<TexturePackFile>,
+    pub meshes: Recovered<MeshPackFile>,
+    pub scripts: Recovered<LuaPackFile>,
+    pub level: Recovered<Vec<u8>>,
+}
+
+impl Ppf {
+    /// Reads each section on its own, keeping the ones that parse even when others don't.
+    ///
+    /// Sections don't store their length, so a section after a failed one can only be found again by its magic.
+    /// That works for the meshes, which start with `MPAK`; after failed meshes or scripts, the sections that follow
+    /// are reported as lost. Only I/O errors while reading the rest of `reader` into memory fail the whole read.
+    pub fn read_lenient<R: Read>(reader: &mut R) -> Result<LenientPpf, Error> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let mut reader = io::Cursor::new(&data[..]);
+
+        let textures = read_recovered(&mut reader, Some(4), |reader| {
+            if !data.starts_with(b"PPAK") {
+                return Err(Error::Parse {
+                    pos: 0,
+                    message: format!("bad magic {:?}", &data[..data.len().min(4)]),
+                });
+            }
+            Ok(TexturePackFile::read(reader)?)
+        });
+        let meshes_offset = textures.end().or_else(|| {
+            data.get(4..)?
+                .windows(4)
+                .position(|window| window == b"MPAK")
+                .map(|position| position as u64 + 4)
+        });
+        let meshes = read_recovered(&mut reader, meshes_offset, |reader| Ok(MeshPackFile::read(reader)?));
+        let scripts = read_recovered(&mut reader, meshes.end(), |reader| Ok(LuaPackFile::read(reader)?));
+        let level = read_recovered(&mut reader, scripts.end(), |reader| {
+            let mut level = Vec::new();
+            reader.read_to_end(&mut level)?;
+            Ok(level)
+        });
+
+        Ok(LenientPpf {
+            textures,
+            meshes,
+            scripts,
+            level,
+        })
+    }
+}
+
 impl fmt::Debug for Ppf {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Ppf")
@@ -31,3 +228,309 @@ impl fmt::Debug for Ppf {
             .finish()
     }
 }
+
+/// A listing of everything in a [`Ppf`], see [`Ppf::summary`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PpfSummary {
+    pub textures: Vec<TextureSummary>,
+    pub meshes: Vec<EntrySummary>,
+    pub globals: Vec<EntrySummary>,
+    pub scripts: Vec<EntrySummary>,
+    pub level_size: usize,
+}
+
+/// One game texture, with the metadata of each of its frames.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TextureSummary {
+    pub path: Option<String>,
+    /// `None` for textures shared by every language.
+    pub language: Option<LanguageId>,
+    pub frames: Vec<TextureMetadata>,
+}
+
+/// A mesh, global or script. Only v0 scripts lack a path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EntrySummary {
+    pub path: Option<String>,
+    pub size: usize,
+}
+
+impl Ppf {
+    /// Lists every texture, mesh, global and script, with textures of every language included.
+    pub fn summary(&self) -> PpfSummary {
+        let texture = |language, game_texture: &GameTexture| {
+            let game_texture = game_texture.inner();
+            TextureSummary {
+                path: game_texture.path.as_ref().map(|path| path.to_string()),
+                language,
+                frames: game_texture.textures.iter().map(Texture::metadata).collect(),
+            }
+        };
+        let entry = |path: Option<&Path>, size| EntrySummary {
+            path: path.map(|path| path.to_string()),
+            size,
+        };
+
+        PpfSummary {
+            textures: self
+                .textures
+                .game_textures
+                .iter()
+                .map(|game_texture| texture(None, game_texture))
+                .chain(self.textures.languages.iter().flat_map(|language| {
+                    language
+                        .game_textures
+                        .iter()
+                        .map(move |game_texture| texture(Some(language.id), game_texture))
+                }))
+                .collect(),
+            meshes: self
+                .meshes
+                .meshes
+                .iter()
+                .map(|mesh| entry(Some(&mesh.path), mesh.data.len()))
+                .collect(),
+            globals: self
+                .scripts
+                .globals
+                .iter()
+                .map(|global| entry(Some(&global.path), global.data.len()))
+                .collect(),
+            scripts: self
+                .scripts
+                .scripts
+                .iter()
+                .map(|script| match script {
+                    Script::V0(script) => entry(None, script.data.len()),
+                    Script::V1(script) => entry(Some(&script.path), script.script.data.len()),
+                })
+                .collect(),
+            level_size: self.level.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use binrw::BinWrite;
+
+    use super::*;
+
+    fn texture(width: usize, height: usize) -> Texture {
+        Texture {
+            format: TextureFormat::L8,
+            type_: TextureType::Bitmap,
+            flags: TextureFlags::empty(),
+            width,
+            height,
+            mipmaps: 1,
+            palette: None,
+            data: vec![0; width * height],
+        }
+    }
+
+    fn game_texture(path: &str, texture: Texture) -> GameTexture {
+        GameTexture::V0(tpf::v0::GameTexture {
+            element_id: 0,
+            texture_handle: 1,
+            palette_handle: 0,
+            path_pointer: 1,
+            animation_info_pointer: 0,
+            density: 1.0,
+            visual_importance: 0,
+            memory_importance: 0,
+            unknown0: 0,
+            flags: GameTextureFlags::empty(),
+            path: Some(Path::new(path)),
+            animation_info: None,
+            textures: vec![texture],
+        })
+    }
+
+    #[cfg(feature = "tokio")]
+    fn write(ppf: &Ppf) -> Vec<u8> {
+        let mut writer = Cursor::new(Vec::new());
+        ppf.write_le(&mut writer).unwrap();
+        writer.into_inner()
+    }
+
+    /// A ppf with something in every section.
+    #[cfg(feature = "tokio")]
+    fn full_ppf() -> Vec<u8> {
+        write(&Ppf {
+            textures: TexturePackFile {
+                version: None,
+                languages: vec![],
+                game_textures: vec![game_texture("a", texture(4, 2)), game_texture("b", texture(1, 1))],
+            },
+            meshes: MeshPackFile {
+                meshes: vec![Mesh {
+                    path: Path::new("meshes/rock"),
+                    unknown0: 7,
+                    data: vec![1, 2, 3],
+                }],
+            },
+            scripts: LuaPackFile {
+                version: Some(lpf::Version::V1),
+                globals: vec![Global {
+                    path: Path::new("globals.lua"),
+                    data: b"x = 1".to_vec(),
+                }],
+                scripts: vec![Script::V1(ScriptV1 {
+                    path: Path::new("scripts/main.lua"),
+                    script: ScriptV0 {
+                        data: b"print(x)".to_vec(),
+                    },
+                })],
+            },
+            level: b"level".to_vec(),
+        })
+    }
+
+    fn empty_ppf() -> Vec<u8> {
+        let mut data = b"PPAK".to_vec();
+        data.extend_from_slice(&[0, 0]); // no textures
+        data.extend_from_slice(b"MPAK\0\0"); // no meshes
+        data.extend_from_slice(&[0; 4]); // no globals or scripts
+        data.extend_from_slice(b"level");
+        data
+    }
+
+    #[test]
+    fn empty() {
+        let ppf = Ppf::from_reader(&mut Cursor::new(empty_ppf())).unwrap();
+        assert!(ppf.textures.game_textures.is_empty());
+        assert_eq!(b"level", &ppf.level[..]);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn read_async() {
+        let data = full_ppf();
+        let sync = Ppf::from_reader(&mut Cursor::new(&data)).unwrap();
+        let async_ = Ppf::read_async(&mut Cursor::new(&data)).await.unwrap();
+
+        let summary = async_.summary();
+        assert_eq!(sync.summary(), summary);
+        assert_eq!(
+            (2, 1, 1, 1),
+            (
+                summary.textures.len(),
+                summary.meshes.len(),
+                summary.globals.len(),
+                summary.scripts.len()
+            )
+        );
+
+        // The sections only have summaries in common, so compare what they write.
+        fn section(write: impl FnOnce(&mut Cursor<Vec<u8>>) -> binrw::BinResult<()>) -> Vec<u8> {
+            let mut writer = Cursor::new(Vec::new());
+            write(&mut writer).unwrap();
+            writer.into_inner()
+        }
+        assert_eq!(
+            section(|writer| sync.textures.write_le(writer)),
+            section(|writer| async_.textures.write_le(writer))
+        );
+        assert_eq!(
+            section(|writer| sync.meshes.write_le(writer)),
+            section(|writer| async_.meshes.write_le(writer))
+        );
+        assert_eq!(
+            section(|writer| sync.scripts.write_le(writer)),
+            section(|writer| async_.scripts.write_le(writer))
+        );
+        assert_eq!(sync.level, async_.level);
+        assert_eq!(data, write(&async_));
+    }
+
+    #[test]
+    fn read_only_textures() {
+        let mut reader = Cursor::new(empty_ppf());
+        let textures = read_sections(&mut reader).next();
+        assert!(matches!(textures, Some(Ok(PpfSection::Textures(textures))) if textures.game_textures.is_empty()));
+        assert_eq!(6, reader.position());
+
+        let sections: Vec<_> = read_sections(&mut Cursor::new(empty_ppf()))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(matches!(&sections[3], PpfSection::Level(level) if level == b"level"));
+        assert_eq!(4, sections.len());
+    }
+
+    #[test]
+    fn summary() {
+        let textures = TexturePackFile {
+            version: None,
+            languages: vec![],
+            game_textures: vec![game_texture("a", texture(4, 2)), game_texture("b", texture(1, 1))],
+        };
+        let mut writer = Cursor::new(b"PPAK".to_vec());
+        writer.set_position(4);
+        textures.write_le(&mut writer).unwrap();
+        let mut data = writer.into_inner();
+        data.extend_from_slice(&empty_ppf()[6..]);
+
+        let ppf = Ppf::from_reader(&mut Cursor::new(data)).unwrap();
+        let summary = ppf.summary();
+        assert_eq!(ppf.textures.game_textures.len(), summary.textures.len());
+        assert_eq!(Some("a"), summary.textures[0].path.as_deref());
+        assert_eq!((4, 2, 1), {
+            let frame = &summary.textures[0].frames[0];
+            (frame.width, frame.height, frame.mipmaps)
+        });
+        assert!(summary.meshes.is_empty() && summary.scripts.is_empty());
+        assert_eq!(5, summary.level_size);
+    }
+
+    #[test]
+    fn lenient_truncated_meshes() {
+        let mut data = empty_ppf()[..6].to_vec();
+        data.extend_from_slice(b"MPAK\x01\x00\x09\x00meshes/a"); // one mesh, cut off inside its path
+
+        assert!(Ppf::from_reader(&mut Cursor::new(&data)).is_err());
+        let ppf = Ppf::read_lenient(&mut Cursor::new(&data)).unwrap();
+        assert!(ppf.textures.value.unwrap().game_textures.is_empty());
+        assert_eq!((4, 2), (ppf.textures.offset, ppf.textures.size));
+        assert_eq!(6, ppf.meshes.offset);
+        assert!(ppf.meshes.value.is_err());
+        assert!(ppf.scripts.value.is_err());
+        assert!(ppf.level.value.is_err());
+    }
+
+    #[test]
+    fn lenient_resyncs_at_meshes() {
+        let mut data = b"PPAK\xFD\xFD\x07\x00".to_vec(); // an unknown tpf version
+        data.extend_from_slice(&empty_ppf()[6..]);
+
+        let ppf = Ppf::read_lenient(&mut Cursor::new(&data)).unwrap();
+        assert!(ppf.textures.value.is_err());
+        assert_eq!(8, ppf.meshes.offset);
+        assert!(ppf.meshes.value.unwrap().meshes.is_empty());
+        assert!(ppf.scripts.value.is_ok());
+        assert_eq!(b"level", &ppf.level.value.unwrap()[..]);
+    }
+
+    #[test]
+    fn bad_magic() {
+        let result = Ppf::from_reader(&mut Cursor::new(b"ZPKG".to_vec()));
+        assert!(matches!(result, Err(Error::Parse { pos: 0, .. })));
+    }
+
+    #[test]
+    fn texture_size_mismatch() {
+        let mut data = b"PPAK".to_vec();
+        data.extend_from_slice(&[0xFF, 0xFF, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+
+        let result = Ppf::from_reader(&mut Cursor::new(data));
+        assert!(matches!(
+            result,
+            Err(Error::Texture(TextureError::SizeMismatch { what: "Language", .. }))
+        ));
+    }
+}
@@ -1,25 +1,126 @@
 use std::{
+    collections::BTreeMap,
     ffi::OsStr,
     fs::File,
-    io::{BufReader, BufWriter, Read, Write},
-    path::{Path, PathBuf},
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::{Component, Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
 };
 
 use clap::Parser;
+use image::{codecs::png::PngEncoder, ColorType, ImageEncoder};
+use rayon::prelude::*;
+use serde::Serialize;
 
 use binrw::{BinRead, BinWrite};
 
-use dds::PixelFormat;
-use pkg::{Zpkg, ZpkgFile};
-use ppf::{Ppf, Texture, TextureFormat, TextureType};
+use common::{logging::LogArgs, normalize_path, path_eq};
+use dds::{Dds, PitchAlignment};
+use pkg::Zpkg;
+use ppf::{
+    DdsCompat, DecodeCache, EntrySummary, GameTexture, GameTextureFlags, GameTextureV0, LanguageId, LuaPackFile,
+    MeshPackFile, Ppf, PpfSummary, Script, Texture, TextureError, TextureMetadata, TexturePackFile, DEFAULT_LANGUAGE,
+};
 
-type BoxError = Box<dyn std::error::Error + Send + Sync>;
+#[derive(Debug, thiserror::Error)]
+enum Error {
+    #[error(transparent)]
+    Pkg(#[from] pkg::Error),
+    #[error("{} files failed verification.", .0.len())]
+    Verify(Vec<pkg::Error>),
+    #[error(transparent)]
+    Ppf(#[from] ppf::Error),
+    #[error(transparent)]
+    Lpf(binrw::Error),
+    #[error(transparent)]
+    Texture(#[from] TextureError),
+    #[error(transparent)]
+    Write(#[from] binrw::Error),
+    #[error("Unsupported input {0:?}.")]
+    Unsupported(PathBuf),
+    #[error("Unsupported input {path:?}, expected one of: {}.", .supported.join(", "))]
+    UnsupportedExtension {
+        path: PathBuf,
+        supported: Vec<&'static str>,
+    },
+    #[error("Problems found: {0}.")]
+    Invalid(usize),
+    #[error("{0:?} isn't empty, pass --merge to add to it or --force to overwrite what's there.")]
+    OutputNotEmpty(PathBuf),
+    #[error("{0:?} is not a DDS file.")]
+    InvalidDds(PathBuf),
+    #[error("No texture named {0:?}.")]
+    TextureNotFound(String),
+    #[error("No textures for {language:?}, only for {available:?}.")]
+    LanguageNotFound {
+        language: LanguageId,
+        available: Vec<LanguageId>,
+    },
+    #[error("--language and --all-languages only apply to tpf textures, not to {0:?}.")]
+    LanguageNotApplicable(PathBuf),
+    #[error("{path:?} holds {count} textures, only a single texture can be converted.")]
+    NotSingleTexture { path: PathBuf, count: usize },
+    #[error("The {0} section of the joined ppf doesn't match its piece.")]
+    JoinMismatch(&'static str),
+    #[error("{0:?} isn't a plain relative path and could be written outside the output directory.")]
+    UnsafePath(String),
+    #[error("Texture {path:?} can't be replaced: {reason}.")]
+    Incompatible { path: String, reason: String },
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl Error {
+    /// Exit codes follow sysexits.h, so scripts can tell bad input apart from I/O trouble.
+    fn exit_code(&self) -> i32 {
+        match self {
+            Error::Pkg(pkg::Error::Io(_)) => 74,
+            Error::Pkg(_) | Error::Verify(_) | Error::Ppf(ppf::Error::Parse { .. }) => 65,
+            Error::Lpf(err) if matches!(err.root_cause(), binrw::Error::Io(_)) => 74,
+            Error::Lpf(_) => 65,
+            Error::InvalidDds(_)
+            | Error::TextureNotFound(_)
+            | Error::LanguageNotFound { .. }
+            | Error::NotSingleTexture { .. }
+            | Error::Incompatible { .. }
+            | Error::UnsafePath(_)
+            | Error::Invalid(_) => 65,
+            Error::Ppf(ppf::Error::Texture(err)) | Error::Texture(err) => match err {
+                TextureError::UnsupportedFormat(_)
+                | TextureError::UnsupportedType(_)
+                | TextureError::UnsupportedPixelFormat => 69,
+                TextureError::Io(_) => 74,
+                _ => 65,
+            },
+            Error::Unsupported(_) | Error::UnsupportedExtension { .. } => 69,
+            Error::OutputNotEmpty(_) => 73,
+            Error::LanguageNotApplicable(_) => 64,
+            Error::Ppf(ppf::Error::Io(_)) | Error::Io(_) => 74,
+            Error::Write(_) | Error::JoinMismatch(_) | Error::Image(_) => 70,
+        }
+    }
+}
 
 #[derive(Parser)]
 #[clap(author, version, about = None, long_about = None)]
 struct Opts {
     #[clap(short = 'v', long)]
     verbose: bool,
+    /// Log how long extraction spends parsing and writing, with the files and bytes of each.
+    #[clap(long)]
+    timings: bool,
+    /// Map inputs into memory instead of reading them into a buffer.
+    #[cfg(feature = "memmap2")]
+    #[clap(long)]
+    mmap: bool,
+    /// Threads to decode textures with. Defaults to one per core.
+    #[clap(long)]
+    jobs: Option<usize>,
+    #[clap(flatten)]
+    log: LogArgs,
     #[clap(subcommand)]
     subcommand: SubCommand,
 }
@@ -29,124 +130,1325 @@ enum SubCommand {
     Info {
         #[clap(parse(from_os_str))]
         input: PathBuf,
+        /// List every texture, mesh, global and script of a ppf instead of just counting them.
+        #[clap(long)]
+        long: bool,
+    },
+    /// Print the directories and files of a pkg, or the sections of a ppf, as an indented tree.
+    Tree {
+        #[clap(parse(from_os_str))]
+        input: PathBuf,
+        /// Levels below the input to print, everything by default.
+        #[clap(long)]
+        depth: Option<usize>,
+    },
+    /// Parse a pkg, ppf or tpf in full and report every problem found, without writing anything.
+    Validate {
+        #[clap(parse(from_os_str))]
+        input: PathBuf,
     },
     Extract {
         #[clap(parse(from_os_str))]
         input: PathBuf,
         #[clap(short = 'o', long, parse(from_os_str))]
         output: Option<PathBuf>,
+        /// Extract pkg files whose data lies outside the archive, cut short, instead of refusing to.
+        #[clap(long)]
+        salvage: bool,
+        /// Inflate pkg files stored as zlib streams. Nothing in an archive marks them, so this is off by default to
+        /// keep any file that only looks like one as it is.
+        #[clap(long)]
+        inflate: bool,
+        /// Write a JSON manifest of each extracted file's CRC32 and size.
+        #[clap(long, parse(from_os_str))]
+        manifest: Option<PathBuf>,
+        /// Write scripts as decompiled Lua, keeping the bytecode of any that fail.
+        #[clap(long)]
+        decompile: bool,
+        /// Also write the globals of an lpf or ppf, each at its path below `globals/`.
+        #[clap(long)]
+        include_globals: bool,
+        /// Rename files whose path is already taken instead of refusing to extract.
+        #[clap(long)]
+        dedupe: bool,
+        /// Language whose textures to extract from a tpf, by name or number. Defaults to English. Rejected for
+        /// other inputs, which have no languages.
+        #[clap(long, parse(try_from_str = parse_language))]
+        language: Option<LanguageId>,
+        /// DDS headers to give tpf textures: `dds` follows the spec, `game` writes L8 as A8 and R8G8B8 as
+        /// X8R8G8B8 like parts of the game's own loader.
+        #[clap(long, parse(try_from_str = parse_compat), default_value = "dds")]
+        compat: DdsCompat,
+        /// Row pitch of uncompressed DDS textures: `byte` packs rows tightly like the game's own files, `dword`
+        /// pads each row to four bytes for tools that expect it, such as 17 pixel wide R8G8B8 textures.
+        #[clap(long, parse(try_from_str = parse_pitch_alignment), default_value = "byte")]
+        pitch_alignment: PitchAlignment,
+        /// Extract the textures of every language of a tpf, each below `lang/<language>/`.
+        #[clap(long, conflicts_with = "language")]
+        all_languages: bool,
+        /// Log every file that would be written, without touching the disk.
+        #[clap(long)]
+        dry_run: bool,
+        /// Extract into a non-empty output directory, overwriting existing files.
+        #[clap(long)]
+        force: bool,
+        /// Extract into a non-empty output directory, skipping existing files.
+        #[clap(long, conflicts_with = "force")]
+        merge: bool,
+        /// Write animated textures as `<path>_0000.dds` onwards, with their playback settings in `<path>.json`.
+        #[clap(long)]
+        sequence: bool,
+        /// Format to write tpf textures as, `dds`, `png` or `raw`. PNGs hold only the top mip level of the first face.
+        /// Raw files hold the texture data exactly as stored, with its metadata and palette in a `.json` beside it.
+        #[clap(long, parse(try_from_str = parse_format), default_value = "dds")]
+        format: ExportFormat,
     },
     Split {
         #[clap(parse(from_os_str))]
         input: PathBuf,
         #[clap(short = 'o', long, parse(from_os_str))]
         output: Option<PathBuf>,
+        /// Skip the level, which is usually the largest piece.
+        #[clap(long)]
+        strip_level: bool,
+        /// Write only the piece with this extension.
+        #[clap(long, possible_values = &["tpf", "mpf", "lpf", "plb"], conflicts_with = "strip-level")]
+        only: Option<String>,
+    },
+    /// Join the pieces `split` writes below `input` back into a ppf, named after `output`.
+    Join {
+        #[clap(parse(from_os_str))]
+        input: PathBuf,
+        #[clap(short = 'o', long, parse(from_os_str))]
+        output: PathBuf,
+        /// Read the ppf back after writing it and check every section against its piece.
+        #[clap(long)]
+        verify: bool,
+    },
+    /// Swap one texture in a ppf for the contents of a DDS file.
+    Replace {
+        #[clap(parse(from_os_str))]
+        input: PathBuf,
+        /// Path of the texture to replace, as stored in the ppf.
+        #[clap(long)]
+        texture: String,
+        /// Animation frame to replace.
+        #[clap(long, default_value_t = 0)]
+        frame: usize,
+        #[clap(long, parse(from_os_str))]
+        dds: PathBuf,
+        #[clap(short = 'o', long, parse(from_os_str))]
+        output: PathBuf,
+    },
+    /// Pack every file below `input` into a pkg, in a stable order so the same tree always gives the same archive.
+    Pack {
+        #[clap(parse(from_os_str))]
+        input: PathBuf,
+        #[clap(short = 'o', long, parse(from_os_str))]
+        output: PathBuf,
+    },
+    /// Convert a single texture from a DDS file to a tpf or back, in the direction the extensions give.
+    Convert {
+        #[clap(parse(from_os_str))]
+        input: PathBuf,
+        #[clap(parse(from_os_str))]
+        output: PathBuf,
+        /// Path to store the texture under in a tpf. Defaults to the DDS file's name without its extension.
+        #[clap(long)]
+        path: Option<String>,
     },
 }
 
-trait DdsHeader {
-    fn dds_header(&self) -> Result<Vec<u8>, BoxError>;
-}
-
-impl DdsHeader for Texture {
-    fn dds_header(&self) -> Result<Vec<u8>, BoxError> {
-        let mut header: dds::Header = dds::Header {
-            height: self.height as u32,
-            width: self.width as u32,
-            depth: 1,
-            mip_map_count: self.mipmaps as u32,
-            pixel_format: match self.format {
-                TextureFormat::A8R8G8B8 => PixelFormat::A8R8G8B8,
-                TextureFormat::R8G8B8 => PixelFormat::R8G8B8, // FIXME: OpenGL types point to X8R8G8B8, but LoadTextureFromDDSStream points to R8G8B8
-                TextureFormat::A4R4G4B4 => PixelFormat::A4R4G4B4,
-                TextureFormat::A1R5G5B5 => PixelFormat::A1R5G5B5,
-                TextureFormat::X1R5G5B5 => PixelFormat::X1R5G5B5,
-                TextureFormat::R5G6B5 => PixelFormat::R5G6B5,
-                TextureFormat::A8 => PixelFormat::A8,
-                TextureFormat::L8 => PixelFormat::L8, // FIXME: LoadTextureFromDDSStream loads this from A8 dds header.
-                TextureFormat::AL8 => unimplemented!(), // FIXME: Possibly A8L8_ALT or A4L4.
-                TextureFormat::DXT1 => PixelFormat::DXT1,
-                TextureFormat::DXT3 => PixelFormat::DXT3,
-                TextureFormat::DXT5 => PixelFormat::DXT5,
-                TextureFormat::V8U8 => PixelFormat::V8U8,
-                TextureFormat::V16U16 => PixelFormat::V16U16,
-                TextureFormat::PAL8 => PixelFormat::from_tuple((dds::PAL8, 0, 0, 0, 0, 0)), // FIXME: This is wrong, according to the game generated PAL8 textures.
-            },
-            ..Default::default()
-        };
+fn read_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, Error> {
+    let file = File::open(path.as_ref())?;
+    let metadata = file.metadata()?;
+    let mut reader = BufReader::new(file);
 
-        if self.mipmaps > 1 {
-            header.header_flags.insert(dds::HEADER_FLAGS_MIPMAP);
-            header.surface_flags.insert(dds::SURFACE_FLAGS_MIPMAP);
+    let mut buffer = Vec::with_capacity(metadata.len() as usize);
+    reader.read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+#[cfg(feature = "memmap2")]
+fn map_file(path: &Path) -> Option<memmap2::Mmap> {
+    let file = File::open(path).ok()?;
+    // SAFETY: The map is read-only and never outlives the parse, but the file could still be changed under us.
+    match unsafe { memmap2::Mmap::map(&file) } {
+        Ok(map) => Some(map),
+        Err(err) => {
+            log::warn!("Unable to map {:?}, falling back to buffered reads: {}", path, err);
+            None
         }
+    }
+}
 
-        if self.type_ == TextureType::Cubemap {
-            header.surface_flags.insert(dds::SURFACE_FLAGS_CUBEMAP);
-            header.caps2 = dds::CUBEMAP_ALLFACES;
+fn read_pkg<P: AsRef<Path>>(path: P, mmap: bool) -> Result<Zpkg, Error> {
+    if mmap {
+        #[cfg(feature = "memmap2")]
+        if let Some(map) = map_file(path.as_ref()) {
+            return Ok(Zpkg::from_slice(&map)?);
         }
+    }
 
-        header.pitch_or_linear_size = if self.format.compressed() {
-            header.header_flags.insert(dds::HEADER_FLAGS_LINEARSIZE);
-            let (width, height) = (self.width as u32, self.height as u32);
-            ((width + 3) >> 2).max(1) * ((height + 3) >> 2).max(1) * self.format.block_size() as u32
-        } else {
-            header.header_flags.insert(dds::HEADER_FLAGS_PITCH);
-            (self.width as u32 * header.pixel_format.rgb_bit_count + 7) / 8
+    let data = read_file(path)?;
+    Ok(Zpkg::from_slice(&data)?)
+}
+
+fn read_ppf<P: AsRef<Path>>(path: P, mmap: bool) -> Result<Ppf, Error> {
+    if mmap {
+        #[cfg(feature = "memmap2")]
+        if let Some(map) = map_file(path.as_ref()) {
+            return Ok(Ppf::from_reader(&mut std::io::Cursor::new(&map[..]))?);
+        }
+    }
+
+    let mut reader = BufReader::new(File::open(path)?);
+    Ok(Ppf::from_reader(&mut reader)?)
+}
+
+fn read_lpf<P: AsRef<Path>>(path: P) -> Result<LuaPackFile, Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    LuaPackFile::read(&mut reader).map_err(Error::Lpf)
+}
+
+#[derive(Clone, Copy)]
+struct WriteOptions<'a> {
+    dry_run: bool,
+    force: bool,
+    compat: DdsCompat,
+    pitch_alignment: PitchAlignment,
+    sequence: bool,
+    format: ExportFormat,
+    written: &'a Written,
+}
+
+/// Files and bytes written so far by one extraction, for `--timings`.
+#[derive(Default)]
+struct Written {
+    files: AtomicUsize,
+    bytes: AtomicUsize,
+}
+
+impl Written {
+    fn count(&self, bytes: usize) {
+        self.files.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+}
+
+/// Logs the time each phase of a command took, when `--timings` asks for it.
+struct Timings {
+    enabled: bool,
+    last: Instant,
+}
+
+impl Timings {
+    fn new(enabled: bool) -> Timings {
+        Timings {
+            enabled,
+            last: Instant::now(),
+        }
+    }
+
+    /// Ends the phase that started when the last one ended.
+    fn phase(&mut self, name: &str, files: usize, bytes: usize) {
+        let now = Instant::now();
+        if self.enabled {
+            log::info!(
+                "{} took {:.3?} ({} files, {} bytes)",
+                name,
+                now - self.last,
+                files,
+                bytes
+            );
+        }
+        self.last = now;
+    }
+}
+
+/// Writes `data` to `path`, creating its directory, or only logs it for a dry run. Existing files are skipped
+/// unless forced.
+fn write_output(path: &Path, data: &[u8], options: WriteOptions) -> Result<(), Error> {
+    if !options.force && path.exists() {
+        log::warn!("skipping existing {:?}", path);
+        return Ok(());
+    }
+    if options.dry_run {
+        log::info!("would write {:?} ({} bytes)", path, data.len());
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    log::info!("writing {:?}", path);
+    std::fs::write(path, data)?;
+    options.written.count(data.len());
+    Ok(())
+}
+
+/// `path`, as an archive names an entry, with `\` read as `/` and no leading separator, ready to be joined onto an
+/// output directory. Fails for a path with anything but plain names in it, like `..` or a drive, that could lead
+/// outside of that directory.
+fn relative_output_path(path: &str) -> Result<String, Error> {
+    let relative = normalize_path(path);
+    if Path::new(&relative)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        Ok(relative)
+    } else {
+        Err(Error::UnsafePath(path.to_string()))
+    }
+}
+
+/// Writes each script below `output` as `.luac` bytecode, or as `.lua` source when `decompile` succeeds.
+/// Scripts without a path are named after their index.
+fn extract_scripts(scripts: &LuaPackFile, output: &Path, decompile: bool, options: WriteOptions) -> Result<(), Error> {
+    for (index, script) in scripts.scripts.iter().enumerate() {
+        let (path, data) = match script {
+            Script::V0(script) => (PathBuf::from(format!("script_{:04}", index)), &script.data),
+            Script::V1(script) => (PathBuf::from(relative_output_path(&script.path)?), &script.script.data),
         };
+        let path = output.join(path);
 
-        Ok(bincode::serialize(&header)?)
+        let source = match decompile.then(|| luadec::decompile(data)) {
+            Some(Ok(source)) => Some(source),
+            Some(Err(err)) => {
+                log::warn!("unable to decompile {:?}, keeping the bytecode: {}", path, err);
+                None
+            }
+            None => None,
+        };
+        let (path, data) = match &source {
+            Some(source) => (path.with_extension("lua"), source.as_bytes()),
+            None => (path.with_extension("luac"), &data[..]),
+        };
+        write_output(&path, data, options)?;
     }
+    Ok(())
 }
 
-fn read_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, BoxError> {
-    let file = File::open(path.as_ref())?;
-    let metadata = file.metadata()?;
-    let mut reader = BufReader::new(file);
+/// Writes each global below `output/globals` at its path, as it is stored.
+fn extract_globals(scripts: &LuaPackFile, output: &Path, options: WriteOptions) -> Result<(), Error> {
+    let output = output.join("globals");
+    for global in &scripts.globals {
+        write_output(&output.join(relative_output_path(&global.path)?), &global.data, options)?;
+    }
+    Ok(())
+}
 
-    let mut buffer = Vec::with_capacity(metadata.len() as usize);
-    reader.read_to_end(&mut buffer)?;
-    Ok(buffer)
+fn parse_language(value: &str) -> Result<LanguageId, String> {
+    LanguageId::ALL
+        .iter()
+        .copied()
+        .find(|&language| value.eq_ignore_ascii_case(language.code()) || value == (language as u16).to_string())
+        .ok_or_else(|| format!("unknown language {:?}", value))
+}
+
+fn parse_compat(value: &str) -> Result<DdsCompat, String> {
+    match value {
+        "dds" => Ok(DdsCompat::Dds),
+        "game" => Ok(DdsCompat::Game),
+        _ => Err(format!("unknown compat {:?}, expected dds or game", value)),
+    }
+}
+
+fn parse_pitch_alignment(value: &str) -> Result<PitchAlignment, String> {
+    match value {
+        "byte" => Ok(PitchAlignment::Byte),
+        "dword" => Ok(PitchAlignment::Dword),
+        _ => Err(format!("unknown pitch alignment {:?}, expected byte or dword", value)),
+    }
+}
+
+/// What `extract` writes textures as.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    Dds,
+    Png,
+    Raw,
+}
+
+fn parse_format(value: &str) -> Result<ExportFormat, String> {
+    match value {
+        "dds" => Ok(ExportFormat::Dds),
+        "png" => Ok(ExportFormat::Png),
+        "raw" => Ok(ExportFormat::Raw),
+        _ => Err(format!("unknown format {:?}, expected dds, png or raw", value)),
+    }
+}
+
+fn read_tpf<P: AsRef<Path>>(path: P) -> Result<TexturePackFile, Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    Ok(TexturePackFile::from_reader(&mut reader)?)
+}
+
+/// Playback settings of an animated texture, written next to its frames by `extract --sequence`.
+#[derive(Serialize)]
+struct AnimationSidecar {
+    frame_count: u32,
+    frame_rate: f32,
+    play_mode: String,
+    start_frame: f32,
+    loop_frame: f32,
+}
+
+/// What `extract --format raw` writes next to each texture's data, enough to rebuild it with `Texture::from_raw`.
+#[derive(Serialize)]
+struct RawSidecar<'a> {
+    #[serde(flatten)]
+    metadata: TextureMetadata,
+    palette: Option<&'a [u32]>,
+}
+
+/// Encodes a texture as the file `extract` writes for it, decoding PNGs through `cache` under the texture's `handle`.
+fn encode_texture<'a>(
+    handle: u32,
+    texture: &'a Texture,
+    cache: &mut DecodeCache<'a>,
+    options: WriteOptions,
+) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+    match options.format {
+        ExportFormat::Dds => texture
+            .to_dds_aligned(options.compat, options.pitch_alignment)?
+            .write(&mut data)?,
+        ExportFormat::Png => {
+            let rgba = cache.get_or_decode(handle, texture)?;
+            PngEncoder::new(&mut data).write_image(
+                rgba,
+                texture.width as u32,
+                texture.height as u32,
+                ColorType::Rgba8,
+            )?;
+        }
+        ExportFormat::Raw => data.extend_from_slice(&texture.data),
+    }
+    Ok(data)
+}
+
+/// Names a texture without a path after where it is and the ids the game gives it, so extracting the same file
+/// again writes the same files: `texture_<index>_<element id>_<texture handle>`, with the language after `texture`
+/// for textures of one language. `index` counts the game textures of the list the texture is in, from zero.
+fn unnamed_texture_name(game_texture: &GameTextureV0, index: usize, language: Option<LanguageId>) -> String {
+    let language = language
+        .map(|language| format!("_{}", language.code()))
+        .unwrap_or_default();
+    format!(
+        "texture{}_{}_{}_{}",
+        language, index, game_texture.element_id, game_texture.texture_handle
+    )
+}
+
+/// Writes every frame of each texture below `output` as a DDS, PNG or raw file, numbering animation frames.
+///
+/// Textures are encoded in parallel, then written in order, so a path that's used twice ends up the same as it
+/// would extracting one texture at a time. Each rayon job decodes PNGs through a [`DecodeCache`] of its own, so
+/// a texture repeated within it is only decoded once.
+fn extract_textures(
+    game_textures: &[GameTexture],
+    language: Option<LanguageId>,
+    output: &Path,
+    options: WriteOptions,
+) -> Result<(), Error> {
+    let game_textures = game_textures.iter().map(GameTexture::inner).enumerate();
+    let extension = match options.format {
+        ExportFormat::Dds => "dds",
+        ExportFormat::Png => "png",
+        ExportFormat::Raw => "raw",
+    };
+
+    let mut frames_to_write = Vec::new();
+    for (index, game_texture) in game_textures {
+        let name = match game_texture.path.as_deref() {
+            Some(path) => relative_output_path(path)?,
+            None => {
+                let name = unnamed_texture_name(game_texture, index, language);
+                log::warn!("writing a texture without a path as {}", name);
+                name
+            }
+        };
+
+        let animation_info = game_texture.animation_info.as_ref().filter(|_| options.sequence);
+        let frames = match animation_info {
+            Some(animation_info) => {
+                let sidecar = AnimationSidecar {
+                    frame_count: animation_info.frame_count,
+                    frame_rate: animation_info.frame_rate,
+                    play_mode: animation_info.play_mode.to_string(),
+                    start_frame: animation_info.start_frame,
+                    loop_frame: animation_info.loop_frame,
+                };
+                let data = serde_json::to_vec_pretty(&sidecar).map_err(std::io::Error::from)?;
+                write_output(&output.join(format!("{}.json", name)), &data, options)?;
+                animation_info.frame_count as usize
+            }
+            None => game_texture.textures.len(),
+        };
+        if frames != game_texture.textures.len() {
+            log::warn!(
+                "{} has {} frames but {} textures",
+                name,
+                frames,
+                game_texture.textures.len()
+            );
+        }
+
+        for (frame, texture) in game_texture.textures.iter().take(frames).enumerate() {
+            let path = match (animation_info, frames) {
+                (Some(_), _) => output.join(format!("{}_{:04}.{}", name, frame, extension)),
+                (None, 1) => output.join(format!("{}.{}", name, extension)),
+                (None, _) => output.join(format!("{}_{}.{}", name, frame, extension)),
+            };
+            frames_to_write.push((path, game_texture.texture_handle, texture));
+        }
+    }
+
+    let encoded = frames_to_write
+        .par_iter()
+        .map_init(DecodeCache::new, |cache, &(_, handle, texture)| {
+            encode_texture(handle, texture, cache, options)
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    for ((path, _, texture), data) in frames_to_write.iter().zip(encoded) {
+        write_output(path, &data, options)?;
+        if options.format == ExportFormat::Raw {
+            let sidecar = RawSidecar {
+                metadata: texture.metadata(),
+                palette: texture
+                    .palette
+                    .as_ref()
+                    .and_then(|palette| palette.data.as_ref())
+                    .map(|data| &data[..]),
+            };
+            let sidecar = serde_json::to_vec_pretty(&sidecar).map_err(std::io::Error::from)?;
+            write_output(&path.with_extension("json"), &sidecar, options)?;
+        }
+    }
+    Ok(())
 }
 
-fn write_file<P: AsRef<Path>>(path: P, data: &[u8]) -> Result<(), BoxError> {
+/// Extracts the language independent textures, plus those of `language` or of every language.
+fn extract_tpf(
+    tpf: &TexturePackFile,
+    output: &Path,
+    language: LanguageId,
+    all_languages: bool,
+    options: WriteOptions,
+) -> Result<(), Error> {
+    extract_textures(&tpf.game_textures, None, output, options)?;
+    if all_languages {
+        for language in &tpf.languages {
+            let output = output.join("lang").join(language.id.code());
+            extract_textures(&language.game_textures, Some(language.id), &output, options)?;
+        }
+    } else if !tpf.languages.is_empty() {
+        let selected =
+            tpf.languages
+                .iter()
+                .find(|other| other.id == language)
+                .ok_or_else(|| Error::LanguageNotFound {
+                    language,
+                    available: tpf.languages_present(),
+                })?;
+        extract_textures(&selected.game_textures, Some(language), output, options)?;
+    }
+    Ok(())
+}
+
+fn read_dds<P: AsRef<Path>>(path: P) -> Result<Dds, Error> {
     let path = path.as_ref();
-    if let Some(parent) = path.parent() {
+    let mut reader = BufReader::new(File::open(path)?);
+    Dds::read(&mut reader).map_err(|err| match err {
+        dds::Error::Io(err) if err.kind() != ErrorKind::UnexpectedEof => Error::Io(err),
+        _ => Error::InvalidDds(path.to_path_buf()),
+    })
+}
+
+fn dds_info(dds: &Dds) -> Result<(), Error> {
+    let header = &dds.header;
+    log::info!("dimensions = {}x{}", header.width, header.height);
+    if header.is_volume() {
+        log::info!("depth = {}", header.depth);
+    }
+    match header.pixel_format.name() {
+        Some(name) => log::info!("format = {}", name),
+        None => log::info!("format = {:?}", header.pixel_format),
+    }
+    log::info!("mipmaps = {}", header.mip_map_count.max(1));
+    log::info!("caps = {}", header.caps());
+    log::info!("cubemap = {}", header.is_cubemap());
+    log::info!("volume = {}", header.is_volume());
+    log::info!("dx10 = {}", header.is_dx10());
+    if let Some(header10) = &dds.header10 {
+        log::info!("{:#?}", header10);
+    }
+
+    // Only the layouts a tpf can hold are known well enough to check the data length.
+    match Texture::from_dds(header, &dds.data) {
+        Ok(_) => log::info!("data = {} bytes", dds.data.len()),
+        Err(TextureError::UnsupportedPixelFormat) => log::warn!("unable to validate the data length of this format"),
+        Err(err) => return Err(err.into()),
+    }
+    Ok(())
+}
+
+/// Checks for colliding paths like [`Zpkg::extract_to`], then logs what it would write.
+fn extract_pkg_dry_run(zpkg: &Zpkg, output: &Path, options: WriteOptions) -> Result<(), Error> {
+    let duplicates = zpkg.duplicate_paths();
+    if !duplicates.is_empty() {
+        let duplicates = duplicates.into_iter().map(String::from).collect();
+        return Err(pkg::Error::DuplicatePaths(duplicates).into());
+    }
+
+    for file in &zpkg.files {
+        write_output(&output.join(file.relative_path()), &file.data, options)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "indicatif")]
+fn extract_pkg(zpkg: &Zpkg, output: &Path, written: &Written) -> Result<(), Error> {
+    let total_bytes = zpkg.files.iter().map(|file| file.data.len() as u64).sum();
+    let bar = indicatif::ProgressBar::new(total_bytes).with_style(
+        indicatif::ProgressStyle::with_template("{bar:40} {bytes}/{total_bytes} ({msg} files)")
+            .expect("valid progress template"),
+    );
+    let mut bytes = 0;
+    zpkg.extract_to_with_progress(output, |progress| {
+        written.count(progress.bytes - bytes);
+        bytes = progress.bytes;
+        bar.set_message(format!("{}/{}", progress.files, progress.total_files));
+        bar.set_position(progress.bytes as u64);
+    })?;
+    bar.finish_and_clear();
+    Ok(())
+}
+
+#[cfg(not(feature = "indicatif"))]
+fn extract_pkg(zpkg: &Zpkg, output: &Path, written: &Written) -> Result<(), Error> {
+    let mut bytes = 0;
+    zpkg.extract_to_with_progress(output, |progress| {
+        written.count(progress.bytes - bytes);
+        bytes = progress.bytes;
+    })?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ManifestEntry {
+    crc32: u32,
+    size: usize,
+}
+
+/// Every file of `zpkg` keyed by its path below the output directory.
+fn manifest(zpkg: &Zpkg) -> BTreeMap<String, ManifestEntry> {
+    zpkg.files
+        .iter()
+        .map(|file| {
+            let entry = ManifestEntry {
+                crc32: crc32fast::hash(&file.data),
+                size: file.data.len(),
+            };
+            (file.relative_path().to_string(), entry)
+        })
+        .collect()
+}
+
+fn write_manifest<P: AsRef<Path>>(path: P, manifest: &BTreeMap<String, ManifestEntry>) -> Result<(), Error> {
+    let writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(writer, &manifest).map_err(std::io::Error::from)?;
+    Ok(())
+}
+
+fn ppf_info(summary: &PpfSummary, long: bool) {
+    log::info!("textures = {}", summary.textures.len());
+    if long {
+        for texture in &summary.textures {
+            let path = texture.path.as_deref().unwrap_or("<unnamed>");
+            let language = texture
+                .language
+                .map(|language| format!(" ({})", language.name()))
+                .unwrap_or_default();
+            for (frame, metadata) in texture.frames.iter().enumerate() {
+                log::info!(
+                    "  {}{} (frame {}): {} {}x{}, {} mipmaps",
+                    path,
+                    language,
+                    frame,
+                    metadata.format,
+                    metadata.width,
+                    metadata.height,
+                    metadata.mipmaps
+                );
+            }
+        }
+    }
+
+    let entries = [
+        ("meshes", &summary.meshes),
+        ("globals", &summary.globals),
+        ("scripts", &summary.scripts),
+    ];
+    for (name, entries) in entries.iter().copied() {
+        log::info!("{} = {}", name, entries.len());
+        if long {
+            for entry in entries {
+                log::info!(
+                    "  {} ({} bytes)",
+                    entry.path.as_deref().unwrap_or("<unnamed>"),
+                    entry.size
+                );
+            }
+        }
+    }
+    log::info!("level = {} bytes", summary.level_size);
+}
+
+/// What [`Texture::validate`] finds in every texture, and with `exportable` the textures that can't be given a DDS
+/// header, each prefixed with the texture's path and frame.
+fn texture_problems(textures: &TexturePackFile, exportable: bool) -> Vec<String> {
+    let game_textures = textures
+        .game_textures
+        .iter()
+        .chain(textures.languages.iter().flat_map(|language| &language.game_textures))
+        .map(GameTexture::inner);
+
+    let mut problems = Vec::new();
+    for game_texture in game_textures {
+        let path = game_texture.path.as_deref().unwrap_or("<unnamed>");
+        for (frame, texture) in game_texture.textures.iter().enumerate() {
+            for warning in texture.validate().err().into_iter().flatten() {
+                problems.push(format!("{} (frame {}): {}", path, frame, warning));
+            }
+            if let Some(err) = texture.dds_header().err().filter(|_| exportable) {
+                problems.push(format!("{} (frame {}): {}", path, frame, err));
+            }
+        }
+    }
+    problems
+}
+
+fn warn_textures(ppf: &Ppf) {
+    for problem in texture_problems(&ppf.textures, false) {
+        log::warn!("{}", problem);
+    }
+}
+
+fn mesh_problems(meshes: &MeshPackFile) -> Vec<String> {
+    meshes
+        .duplicate_paths()
+        .into_iter()
+        .map(|path| format!("More than one mesh has the path {:?}.", path))
+        .collect()
+}
+
+fn warn_meshes(ppf: &Ppf) {
+    for problem in mesh_problems(&ppf.meshes) {
+        log::warn!("{}", problem);
+    }
+}
+
+/// The section, directory and extension of each piece `split` writes a ppf as.
+const PIECES: [(&str, &str, &str); 4] = [
+    ("textures", "pcpackfiles", "tpf"),
+    ("meshes", "packfiles", "mpf"),
+    ("scripts", "scripts/packfiles", "lpf"),
+    ("level", "levels", "plb"),
+];
+
+/// Reads `output` back and compares the CRC32 of each of its sections with that of the piece it was joined from.
+fn verify_join(output: &Path, pieces: &[Vec<u8>]) -> Result<(), Error> {
+    let data = read_file(output)?;
+    if !data.starts_with(b"PPAK") {
+        return Err(Error::JoinMismatch("magic"));
+    }
+
+    let mut reader = std::io::Cursor::new(&data[..]);
+    reader.set_position(4);
+    for ((section, _, _), piece) in PIECES.iter().zip(pieces) {
+        let start = reader.position() as usize;
+        match *section {
+            "textures" => {
+                TexturePackFile::from_reader(&mut reader)?;
+            }
+            "meshes" => {
+                MeshPackFile::read(&mut reader).map_err(ppf::Error::from)?;
+            }
+            "scripts" => {
+                LuaPackFile::read(&mut reader).map_err(Error::Lpf)?;
+            }
+            _ => reader.set_position(data.len() as u64),
+        }
+        let end = reader.position() as usize;
+        if crc32fast::hash(&data[start..end]) != crc32fast::hash(piece) || end - start != piece.len() {
+            return Err(Error::JoinMismatch(section));
+        }
+        log::info!("verified {} ({} bytes)", section, piece.len());
+    }
+    Ok(())
+}
+
+/// Wraps the texture of a DDS file in a tpf of its own, or writes the only texture of a tpf as a DDS file.
+fn convert(input: &Path, output: &Path, path: Option<String>) -> Result<(), Error> {
+    let extension = |path: &Path| path.extension().and_then(OsStr::to_str).map(str::to_ascii_lowercase);
+    let data = match (extension(input).as_deref(), extension(output).as_deref()) {
+        (Some("dds"), Some("tpf")) => {
+            let dds = read_dds(input)?;
+            let texture = Texture::from_dds(&dds.header, &dds.data)?;
+            let path = path.unwrap_or_else(|| {
+                input
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            });
+            let tpf = TexturePackFile {
+                version: None,
+                languages: vec![],
+                game_textures: vec![GameTexture::V0(GameTextureV0 {
+                    element_id: 0,
+                    texture_handle: 1,
+                    palette_handle: 0,
+                    path_pointer: 1,
+                    animation_info_pointer: 0,
+                    density: 1.0,
+                    visual_importance: 0,
+                    memory_importance: 0,
+                    unknown0: 0,
+                    flags: GameTextureFlags::empty(),
+                    path: Some(ppf::Path::new(path)),
+                    animation_info: None,
+                    textures: vec![texture],
+                })],
+            };
+            let mut writer = std::io::Cursor::new(Vec::new());
+            tpf.write_le(&mut writer)?;
+            writer.into_inner()
+        }
+        (Some("tpf"), Some("dds")) => {
+            let tpf = read_tpf(input)?;
+            let mut textures = tpf
+                .game_textures
+                .iter()
+                .chain(tpf.languages.iter().flat_map(|language| &language.game_textures))
+                .flat_map(|game_texture| &game_texture.inner().textures);
+            let texture = match (textures.next(), textures.count()) {
+                (Some(texture), 0) => texture,
+                (first, rest) => {
+                    return Err(Error::NotSingleTexture {
+                        path: input.to_path_buf(),
+                        count: first.map_or(0, |_| 1 + rest),
+                    })
+                }
+            };
+            let mut data = Vec::new();
+            texture.to_dds()?.write(&mut data)?;
+            data
+        }
+        (Some("dds"), _) | (Some("tpf"), _) => return Err(Error::Unsupported(output.to_path_buf())),
+        _ => return Err(Error::Unsupported(input.to_path_buf())),
+    };
+
+    if let Some(parent) = output.parent() {
         std::fs::create_dir_all(parent)?;
     }
+    std::fs::write(output, data)?;
+    Ok(())
+}
+
+fn replace_texture(ppf: &mut Ppf, path: &str, frame: usize, mut replacement: Texture) -> Result<(), Error> {
+    let incompatible = |reason: String| Error::Incompatible {
+        path: path.to_string(),
+        reason,
+    };
+
+    let textures = &mut ppf.textures;
+    let game_texture = textures
+        .game_textures
+        .iter_mut()
+        .chain(
+            textures
+                .languages
+                .iter_mut()
+                .flat_map(|language| language.game_textures.iter_mut()),
+        )
+        .map(GameTexture::inner_mut)
+        .find(|game_texture| {
+            game_texture
+                .path
+                .as_deref()
+                .is_some_and(|texture_path| path_eq(texture_path, path))
+        })
+        .ok_or_else(|| Error::TextureNotFound(path.to_string()))?;
+
+    let frames = game_texture.textures.len();
+    if frame >= frames {
+        return Err(incompatible(format!(
+            "frame {} is out of range, it has {}",
+            frame, frames
+        )));
+    }
+
+    let existing = &game_texture.textures[frame];
+    if (existing.format, existing.type_) != (replacement.format, replacement.type_) {
+        return Err(incompatible(format!(
+            "expected a {} {}, found a {} {}",
+            existing.format, existing.type_, replacement.format, replacement.type_
+        )));
+    }
+    // Animation frames share a single size.
+    if frames > 1 && (existing.width, existing.height) != (replacement.width, replacement.height) {
+        return Err(incompatible(format!(
+            "animation frames are {}x{}, found {}x{}",
+            existing.width, existing.height, replacement.width, replacement.height
+        )));
+    }
+
+    replacement.flags = existing.flags;
+    game_texture.textures[frame] = replacement;
+    Ok(())
+}
+
+/// What `info` needs from the command line.
+struct InfoArgs {
+    mmap: bool,
+    long: bool,
+}
+
+/// An entry of the listing `tree` prints.
+struct TreeNode {
+    name: String,
+    children: Vec<TreeNode>,
+}
+
+impl TreeNode {
+    fn leaf(name: impl Into<String>) -> TreeNode {
+        TreeNode {
+            name: name.into(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Writes the children of this node and up to `depth` levels below them, each child drawn under `prefix`.
+    fn write<W: Write>(&self, writer: &mut W, prefix: &str, depth: Option<usize>) -> std::io::Result<()> {
+        if depth == Some(0) {
+            return Ok(());
+        }
+        for (index, child) in self.children.iter().enumerate() {
+            let last = index + 1 == self.children.len();
+            writeln!(writer, "{}{}{}", prefix, if last { "`-- " } else { "|-- " }, child.name)?;
+            child.write(
+                writer,
+                &format!("{}{}", prefix, if last { "    " } else { "|   " }),
+                depth.map(|depth| depth - 1),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// What `tree` and `validate`, which only read their input, need from the command line.
+struct ReadArgs {
+    mmap: bool,
+}
+
+/// What `extract` needs from the command line, with the output directory already resolved.
+struct ExtractArgs<'a> {
+    mmap: bool,
+    output: PathBuf,
+    salvage: bool,
+    inflate: bool,
+    manifest: Option<PathBuf>,
+    decompile: bool,
+    include_globals: bool,
+    dedupe: bool,
+    language: LanguageId,
+    all_languages: bool,
+    options: WriteOptions<'a>,
+    timings: Timings,
+    input_size: usize,
+}
+
+/// What `split` needs from the command line.
+struct SplitArgs {
+    mmap: bool,
+    output: PathBuf,
+    strip_level: bool,
+    only: Option<String>,
+}
+
+type InfoFn = fn(&Path, &InfoArgs) -> Result<(), Error>;
+type TreeFn = fn(&Path, &ReadArgs) -> Result<TreeNode, Error>;
+type ValidateFn = fn(&Path, &ReadArgs) -> Result<Vec<String>, Error>;
+type ExtractFn = fn(&Path, &mut ExtractArgs) -> Result<(), Error>;
+type SplitFn = fn(&Path, &SplitArgs) -> Result<(), Error>;
+
+/// What the subcommands can do with files of one extension. Supporting another kind of file, or another command
+/// for one, only takes an entry in [`HANDLERS`].
+struct Handler {
+    extension: &'static str,
+    info: Option<InfoFn>,
+    tree: Option<TreeFn>,
+    validate: Option<ValidateFn>,
+    extract: Option<ExtractFn>,
+    split: Option<SplitFn>,
+}
+
+const HANDLERS: &[Handler] = &[
+    Handler {
+        extension: "pkg",
+        info: Some(info_pkg),
+        tree: Some(tree_pkg),
+        validate: Some(validate_pkg),
+        extract: Some(extract_from_pkg),
+        split: None,
+    },
+    Handler {
+        extension: "ppf",
+        info: Some(info_ppf),
+        tree: Some(tree_ppf),
+        validate: Some(validate_ppf),
+        extract: Some(extract_from_ppf),
+        split: Some(split_ppf),
+    },
+    Handler {
+        extension: "lpf",
+        info: None,
+        tree: None,
+        validate: None,
+        extract: Some(extract_from_lpf),
+        split: None,
+    },
+    Handler {
+        extension: "tpf",
+        info: None,
+        tree: None,
+        validate: Some(validate_tpf),
+        extract: Some(extract_from_tpf),
+        split: None,
+    },
+    Handler {
+        extension: "dds",
+        info: Some(info_dds),
+        tree: None,
+        validate: None,
+        extract: None,
+        split: None,
+    },
+];
+
+/// Finds what `command` picks out of the handler for `input`'s extension, or lists the extensions that have one.
+fn handler<T>(input: &Path, command: fn(&Handler) -> Option<T>) -> Result<T, Error> {
+    let extension = input.extension();
+    HANDLERS
+        .iter()
+        .filter(|handler| extension == Some(OsStr::new(handler.extension)))
+        .find_map(command)
+        .ok_or_else(|| Error::UnsupportedExtension {
+            path: input.to_path_buf(),
+            supported: HANDLERS
+                .iter()
+                .filter(|handler| command(handler).is_some())
+                .map(|handler| handler.extension)
+                .collect(),
+        })
+}
+
+fn info_pkg(input: &Path, args: &InfoArgs) -> Result<(), Error> {
+    let zpkg = read_pkg(input, args.mmap)?;
+    log::info!("{:#?}", zpkg);
+    Ok(())
+}
+
+fn info_ppf(input: &Path, args: &InfoArgs) -> Result<(), Error> {
+    let ppf = read_ppf(input, args.mmap)?;
+    ppf_info(&ppf.summary(), args.long);
+    warn_textures(&ppf);
+    Ok(())
+}
+
+fn info_dds(input: &Path, _: &InfoArgs) -> Result<(), Error> {
+    dds_info(&read_dds(input)?)
+}
+
+fn tree_pkg(input: &Path, args: &ReadArgs) -> Result<TreeNode, Error> {
+    fn directory_node(name: String, directory: &pkg::Directory<'_>) -> TreeNode {
+        let mut children: Vec<TreeNode> = directory
+            .directories
+            .iter()
+            .map(|(name, directory)| directory_node(format!("{}/", name), directory))
+            .collect();
+        children.extend(directory.files.iter().map(|file| TreeNode::leaf(file.file_name())));
+        TreeNode { name, children }
+    }
+
+    let zpkg = read_pkg(input, args.mmap)?;
+    Ok(directory_node(input.display().to_string(), &zpkg.tree()))
+}
+
+fn tree_ppf(input: &Path, args: &ReadArgs) -> Result<TreeNode, Error> {
+    let summary = read_ppf(input, args.mmap)?.summary();
+    let section = |name: &str, children: Vec<TreeNode>| TreeNode {
+        name: format!("{} ({})", name, children.len()),
+        children,
+    };
+    let entries = |entries: &[EntrySummary]| {
+        entries
+            .iter()
+            .map(|entry| TreeNode::leaf(entry.path.as_deref().unwrap_or("<unnamed>")))
+            .collect()
+    };
+
+    let textures = summary
+        .textures
+        .iter()
+        .map(|texture| {
+            let path = texture.path.as_deref().unwrap_or("<unnamed>");
+            match texture.language {
+                Some(language) => TreeNode::leaf(format!("{} ({})", path, language.name())),
+                None => TreeNode::leaf(path),
+            }
+        })
+        .collect();
+    Ok(TreeNode {
+        name: input.display().to_string(),
+        children: vec![
+            section("textures", textures),
+            section("meshes", entries(&summary.meshes)),
+            section("globals", entries(&summary.globals)),
+            section("scripts", entries(&summary.scripts)),
+            TreeNode::leaf(format!("level ({} bytes)", summary.level_size)),
+        ],
+    })
+}
+
+fn validate_pkg(input: &Path, args: &ReadArgs) -> Result<Vec<String>, Error> {
+    let zpkg = read_pkg(input, args.mmap)?;
+    let mut problems: Vec<String> = zpkg
+        .verify()
+        .err()
+        .into_iter()
+        .flatten()
+        .map(|err| err.to_string())
+        .collect();
+    let duplicates = zpkg.duplicate_paths();
+    if !duplicates.is_empty() {
+        let duplicates = duplicates.into_iter().map(str::to_string).collect();
+        problems.push(pkg::Error::DuplicatePaths(duplicates).to_string());
+    }
+    Ok(problems)
+}
+
+fn validate_ppf(input: &Path, args: &ReadArgs) -> Result<Vec<String>, Error> {
+    let ppf = read_ppf(input, args.mmap)?;
+    let mut problems = texture_problems(&ppf.textures, true);
+    problems.extend(mesh_problems(&ppf.meshes));
+    Ok(problems)
+}
+
+fn validate_tpf(input: &Path, _: &ReadArgs) -> Result<Vec<String>, Error> {
+    Ok(texture_problems(&read_tpf(input)?, true))
+}
+
+fn extract_from_pkg(input: &Path, args: &mut ExtractArgs) -> Result<(), Error> {
+    let mut zpkg = read_pkg(input, args.mmap)?;
+    args.timings.phase("parse", 1, args.input_size);
+    // Out of bounds data reads back cut short, so it is only extracted when asked for.
+    if let Err(errors) = zpkg.verify() {
+        if args.salvage {
+            for err in &errors {
+                log::warn!("salvaging: {}", err);
+            }
+        } else if args.options.dry_run {
+            for err in &errors {
+                log::warn!("would fail: {}", err);
+            }
+        } else {
+            for err in &errors {
+                log::error!("{}", err);
+            }
+            return Err(Error::Verify(errors));
+        }
+    }
+    if args.inflate {
+        log::info!("inflated {} files", zpkg.inflate());
+    }
+
+    if args.dedupe {
+        for path in zpkg.duplicate_paths() {
+            log::warn!("renaming duplicates of {:?}", path);
+        }
+        zpkg.dedupe_paths();
+    }
+
+    // Taken before existing files are skipped, so the manifest covers the whole archive whatever is written.
+    let manifest = args
+        .manifest
+        .as_ref()
+        .filter(|_| !args.options.dry_run)
+        .map(|path| (path, manifest(&zpkg)));
+
+    let output = &args.output;
+    if args.options.dry_run {
+        extract_pkg_dry_run(&zpkg, output, args.options)?;
+    } else {
+        if !args.options.force {
+            zpkg.files.retain(|file| {
+                let path = output.join(file.relative_path());
+                let exists = path.exists();
+                if exists {
+                    log::warn!("skipping existing {:?}", path);
+                }
+                !exists
+            });
+        }
+        extract_pkg(&zpkg, output, args.options.written)?;
+    }
+    if let Some((path, manifest)) = manifest {
+        log::info!("writing {:?}", path);
+        write_manifest(path, &manifest)?;
+    }
+    Ok(())
+}
+
+fn extract_from_ppf(input: &Path, args: &mut ExtractArgs) -> Result<(), Error> {
+    let ppf = read_ppf(input, args.mmap)?;
+    args.timings.phase("parse", 1, args.input_size);
+    warn_meshes(&ppf);
+    if args.include_globals {
+        extract_globals(&ppf.scripts, &args.output, args.options)?;
+    }
+    extract_scripts(&ppf.scripts, &args.output, args.decompile, args.options)
+}
+
+fn extract_from_lpf(input: &Path, args: &mut ExtractArgs) -> Result<(), Error> {
+    let lpf = read_lpf(input)?;
+    args.timings.phase("parse", 1, args.input_size);
+    if args.include_globals {
+        extract_globals(&lpf, &args.output, args.options)?;
+    }
+    extract_scripts(&lpf, &args.output, args.decompile, args.options)
+}
+
+fn extract_from_tpf(input: &Path, args: &mut ExtractArgs) -> Result<(), Error> {
+    let tpf = read_tpf(input)?;
+    args.timings.phase("parse", 1, args.input_size);
+    extract_tpf(&tpf, &args.output, args.language, args.all_languages, args.options)
+}
+
+fn split_ppf(input: &Path, args: &SplitArgs) -> Result<(), Error> {
+    let level_name = input
+        .file_stem()
+        .and_then(OsStr::to_str)
+        .ok_or_else(|| Error::Unsupported(input.to_path_buf()))?;
+
+    let ppf = read_ppf(input, args.mmap)?;
+    warn_meshes(&ppf);
 
-    let mut file = BufWriter::new(File::create(path)?);
-    file.write_all(data)?;
+    let pieces = PIECES.iter().filter(|(_, _, ext)| {
+        args.only.as_deref().is_none_or(|only| only == *ext) && !(args.strip_level && *ext == "plb")
+    });
+    for (section, directory, ext) in pieces {
+        let output = args.output.join(directory);
+        std::fs::create_dir_all(&output)?;
+
+        let output = output.join(format!("{}.{}", level_name, ext));
+        log::info!("writing {:?}", output);
+
+        let file = File::create(output)?;
+        let mut writer = BufWriter::new(file);
+        match *section {
+            "textures" => ppf.textures.write_le(&mut writer)?,
+            "meshes" => ppf.meshes.write_le(&mut writer)?,
+            "scripts" => ppf.scripts.write_le(&mut writer)?,
+            _ => ppf.level.write_le(&mut writer)?,
+        }
+    }
     Ok(())
 }
 
-fn main() -> Result<(), BoxError> {
-    env_logger::builder()
-        .filter_level(log::LevelFilter::Info)
-        .parse_default_env()
-        .init();
+fn main() {
     let opts: Opts = Opts::parse();
+    opts.log.init(if opts.verbose {
+        log::LevelFilter::Debug
+    } else {
+        log::LevelFilter::Info
+    });
+
+    if let Err(err) = run(opts) {
+        log::error!("{}", err);
+        std::process::exit(err.exit_code());
+    }
+}
+
+fn run(opts: Opts) -> Result<(), Error> {
+    #[cfg(feature = "memmap2")]
+    let mmap = opts.mmap;
+    #[cfg(not(feature = "memmap2"))]
+    let mmap = false;
+
+    if let Some(jobs) = opts.jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global()
+            .expect("the global thread pool is only built here");
+    }
 
     match opts.subcommand {
-        SubCommand::Info { input } => {
+        SubCommand::Info { input, long } => {
             log::info!("input = {:?}", input);
 
-            match input.extension() {
-                Some(ext) if ext == "pkg" => {
-                    let data = read_file(&input)?;
-                    let zpkg = Zpkg::from_slice(&data)?;
-                    log::info!("{:#?}", zpkg);
-                }
-                Some(ext) if ext == "ppf" => {
-                    let file = File::open(&input)?;
-                    let mut reader = BufReader::new(file);
-                    let ppf = Ppf::read(&mut reader)?;
-                    log::info!("{:#?}", ppf);
-                }
-                _ => unimplemented!(),
+            let info = handler(&input, |handler| handler.info)?;
+            info(&input, &InfoArgs { mmap, long })?;
+        }
+        SubCommand::Tree { input, depth } => {
+            let tree = handler(&input, |handler| handler.tree)?;
+            let root = tree(&input, &ReadArgs { mmap })?;
+            let mut stdout = BufWriter::new(std::io::stdout().lock());
+            writeln!(stdout, "{}", root.name)?;
+            root.write(&mut stdout, "", depth)?;
+            stdout.flush()?;
+        }
+        SubCommand::Validate { input } => {
+            log::info!("input = {:?}", input);
+            let validate = handler(&input, |handler| handler.validate)?;
+            let problems = validate(&input, &ReadArgs { mmap })?;
+            for problem in &problems {
+                log::error!("{}", problem);
+            }
+            if !problems.is_empty() {
+                return Err(Error::Invalid(problems.len()));
             }
+            log::info!("no problems found");
         }
-        SubCommand::Extract { input, output } => {
+        SubCommand::Extract {
+            input,
+            output,
+            salvage,
+            inflate,
+            manifest,
+            decompile,
+            include_globals,
+            dedupe,
+            language,
+            compat,
+            pitch_alignment,
+            all_languages,
+            dry_run,
+            force,
+            merge,
+            sequence,
+            format,
+        } => {
             log::info!("input = {:?}", input);
+            let is_tpf = input
+                .extension()
+                .and_then(OsStr::to_str)
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("tpf"));
+            if (language.is_some() || all_languages) && !is_tpf {
+                return Err(Error::LanguageNotApplicable(input));
+            }
             let output = output.unwrap_or_else(|| {
                 input
                     .parent()
@@ -156,24 +1458,52 @@ fn main() -> Result<(), BoxError> {
             });
             log::info!("output = {:?}", output);
 
-            match input.extension() {
-                Some(ext) if ext == "pkg" => {
-                    let data = read_file(&input)?;
-                    let zpkg = Zpkg::from_slice(&data)?;
-
-                    for ZpkgFile { path, data } in zpkg.files {
-                        let path = match path {
-                            _ if path.starts_with('/') => &path[1..path.len()],
-                            _ => &path,
-                        };
-                        write_file(&output.join(path), &data)?;
-                    }
+            if !force && !merge && std::fs::read_dir(&output).is_ok_and(|mut entries| entries.next().is_some()) {
+                // A dry run still goes through every entry, only noting that the real run would stop here.
+                if !dry_run {
+                    return Err(Error::OutputNotEmpty(output));
                 }
-                Some(ext) if ext == "tpf" => todo!(),
-                _ => unimplemented!(),
+                log::warn!("would fail: {}", Error::OutputNotEmpty(output.clone()));
             }
+            let written = Written::default();
+            let mut args = ExtractArgs {
+                mmap,
+                output,
+                salvage,
+                inflate,
+                manifest,
+                decompile,
+                include_globals,
+                dedupe,
+                language: language.unwrap_or(DEFAULT_LANGUAGE),
+                all_languages,
+                options: WriteOptions {
+                    dry_run,
+                    force,
+                    compat,
+                    pitch_alignment,
+                    sequence,
+                    format,
+                    written: &written,
+                },
+                timings: Timings::new(opts.timings),
+                input_size: std::fs::metadata(&input).map_or(0, |metadata| metadata.len() as usize),
+            };
+
+            let extract = handler(&input, |handler| handler.extract)?;
+            extract(&input, &mut args)?;
+            args.timings.phase(
+                "write",
+                written.files.load(Ordering::Relaxed),
+                written.bytes.load(Ordering::Relaxed),
+            );
         }
-        SubCommand::Split { input, output } => {
+        SubCommand::Split {
+            input,
+            output,
+            strip_level,
+            only,
+        } => {
             log::info!("input = {:?}", input);
             let output = output.unwrap_or_else(|| {
                 input
@@ -184,41 +1514,93 @@ fn main() -> Result<(), BoxError> {
             });
             log::info!("output = {:?}", output);
 
-            match input.extension() {
-                Some(ext) if ext == "ppf" => {
-                    let level_name = input.file_stem().and_then(OsStr::to_str).unwrap();
-
-                    let file = File::open(&input)?;
-                    let mut reader = BufReader::new(file);
-                    let ppf = Ppf::read(&mut reader)?;
-
-                    for ext in ["tpf", "mpf", "lpf", "plb"] {
-                        let output = match ext {
-                            "tpf" => output.join("pcpackfiles"),
-                            "mpf" => output.join("packfiles"),
-                            "lpf" => output.join("scripts").join("packfiles"),
-                            "plb" => output.join("levels"),
-                            _ => unimplemented!(),
-                        };
-
-                        std::fs::create_dir_all(&output)?;
-
-                        let output = output.join(format!("{}.{}", level_name, ext));
-                        log::info!("writing {:?}", output);
-
-                        let file = File::create(output)?;
-                        let mut writer = BufWriter::new(file);
-                        match ext {
-                            "tpf" => ppf.textures.write_to(&mut writer)?,
-                            "mpf" => ppf.meshes.write_to(&mut writer)?,
-                            "lpf" => ppf.scripts.write_to(&mut writer)?,
-                            "plb" => ppf.level.write_to(&mut writer)?,
-                            _ => unimplemented!(),
-                        }
-                    }
-                }
-                _ => unimplemented!(),
+            let split = handler(&input, |handler| handler.split)?;
+            split(
+                &input,
+                &SplitArgs {
+                    mmap,
+                    output,
+                    strip_level,
+                    only,
+                },
+            )?;
+        }
+        SubCommand::Join { input, output, verify } => {
+            log::info!("input = {:?}", input);
+            log::info!("output = {:?}", output);
+            let level_name = output
+                .file_stem()
+                .and_then(OsStr::to_str)
+                .ok_or_else(|| Error::Unsupported(output.clone()))?;
+
+            let pieces = PIECES
+                .iter()
+                .map(|(_, directory, ext)| {
+                    let path = input.join(directory).join(format!("{}.{}", level_name, ext));
+                    log::info!("reading {:?}", path);
+                    read_file(path)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let ppf = Ppf {
+                textures: TexturePackFile::from_reader(&mut std::io::Cursor::new(&pieces[0]))?,
+                meshes: MeshPackFile::read(&mut std::io::Cursor::new(&pieces[1])).map_err(ppf::Error::from)?,
+                scripts: LuaPackFile::read(&mut std::io::Cursor::new(&pieces[2])).map_err(Error::Lpf)?,
+                level: pieces[3].clone(),
+            };
+
+            if let Some(parent) = output.parent() {
+                std::fs::create_dir_all(parent)?;
             }
+            let mut writer = BufWriter::new(File::create(&output)?);
+            ppf.write_le(&mut writer)?;
+            writer.flush()?;
+
+            if verify {
+                verify_join(&output, &pieces)?;
+            }
+        }
+        SubCommand::Replace {
+            input,
+            texture,
+            frame,
+            dds,
+            output,
+        } => {
+            log::info!("input = {:?}", input);
+            log::info!("output = {:?}", output);
+
+            let mut ppf = read_ppf(&input, mmap)?;
+            let dds = read_dds(&dds)?;
+            replace_texture(&mut ppf, &texture, frame, Texture::from_dds(&dds.header, &dds.data)?)?;
+
+            if let Some(parent) = output.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let mut writer = BufWriter::new(File::create(&output)?);
+            ppf.write_le(&mut writer)?;
+        }
+        SubCommand::Pack { input, output } => {
+            log::info!("input = {:?}", input);
+            log::info!("output = {:?}", output);
+            let zpkg = Zpkg::from_directory(&input)?;
+
+            if let Some(parent) = output.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let mut writer = BufWriter::new(File::create(&output)?);
+            zpkg.write(&mut writer)?;
+            writer.flush()?;
+            log::info!(
+                "packed {} files ({} bytes) into {} bytes",
+                zpkg.files.len(),
+                zpkg.files.iter().map(|file| file.data.len()).sum::<usize>(),
+                std::fs::metadata(&output)?.len()
+            );
+        }
+        SubCommand::Convert { input, output, path } => {
+            log::info!("input = {:?}", input);
+            log::info!("output = {:?}", output);
+            convert(&input, &output, path)?;
         }
     }
 
@@ -0,0 +1,1484 @@
+use std::{
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use binrw::BinWrite;
+
+use ppf::{Global, LuaPackFile, Mesh, MeshPackFile, Path as TexturePath};
+use tpf::{
+    v0, v1, AnimationInfo, GameTexture, GameTextureFlags, LanguageId, PlayMode, Texture, TextureFlags, TextureFormat,
+    TextureMetadata, TexturePackFile, TextureType, Version,
+};
+
+/// Builds a ZPKG holding `(name, extension, data)` files in its root directory.
+fn zpkg(files: &[(&str, &str, &[u8])]) -> Vec<u8> {
+    let mut names = Vec::new();
+    let mut types = Vec::new();
+    let mut data = Vec::new();
+    let mut records = Vec::new();
+
+    let file_records_size = files.len() * 16;
+    let directory_records_offset = 512 + file_records_size;
+    let name_directory_offset = directory_records_offset + 12;
+    for (name, ext, _) in files {
+        records.push((names.len(), types.len()));
+        names.extend_from_slice(name.as_bytes());
+        names.push(0);
+        types.extend_from_slice(ext.as_bytes());
+        types.push(0);
+    }
+    let file_type_directory_offset = name_directory_offset + names.len();
+    let file_data_offset = file_type_directory_offset + types.len();
+
+    let mut output = b"ZPKG".to_vec();
+    for value in [
+        1,
+        file_data_offset,
+        files.len(),
+        directory_records_offset,
+        1,
+        name_directory_offset,
+        file_type_directory_offset,
+    ] {
+        output.extend_from_slice(&(value as u32).to_le_bytes());
+    }
+    output.resize(512, 0);
+
+    for ((name_offset, type_offset), (_, _, contents)) in records.into_iter().zip(files) {
+        output.push(0);
+        output.extend_from_slice(&(type_offset as u16).to_le_bytes());
+        output.push(0);
+        output.extend_from_slice(&(name_offset as u32).to_le_bytes());
+        output.extend_from_slice(&((file_data_offset + data.len()) as u32).to_le_bytes());
+        output.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        data.extend_from_slice(contents);
+    }
+
+    // A single directory record without files keeps everything in the root.
+    output.extend_from_slice(&[0; 12]);
+    output.extend(names);
+    output.extend(types);
+    output.extend(data);
+    output
+}
+
+fn extract(input: &Path, output: &Path, extra: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .args(extra)
+        .arg("extract")
+        .arg(input)
+        .arg("-o")
+        .arg(output)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+fn fixture(dir: &Path) -> PathBuf {
+    let input = dir.join("test.pkg");
+    fs::write(
+        &input,
+        zpkg(&[("first", "txt", b"hello"), ("second", "lua", b"x = 1\n")]),
+    )
+    .unwrap();
+    input
+}
+
+#[test]
+fn extract_pkg() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = fixture(dir.path());
+
+    extract(&input, &dir.path().join("output"), &[]);
+
+    assert_eq!(b"hello", &fs::read(dir.path().join("output/first.txt")).unwrap()[..]);
+    assert_eq!(b"x = 1\n", &fs::read(dir.path().join("output/second.lua")).unwrap()[..]);
+}
+
+#[test]
+fn extract_inflate() {
+    use std::io::Write;
+
+    let original = b"x = 1\n".repeat(100);
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&original).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("test.pkg");
+    fs::write(&input, zpkg(&[("script", "lua", &compressed)])).unwrap();
+
+    // Kept as stored unless asked for.
+    extract(&input, &dir.path().join("stored"), &[]);
+    assert_eq!(compressed, fs::read(dir.path().join("stored/script.lua")).unwrap());
+
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("extract")
+        .arg(&input)
+        .arg("-o")
+        .arg(dir.path().join("inflated"))
+        .arg("--inflate")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(original, fs::read(dir.path().join("inflated/script.lua")).unwrap());
+}
+
+#[cfg(feature = "memmap2")]
+#[test]
+fn mmap_extract_matches_buffered() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = fixture(dir.path());
+
+    extract(&input, &dir.path().join("buffered"), &[]);
+    extract(&input, &dir.path().join("mapped"), &["--mmap"]);
+
+    for file in ["first.txt", "second.lua"] {
+        let buffered = fs::read(dir.path().join("buffered").join(file)).unwrap();
+        assert_eq!(buffered, fs::read(dir.path().join("mapped").join(file)).unwrap());
+    }
+}
+
+#[test]
+fn extract_manifest() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = fixture(dir.path());
+    let manifest = dir.path().join("manifest.json");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("extract")
+        .arg(&input)
+        .arg("-o")
+        .arg(dir.path().join("output"))
+        .arg("--manifest")
+        .arg(&manifest)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let manifest: serde_json::Value = serde_json::from_slice(&fs::read(manifest).unwrap()).unwrap();
+    assert_eq!(
+        serde_json::json!({
+            "first.txt": { "crc32": 0x3610a686u32, "size": 5 },
+            "second.lua": { "crc32": 0x8bf6893bu32, "size": 6 },
+        }),
+        manifest
+    );
+
+    // Merging over an earlier extraction writes nothing, but the manifest still lists every file.
+    let merged = dir.path().join("merged.json");
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("extract")
+        .arg(&input)
+        .arg("-o")
+        .arg(dir.path().join("output"))
+        .arg("--merge")
+        .arg("--manifest")
+        .arg(&merged)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let merged: serde_json::Value = serde_json::from_slice(&fs::read(merged).unwrap()).unwrap();
+    assert_eq!(manifest, merged);
+}
+
+#[test]
+fn extract_duplicate_paths() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("test.pkg");
+    fs::write(&input, zpkg(&[("first", "txt", b"a"), ("first", "txt", b"b")])).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("extract")
+        .arg(&input)
+        .arg("-o")
+        .arg(dir.path().join("output"))
+        .status()
+        .unwrap();
+    assert_eq!(Some(65), status.code());
+    assert!(!dir.path().join("output").exists());
+
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("extract")
+        .arg(&input)
+        .arg("-o")
+        .arg(dir.path().join("output"))
+        .arg("--dedupe")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(b"a", &fs::read(dir.path().join("output/first.txt")).unwrap()[..]);
+    assert_eq!(b"b", &fs::read(dir.path().join("output/first_1.txt")).unwrap()[..]);
+}
+
+#[test]
+fn extract_dry_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = fixture(dir.path());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("extract")
+        .arg(&input)
+        .arg("-o")
+        .arg(dir.path().join("output"))
+        .arg("--manifest")
+        .arg(dir.path().join("manifest.json"))
+        .arg("--dry-run")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(!dir.path().join("output").exists());
+    assert!(!dir.path().join("manifest.json").exists());
+
+    let log = String::from_utf8(output.stderr).unwrap();
+    assert!(log.contains("first.txt\" (5 bytes)"), "{}", log);
+    assert!(log.contains("second.lua\" (6 bytes)"), "{}", log);
+
+    // An output that isn't empty is reported as a failure the real run would hit, but the dry run goes on.
+    let output = dir.path().join("output");
+    fs::create_dir(&output).unwrap();
+    fs::write(output.join("first.txt"), b"edited").unwrap();
+    let result = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("extract")
+        .arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .arg("--dry-run")
+        .output()
+        .unwrap();
+    assert!(result.status.success());
+    assert_eq!(vec![PathBuf::from("first.txt")], files_below(&output));
+
+    let log = String::from_utf8(result.stderr).unwrap();
+    assert!(log.contains("would fail: ") && log.contains("isn't empty"), "{}", log);
+    assert!(log.contains("second.lua\" (6 bytes)"), "{}", log);
+}
+
+#[test]
+fn extract_timings() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = fixture(dir.path());
+    extract(&input, &dir.path().join("plain"), &[]);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("--timings")
+        .arg("extract")
+        .arg(&input)
+        .arg("-o")
+        .arg(dir.path().join("timed"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let log = String::from_utf8(output.stderr).unwrap();
+    let input_size = fs::metadata(&input).unwrap().len();
+    assert!(log.contains(&format!("(1 files, {} bytes)", input_size)), "{}", log);
+    assert!(log.contains("parse took"), "{}", log);
+    assert!(log.contains("write took"), "{}", log);
+    assert!(log.contains("(2 files, 11 bytes)"), "{}", log);
+
+    for name in ["first.txt", "second.lua"] {
+        assert_eq!(
+            fs::read(dir.path().join("plain").join(name)).unwrap(),
+            fs::read(dir.path().join("timed").join(name)).unwrap()
+        );
+    }
+}
+
+#[test]
+fn extract_log_options() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("textures.tpf");
+    fs::write(&input, tpf_with_languages(&[(LanguageId::English, 1)])).unwrap();
+    let log = |name: &str, options: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_repkg"))
+            .args(options)
+            .arg("extract")
+            .arg(&input)
+            .arg("-o")
+            .arg(dir.path().join(name))
+            .env_remove("RUST_LOG")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    assert!(log("plain", &[]).contains("writing"));
+    assert_eq!("", log("quiet", &["--quiet"]));
+    // Extracting over the same files again warns about each one, unless quiet.
+    let merge = |options: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_repkg"))
+            .args(options)
+            .arg("extract")
+            .arg(&input)
+            .arg("-o")
+            .arg(dir.path().join("plain"))
+            .arg("--merge")
+            .env_remove("RUST_LOG")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stderr).unwrap()
+    };
+    assert!(merge(&[]).contains("skipping existing"));
+    assert_eq!("", merge(&["--quiet"]));
+
+    let json = log("json", &["--log-format", "json"]);
+    let lines: Vec<serde_json::Value> = json.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert!(lines
+        .iter()
+        .any(|line| line["level"] == "INFO" && line["message"].as_str().unwrap().starts_with("writing")));
+}
+
+#[test]
+fn extract_existing_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = fixture(dir.path());
+    let output = dir.path().join("output");
+    extract(&input, &output, &[]);
+    fs::write(output.join("first.txt"), b"edited").unwrap();
+    fs::remove_file(output.join("second.lua")).unwrap();
+
+    let extract_again = |flag: Option<&str>| {
+        Command::new(env!("CARGO_BIN_EXE_repkg"))
+            .arg("extract")
+            .arg(&input)
+            .arg("-o")
+            .arg(&output)
+            .args(flag)
+            .status()
+            .unwrap()
+    };
+
+    assert_eq!(Some(73), extract_again(None).code());
+    assert!(!output.join("second.lua").exists());
+
+    assert!(extract_again(Some("--merge")).success());
+    assert_eq!(b"edited", &fs::read(output.join("first.txt")).unwrap()[..]);
+    assert_eq!(b"x = 1\n", &fs::read(output.join("second.lua")).unwrap()[..]);
+
+    assert!(extract_again(Some("--force")).success());
+    assert_eq!(b"hello", &fs::read(output.join("first.txt")).unwrap()[..]);
+}
+
+#[test]
+fn extract_rejects_truncated() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = fixture(dir.path());
+    let data = fs::read(&input).unwrap();
+    fs::write(&input, &data[..data.len() - 1]).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("extract")
+        .arg(&input)
+        .arg("-o")
+        .arg(dir.path().join("output"))
+        .status()
+        .unwrap();
+    assert_eq!(Some(65), status.code());
+    assert!(!dir.path().join("output").exists());
+
+    // Unless what is there is wanted anyway.
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("extract")
+        .arg(&input)
+        .arg("-o")
+        .arg(dir.path().join("output"))
+        .arg("--salvage")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(b"hello", &fs::read(dir.path().join("output/first.txt")).unwrap()[..]);
+    assert_eq!(b"x = 1", &fs::read(dir.path().join("output/second.lua")).unwrap()[..]);
+}
+
+fn texture(format: TextureFormat, width: usize, height: usize, fill: u8) -> Texture {
+    Texture {
+        format,
+        type_: TextureType::Bitmap,
+        flags: TextureFlags::empty(),
+        width,
+        height,
+        mipmaps: 1,
+        palette: None,
+        data: vec![fill; tpf::texture_size(format, TextureType::Bitmap, width, height, 1).unwrap()],
+    }
+}
+
+fn game_texture(path: &str, texture: Texture) -> v0::GameTexture {
+    v0::GameTexture {
+        element_id: 0,
+        texture_handle: 1,
+        palette_handle: 0,
+        path_pointer: 1,
+        animation_info_pointer: 0,
+        density: 1.0,
+        visual_importance: 0,
+        memory_importance: 0,
+        unknown0: 0,
+        flags: GameTextureFlags::empty(),
+        path: Some(TexturePath::new(path)),
+        animation_info: None,
+        textures: vec![texture],
+    }
+}
+
+/// Builds a ppf with the given textures, no meshes or scripts and a short level.
+fn ppf(game_textures: Vec<GameTexture>) -> Vec<u8> {
+    let textures = TexturePackFile {
+        version: Some(Version::V1),
+        languages: vec![],
+        game_textures,
+    };
+    let mut writer = Cursor::new(b"PPAK".to_vec());
+    writer.set_position(4);
+    textures.write_le(&mut writer).unwrap();
+
+    let mut output = writer.into_inner();
+    output.extend_from_slice(b"MPAK\0\0"); // no meshes
+    output.extend_from_slice(&[0; 4]); // no globals or scripts
+    output.extend_from_slice(b"level");
+    output
+}
+
+/// Builds a ppf with an empty mesh for each path, no textures or scripts and a short level.
+fn ppf_with_meshes(paths: &[&str]) -> Vec<u8> {
+    let meshes = MeshPackFile {
+        meshes: paths
+            .iter()
+            .map(|path| Mesh {
+                path: TexturePath::new(*path),
+                unknown0: 0,
+                data: vec![],
+            })
+            .collect(),
+    };
+    let mut data = ppf(vec![]);
+    data.truncate(data.len() - b"MPAK\0\0\0\0\0\0level".len());
+    let mut writer = Cursor::new(data);
+    writer.set_position(writer.get_ref().len() as u64);
+    meshes.write_le(&mut writer).unwrap();
+    let mut data = writer.into_inner();
+    data.extend_from_slice(&[0; 4]); // no globals or scripts
+    data.extend_from_slice(b"level");
+    data
+}
+
+fn dds(texture: &Texture) -> Vec<u8> {
+    let mut output = dds::MAGIC.to_le_bytes().to_vec();
+    output.extend(bincode::serialize(&texture.dds_header().unwrap()).unwrap());
+    output.extend_from_slice(&texture.data);
+    output
+}
+
+#[test]
+fn replace_texture() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("level.ppf");
+    let output = dir.path().join("out/level.ppf");
+    let replacement = dir.path().join("new.dds");
+    fs::write(
+        &input,
+        ppf(vec![
+            GameTexture::V1(v1::GameTexture {
+                game_texture: game_texture("textures/first", texture(TextureFormat::DXT1, 4, 4, 1)),
+            }),
+            GameTexture::V0(game_texture(
+                "textures/second",
+                texture(TextureFormat::A8R8G8B8, 2, 2, 2),
+            )),
+        ]),
+    )
+    .unwrap();
+    fs::write(&replacement, dds(&texture(TextureFormat::DXT1, 8, 8, 3))).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .args(["replace", "--texture", "/textures\\first", "--dds"])
+        .arg(&replacement)
+        .arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let ppf = ppf::Ppf::from_reader(&mut Cursor::new(fs::read(&output).unwrap())).unwrap();
+    let textures: Vec<&Texture> = ppf
+        .textures
+        .game_textures
+        .iter()
+        .map(|game_texture| match game_texture {
+            GameTexture::V0(game_texture) => &game_texture.textures[0],
+            GameTexture::V1(game_texture) => &game_texture.game_texture.textures[0],
+        })
+        .collect();
+    assert_eq!(&texture(TextureFormat::DXT1, 8, 8, 3), textures[0]);
+    assert_eq!(&texture(TextureFormat::A8R8G8B8, 2, 2, 2), textures[1]);
+    assert_eq!(b"level", &ppf.level[..]);
+}
+
+#[test]
+fn replace_rejects_other_formats() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("level.ppf");
+    let replacement = dir.path().join("new.dds");
+    fs::write(
+        &input,
+        ppf(vec![GameTexture::V0(game_texture(
+            "textures/first",
+            texture(TextureFormat::DXT1, 4, 4, 1),
+        ))]),
+    )
+    .unwrap();
+    fs::write(&replacement, dds(&texture(TextureFormat::A8R8G8B8, 4, 4, 3))).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .args(["replace", "--texture", "textures/first", "--dds"])
+        .arg(&replacement)
+        .arg(&input)
+        .arg("-o")
+        .arg(dir.path().join("out.ppf"))
+        .status()
+        .unwrap();
+    assert_eq!(Some(65), status.code());
+}
+
+#[test]
+fn dds_info() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("texture.dds");
+    let data = dds(&texture(TextureFormat::DXT5, 8, 8, 1));
+    fs::write(&input, &data).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("info")
+        .arg(&input)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let log = String::from_utf8(output.stderr).unwrap();
+    assert!(log.contains("dimensions = 8x8"));
+    assert!(log.contains("format = DXT5"));
+    assert!(log.contains("cubemap = false"));
+    assert!(log.contains("caps = texture\n"));
+
+    fs::write(&input, &data[..data.len() - 1]).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("info")
+        .arg(&input)
+        .output()
+        .unwrap();
+    assert_eq!(Some(65), output.status.code());
+}
+
+fn luadec_fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../luadec/tests/fixtures")
+        .join(name)
+}
+
+/// Builds a version 1 lpf holding `(path, bytecode)` scripts and no globals.
+fn lpf(scripts: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut output = b"\xFC\xFC".to_vec();
+    output.extend_from_slice(&1u16.to_le_bytes());
+    output.extend_from_slice(&0u16.to_le_bytes());
+    output.extend_from_slice(&(scripts.len() as u16).to_le_bytes());
+    for (path, data) in scripts {
+        output.extend_from_slice(&(path.len() as u16 + 1).to_le_bytes());
+        output.extend_from_slice(path.as_bytes());
+        output.push(0);
+        output.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        output.extend_from_slice(data);
+    }
+    output
+}
+
+#[test]
+fn extract_decompiled_scripts() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("scripts.lpf");
+    let output = dir.path().join("output");
+    let unimplemented = fs::read(luadec_fixture("unimplemented.luac")).unwrap();
+    fs::write(
+        &input,
+        lpf(&[
+            (
+                "scripts/assignments.lua",
+                &fs::read(luadec_fixture("assignments.luac")).unwrap(),
+            ),
+            ("scripts/unimplemented.lua", &unimplemented),
+        ]),
+    )
+    .unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("extract")
+        .arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .arg("--decompile")
+        .output()
+        .unwrap()
+        .status;
+    assert!(status.success());
+
+    let source = fs::read_to_string(output.join("scripts/assignments.lua")).unwrap();
+    assert!(!source.is_empty());
+    assert_eq!(fs::read_to_string(luadec_fixture("assignments.lua")).unwrap(), source);
+    assert_eq!(
+        unimplemented,
+        fs::read(output.join("scripts/unimplemented.luac")).unwrap()
+    );
+}
+
+#[test]
+fn extract_globals() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("scripts.lpf");
+    let scripts = LuaPackFile {
+        version: None,
+        globals: vec![
+            Global {
+                path: TexturePath::new("/config/speed.dat"),
+                data: b"fast".to_vec(),
+            },
+            Global {
+                path: TexturePath::new("debug"),
+                data: vec![1],
+            },
+        ],
+        scripts: vec![],
+    };
+    let mut writer = Cursor::new(Vec::new());
+    scripts.write_le(&mut writer).unwrap();
+    fs::write(&input, writer.into_inner()).unwrap();
+
+    extract(&input, &dir.path().join("without"), &[]);
+    assert!(!dir.path().join("without/globals").exists());
+
+    let output = dir.path().join("with");
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("extract")
+        .arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .arg("--include-globals")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(b"fast", &fs::read(output.join("globals/config/speed.dat")).unwrap()[..]);
+    assert_eq!(vec![1], fs::read(output.join("globals/debug")).unwrap());
+}
+
+#[test]
+fn extract_rejects_escaping_paths() {
+    let dir = tempfile::tempdir().unwrap();
+    let scripts = dir.path().join("scripts.lpf");
+    let lpf = LuaPackFile {
+        version: None,
+        globals: vec![Global {
+            path: TexturePath::new("../../escaped.dat"),
+            data: vec![1],
+        }],
+        scripts: vec![],
+    };
+    let mut writer = Cursor::new(Vec::new());
+    lpf.write_le(&mut writer).unwrap();
+    fs::write(&scripts, writer.into_inner()).unwrap();
+
+    let textures = dir.path().join("textures.tpf");
+    let tpf = TexturePackFile {
+        version: Some(Version::V1),
+        languages: vec![],
+        game_textures: vec![GameTexture::V0(game_texture(
+            "textures\\..\\..\\escaped",
+            texture(TextureFormat::A8R8G8B8, 2, 2, 0),
+        ))],
+    };
+    let mut writer = Cursor::new(Vec::new());
+    tpf.write_le(&mut writer).unwrap();
+    fs::write(&textures, writer.into_inner()).unwrap();
+
+    for (input, extra) in [(&scripts, "--include-globals"), (&textures, "--force")] {
+        let output = Command::new(env!("CARGO_BIN_EXE_repkg"))
+            .arg("extract")
+            .arg(input)
+            .arg("-o")
+            .arg(dir.path().join("output/nested"))
+            .arg(extra)
+            .output()
+            .unwrap();
+        assert_eq!(Some(65), output.status.code(), "{:?}", input);
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("outside the output directory"));
+    }
+    assert!(!dir.path().join("output/escaped.dat").exists());
+    assert!(!dir.path().join("output/escaped.dds").exists());
+}
+
+#[test]
+fn extract_rejects_language_without_tpf() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("scripts.lpf");
+    let lpf = LuaPackFile {
+        version: None,
+        globals: vec![],
+        scripts: vec![],
+    };
+    let mut writer = Cursor::new(Vec::new());
+    lpf.write_le(&mut writer).unwrap();
+    fs::write(&input, writer.into_inner()).unwrap();
+
+    for extra in [&["--language", "french"][..], &["--all-languages"]] {
+        let output = Command::new(env!("CARGO_BIN_EXE_repkg"))
+            .arg("extract")
+            .arg(&input)
+            .arg("-o")
+            .arg(dir.path().join("output"))
+            .args(extra)
+            .output()
+            .unwrap();
+        assert_eq!(Some(64), output.status.code(), "{:?}", extra);
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("only apply to tpf textures"));
+    }
+    assert!(!dir.path().join("output").exists());
+}
+
+#[test]
+fn info_warns_about_textures() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("level.ppf");
+    fs::write(
+        &input,
+        ppf(vec![GameTexture::V0(game_texture(
+            "textures/odd",
+            texture(TextureFormat::A8R8G8B8, 3, 4, 0),
+        ))]),
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("info")
+        .arg(&input)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let log = String::from_utf8(output.stderr).unwrap();
+    assert!(log.contains("textures/odd (frame 0): 3x4 isn't a power of two."));
+    assert!(log.contains("textures = 1"));
+    assert!(!log.contains("A8R8G8B8 3x4"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("info")
+        .arg("--long")
+        .arg(&input)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let log = String::from_utf8(output.stderr).unwrap();
+    assert!(log.contains("textures/odd (frame 0): A8R8G8B8 3x4, 1 mipmaps"));
+    assert!(log.contains("level = 5 bytes"));
+}
+
+#[test]
+fn extract_warns_about_mesh_paths() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("level.ppf");
+    fs::write(
+        &input,
+        ppf_with_meshes(&["meshes/rock", "/meshes\\rock", "meshes/tree"]),
+    )
+    .unwrap();
+
+    for command in ["extract", "split"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_repkg"))
+            .arg(command)
+            .arg(&input)
+            .arg("-o")
+            .arg(dir.path().join(command))
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let log = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            log.contains(r#"More than one mesh has the path "/meshes\\rock"."#),
+            "{}",
+            log
+        );
+        assert!(!log.contains("meshes/tree"), "{}", log);
+    }
+}
+
+/// Builds a tpf whose only textures are one per language, all at the same path.
+fn tpf_with_languages(languages: &[(LanguageId, u8)]) -> Vec<u8> {
+    let mut output = vec![0xFD, 0xFD, 0x01, 0x00];
+    for &(id, fill) in languages {
+        let game_texture = GameTexture::V0(game_texture(
+            "textures/sign",
+            texture(TextureFormat::A8R8G8B8, 2, 2, fill),
+        ));
+        let mut writer = Cursor::new(Vec::new());
+        game_texture.write_le(&mut writer).unwrap();
+        let game_texture = writer.into_inner();
+
+        output.extend_from_slice(&[0xFF, 0xFF]);
+        output.extend_from_slice(&(id as u16).to_le_bytes());
+        output.extend_from_slice(&(game_texture.len() as u32 + 2).to_le_bytes());
+        output.extend_from_slice(&1u16.to_le_bytes());
+        output.extend(game_texture);
+    }
+    output.extend_from_slice(&0u16.to_le_bytes());
+    output
+}
+
+fn read_dds_data(path: &Path) -> Vec<u8> {
+    dds::Dds::read(&mut Cursor::new(fs::read(path).unwrap())).unwrap().data
+}
+
+#[test]
+fn extract_tpf_languages() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("textures.tpf");
+    fs::write(
+        &input,
+        tpf_with_languages(&[
+            (LanguageId::English, 1),
+            (LanguageId::French, 2),
+            (LanguageId::Nonsense, 3),
+        ]),
+    )
+    .unwrap();
+
+    extract(&input, &dir.path().join("default"), &[]);
+    assert_eq!(
+        vec![1; 16],
+        read_dds_data(&dir.path().join("default/textures/sign.dds"))
+    );
+
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("extract")
+        .arg(&input)
+        .arg("-o")
+        .arg(dir.path().join("french"))
+        .args(["--language", "french"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(vec![2; 16], read_dds_data(&dir.path().join("french/textures/sign.dds")));
+
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("extract")
+        .arg(&input)
+        .arg("-o")
+        .arg(dir.path().join("nonsense"))
+        .args(["--language", "nonsense"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(
+        vec![3; 16],
+        read_dds_data(&dir.path().join("nonsense/textures/sign.dds"))
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("extract")
+        .arg(&input)
+        .arg("-o")
+        .arg(dir.path().join("german"))
+        .args(["--language", "2"])
+        .output()
+        .unwrap();
+    assert_eq!(Some(65), output.status.code());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("only for [English, French, Nonsense]"));
+
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("extract")
+        .arg(&input)
+        .arg("-o")
+        .arg(dir.path().join("all"))
+        .arg("--all-languages")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    for (language, fill) in [("english", 1), ("french", 2), ("nonsense", 3)] {
+        let path = dir.path().join("all/lang").join(language).join("textures/sign.dds");
+        assert_eq!(vec![fill; 16], read_dds_data(&path));
+    }
+}
+
+#[test]
+fn extract_animation_sequence() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("textures.tpf");
+    let mut animated = game_texture("textures/water", texture(TextureFormat::A8R8G8B8, 2, 2, 0));
+    animated.animation_info_pointer = 1;
+    animated.animation_info = Some(AnimationInfo {
+        frame_count: 3,
+        start_frame: 0.0,
+        loop_frame: 1.0,
+        start_time: 0.0,
+        frame_rate: 12.5,
+        play_mode: PlayMode::Oscillate,
+        playing: true,
+    });
+    animated.textures = (0..3)
+        .map(|fill| texture(TextureFormat::A8R8G8B8, 2, 2, fill))
+        .collect();
+    let textures = TexturePackFile {
+        version: None,
+        languages: vec![],
+        game_textures: vec![GameTexture::V0(animated)],
+    };
+    let mut writer = Cursor::new(Vec::new());
+    textures.write_le(&mut writer).unwrap();
+    fs::write(&input, writer.into_inner()).unwrap();
+
+    let output = dir.path().join("output");
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("extract")
+        .arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .arg("--sequence")
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    for frame in 0..3 {
+        let path = output.join(format!("textures/water_{:04}.dds", frame));
+        assert_eq!(vec![frame as u8; 16], read_dds_data(&path));
+    }
+    assert!(!output.join("textures/water_0003.dds").exists());
+
+    let sidecar: serde_json::Value =
+        serde_json::from_slice(&fs::read(output.join("textures/water.json")).unwrap()).unwrap();
+    assert_eq!(3, sidecar["frame_count"]);
+    assert_eq!(12.5, sidecar["frame_rate"]);
+    assert_eq!("Oscillate", sidecar["play_mode"]);
+    assert_eq!(1.0, sidecar["loop_frame"]);
+}
+
+#[test]
+fn convert_dds_and_tpf() {
+    let dir = tempfile::tempdir().unwrap();
+    let convert = |input: &Path, output: &Path| {
+        Command::new(env!("CARGO_BIN_EXE_repkg"))
+            .arg("convert")
+            .arg(input)
+            .arg(output)
+            .output()
+            .unwrap()
+            .status
+    };
+
+    for (name, original) in [
+        ("dxt5", texture(TextureFormat::DXT5, 8, 8, 1)),
+        ("argb", texture(TextureFormat::A8R8G8B8, 4, 2, 2)),
+    ] {
+        let input = dir.path().join(format!("{}.dds", name));
+        let tpf = dir.path().join(format!("{}.tpf", name));
+        let output = dir.path().join(format!("out/{}.dds", name));
+        fs::write(&input, dds(&original)).unwrap();
+
+        assert!(convert(&input, &tpf).success());
+        let textures = TexturePackFile::from_reader(&mut Cursor::new(fs::read(&tpf).unwrap())).unwrap();
+        match &textures.game_textures[..] {
+            [GameTexture::V0(game_texture)] => {
+                assert_eq!(Some(name), game_texture.path.as_deref());
+                assert_eq!(vec![&original], game_texture.textures.iter().collect::<Vec<_>>());
+            }
+            game_textures => panic!("unexpected textures {:?}", game_textures),
+        }
+
+        assert!(convert(&tpf, &output).success());
+        let converted = dds::Dds::read(&mut Cursor::new(fs::read(&output).unwrap())).unwrap();
+        assert_eq!(original, Texture::from_dds(&converted.header, &converted.data).unwrap());
+    }
+
+    let input = dir.path().join("dxt5.dds");
+    assert_eq!(Some(69), convert(&input, &dir.path().join("dxt5.png")).code());
+
+    let pair = dir.path().join("pair.tpf");
+    fs::write(
+        &pair,
+        tpf_with_languages(&[
+            (LanguageId::English, 1),
+            (LanguageId::French, 2),
+            (LanguageId::Nonsense, 3),
+        ]),
+    )
+    .unwrap();
+    assert_eq!(Some(65), convert(&pair, &dir.path().join("pair.dds")).code());
+}
+
+#[test]
+fn join_split_pieces() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("level.ppf");
+    let original = ppf(vec![
+        GameTexture::V0(game_texture("textures/first", texture(TextureFormat::DXT1, 8, 8, 1))),
+        GameTexture::V0(game_texture(
+            "textures/second",
+            texture(TextureFormat::A8R8G8B8, 4, 4, 2),
+        )),
+    ]);
+    fs::write(&input, &original).unwrap();
+
+    let pieces = dir.path().join("pieces");
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("split")
+        .arg(&input)
+        .arg("-o")
+        .arg(&pieces)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let joined = dir.path().join("joined/level.ppf");
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("join")
+        .arg(&pieces)
+        .arg("-o")
+        .arg(&joined)
+        .arg("--verify")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(original, fs::read(&joined).unwrap());
+}
+
+#[test]
+fn parallel_png_export_matches_serial() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("textures.tpf");
+    let format = |i: u8| [TextureFormat::DXT1, TextureFormat::DXT5, TextureFormat::A8R8G8B8][i as usize % 3];
+    // Every texture has handle 1, so textures sharing a handle have to keep their own pixels.
+    let textures = TexturePackFile {
+        version: Some(Version::V1),
+        languages: vec![],
+        game_textures: (0..16u8)
+            .map(|i| GameTexture::V0(game_texture(&format!("textures/{}", i), texture(format(i), 8, 4, i))))
+            .chain([0x10, 0xF0].map(|fill| {
+                GameTexture::V0(game_texture(
+                    &format!("textures/fill_{:x}", fill),
+                    texture(TextureFormat::A8R8G8B8, 8, 4, fill),
+                ))
+            }))
+            .collect(),
+    };
+    let mut writer = Cursor::new(Vec::new());
+    textures.write_le(&mut writer).unwrap();
+    fs::write(&input, writer.into_inner()).unwrap();
+
+    extract(&input, &dir.path().join("serial"), &["--jobs", "1"]);
+    extract(&input, &dir.path().join("parallel"), &["--jobs", "4"]);
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .args(["--jobs", "4", "extract", "--format", "png", "-o"])
+        .arg(dir.path().join("png"))
+        .arg(&input)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    for i in 0..16 {
+        let path = format!("textures/{}.dds", i);
+        let serial = fs::read(dir.path().join("serial").join(&path)).unwrap();
+        assert_eq!(serial, fs::read(dir.path().join("parallel").join(&path)).unwrap());
+
+        let png = image::open(dir.path().join(format!("png/textures/{}.png", i))).unwrap();
+        assert_eq!((8, 4), (png.width(), png.height()));
+        let expected = texture(format(i), 8, 4, i).decode_rgba8().unwrap();
+        assert_eq!(expected, png.to_rgba8().into_raw(), "textures/{}", i);
+    }
+    for fill in [0x10, 0xF0] {
+        let png = image::open(dir.path().join(format!("png/textures/fill_{:x}.png", fill))).unwrap();
+        assert_eq!(vec![fill; 8 * 4 * 4], png.to_rgba8().into_raw());
+    }
+}
+
+#[test]
+fn raw_export_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("textures.tpf");
+    // Texture isn't Clone, so the originals are built again to compare against.
+    let originals = || {
+        let mut paletted = texture(TextureFormat::PAL8, 4, 4, 3);
+        paletted.palette = Some(tpf::Palette {
+            data: Some([0x8040_20FF; 0x100]),
+        });
+        let mut compressed = texture(TextureFormat::DXT1, 8, 8, 7);
+        compressed.flags = TextureFlags::BIT_4;
+        vec![("textures/paletted", paletted), ("textures/compressed", compressed)]
+    };
+    let textures = TexturePackFile {
+        version: Some(Version::V1),
+        languages: vec![],
+        game_textures: originals()
+            .into_iter()
+            .map(|(path, texture)| GameTexture::V0(game_texture(path, texture)))
+            .collect(),
+    };
+    let mut writer = Cursor::new(Vec::new());
+    textures.write_le(&mut writer).unwrap();
+    fs::write(&input, writer.into_inner()).unwrap();
+
+    let output = dir.path().join("raw");
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .args(["extract", "--format", "raw", "-o"])
+        .arg(&output)
+        .arg(&input)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    for (path, original) in originals() {
+        let data = fs::read(output.join(format!("{}.raw", path))).unwrap();
+        let sidecar: serde_json::Value =
+            serde_json::from_slice(&fs::read(output.join(format!("{}.json", path))).unwrap()).unwrap();
+        let metadata: TextureMetadata = serde_json::from_value(sidecar.clone()).unwrap();
+        let palette: Option<Vec<u32>> = serde_json::from_value(sidecar["palette"].clone()).unwrap();
+        let palette = palette.map(|palette| {
+            let mut entries = [0; 0x100];
+            entries.copy_from_slice(&palette);
+            entries
+        });
+
+        assert_eq!(
+            original,
+            Texture::from_raw(metadata, palette, data).unwrap(),
+            "{}",
+            path
+        );
+    }
+}
+
+#[test]
+fn extract_pitch_alignment() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("textures.tpf");
+    let textures = TexturePackFile {
+        version: None,
+        languages: vec![],
+        game_textures: vec![GameTexture::V0(game_texture(
+            "textures/odd",
+            texture(TextureFormat::R8G8B8, 17, 2, 5),
+        ))],
+    };
+    let mut writer = Cursor::new(Vec::new());
+    textures.write_le(&mut writer).unwrap();
+    fs::write(&input, writer.into_inner()).unwrap();
+
+    for (alignment, pitch) in [("byte", 51), ("dword", 52)] {
+        let output = dir.path().join(alignment);
+        let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+            .arg("extract")
+            .arg(&input)
+            .arg("-o")
+            .arg(&output)
+            .args(["--pitch-alignment", alignment])
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let extracted = dds::Dds::read(&mut Cursor::new(fs::read(output.join("textures/odd.dds")).unwrap())).unwrap();
+        assert_eq!(pitch, extracted.header.pitch_or_linear_size, "{}", alignment);
+        assert_eq!(pitch as usize * 2, extracted.data.len(), "{}", alignment);
+        assert_eq!(
+            texture(TextureFormat::R8G8B8, 17, 2, 5),
+            Texture::from_dds(&extracted.header, &extracted.data).unwrap()
+        );
+    }
+}
+
+#[test]
+fn pack_then_extract() {
+    let dir = tempfile::tempdir().unwrap();
+    let files = [
+        ("readme.txt", &b"hello"[..]),
+        ("scripts/main.lua", b"x = 1\n"),
+        ("scripts/ui/hud.lua", b"hud"),
+        ("textures/ui.dds", b"DDS "),
+    ];
+    for (path, data) in files {
+        let path = dir.path().join("input").join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, data).unwrap();
+    }
+
+    let pack = |output: &Path| {
+        Command::new(env!("CARGO_BIN_EXE_repkg"))
+            .arg("pack")
+            .arg(dir.path().join("input"))
+            .arg("-o")
+            .arg(output)
+            .output()
+            .unwrap()
+    };
+    let archive = dir.path().join("archive.pkg");
+    let output = pack(&archive);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("packed 4 files (18 bytes)"));
+
+    // Packing again gives the same bytes.
+    let again = dir.path().join("again.pkg");
+    assert!(pack(&again).status.success());
+    assert_eq!(fs::read(&archive).unwrap(), fs::read(&again).unwrap());
+
+    extract(&archive, &dir.path().join("output"), &[]);
+    for (path, data) in files {
+        assert_eq!(data, &fs::read(dir.path().join("output").join(path)).unwrap()[..]);
+    }
+}
+
+/// Every file below `dir`, relative to it.
+fn files_below(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files.extend(files_below(&path).into_iter().map(|file| path.join(file)));
+        } else {
+            files.push(path);
+        }
+    }
+    files
+        .into_iter()
+        .map(|file| file.strip_prefix(dir).unwrap().to_path_buf())
+        .collect()
+}
+
+#[test]
+fn split_selected_pieces() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("level.ppf");
+    fs::write(&input, ppf(vec![])).unwrap();
+    let split = |output: &str, extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_repkg"))
+            .arg("split")
+            .arg(&input)
+            .arg("-o")
+            .arg(dir.path().join(output))
+            .args(extra)
+            .status()
+            .unwrap()
+    };
+
+    assert!(split("only", &["--only", "tpf"]).success());
+    assert_eq!(
+        vec![Path::new("pcpackfiles/level.tpf")],
+        files_below(&dir.path().join("only"))
+    );
+
+    assert!(split("stripped", &["--strip-level"]).success());
+    let files = files_below(&dir.path().join("stripped"));
+    assert_eq!(3, files.len());
+    assert!(files.iter().all(|file| file.extension().unwrap() != "plb"));
+
+    assert!(!split("both", &["--only", "plb", "--strip-level"]).success());
+}
+
+#[test]
+fn unsupported_extension() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("level.xyz");
+    fs::write(&input, b"").unwrap();
+    let run = |command: &str| {
+        Command::new(env!("CARGO_BIN_EXE_repkg"))
+            .arg(command)
+            .arg(&input)
+            .arg("-o")
+            .arg(dir.path().join("out"))
+            .output()
+            .unwrap()
+    };
+
+    for (command, supported) in [("extract", "pkg, ppf, lpf, tpf"), ("split", "ppf")] {
+        let output = run(command);
+        assert_eq!(Some(69), output.status.code());
+        let log = String::from_utf8(output.stderr).unwrap();
+        assert!(log.contains(&format!("expected one of: {}.", supported)), "{}", log);
+        assert!(!log.contains("panicked"));
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("info")
+        .arg(&input)
+        .output()
+        .unwrap();
+    assert_eq!(Some(69), output.status.code());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("expected one of: pkg, ppf, dds."));
+}
+
+#[test]
+fn tree_listing() {
+    let dir = tempfile::tempdir().unwrap();
+    for path in [
+        "readme.txt",
+        "scripts/main.lua",
+        "scripts/ui/hud.lua",
+        "textures/ui.dds",
+    ] {
+        let path = dir.path().join("input").join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, b"data").unwrap();
+    }
+    let archive = dir.path().join("archive.pkg");
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("pack")
+        .arg(dir.path().join("input"))
+        .arg("-o")
+        .arg(&archive)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let tree = |input: &Path, extra: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_repkg"))
+            .arg("tree")
+            .arg(input)
+            .args(extra)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        vec![
+            "|-- scripts/",
+            "|   |-- ui/",
+            "|   |   `-- hud.lua",
+            "|   `-- main.lua",
+            "|-- textures/",
+            "|   `-- ui.dds",
+            "`-- readme.txt",
+        ],
+        tree(&archive, &[])
+    );
+    assert_eq!(
+        vec!["|-- scripts/", "|-- textures/", "`-- readme.txt"],
+        tree(&archive, &["--depth", "1"])
+    );
+
+    let input = dir.path().join("level.ppf");
+    let texture = texture(TextureFormat::DXT1, 4, 4, 1);
+    fs::write(&input, ppf(vec![GameTexture::V0(game_texture("ui/logo", texture))])).unwrap();
+    assert_eq!(
+        vec![
+            "|-- textures (1)",
+            "|   `-- ui/logo",
+            "|-- meshes (0)",
+            "|-- globals (0)",
+            "|-- scripts (0)",
+            "`-- level (5 bytes)",
+        ],
+        tree(&input, &[])
+    );
+}
+
+#[test]
+fn extract_unnamed_textures() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("textures.tpf");
+    let named = game_texture("textures/sign", texture(TextureFormat::A8R8G8B8, 2, 2, 1));
+    let mut unnamed = game_texture("", texture(TextureFormat::A8R8G8B8, 2, 2, 2));
+    unnamed.path_pointer = 0;
+    unnamed.path = None;
+    unnamed.element_id = 7;
+    unnamed.texture_handle = 42;
+    let textures = TexturePackFile {
+        version: None,
+        languages: vec![],
+        game_textures: vec![GameTexture::V0(named), GameTexture::V0(unnamed)],
+    };
+    let mut writer = Cursor::new(Vec::new());
+    textures.write_le(&mut writer).unwrap();
+    fs::write(&input, writer.into_inner()).unwrap();
+
+    let output = dir.path().join("output");
+    extract(&input, &output, &[]);
+    assert_eq!(vec![2; 16], read_dds_data(&output.join("texture_1_7_42.dds")));
+
+    // Extracting again writes the same files rather than new ones.
+    let status = Command::new(env!("CARGO_BIN_EXE_repkg"))
+        .arg("extract")
+        .arg(&input)
+        .arg("-o")
+        .arg(&output)
+        .arg("--force")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(
+        vec![Path::new("texture_1_7_42.dds"), Path::new("textures/sign.dds")],
+        files_below(&output)
+    );
+}
+
+#[test]
+fn validate() {
+    let dir = tempfile::tempdir().unwrap();
+    let validate = |input: &Path| {
+        let output = Command::new(env!("CARGO_BIN_EXE_repkg"))
+            .arg("validate")
+            .arg(input)
+            .output()
+            .unwrap();
+        (output.status.code(), String::from_utf8(output.stderr).unwrap())
+    };
+
+    let input = fixture(dir.path());
+    let (code, log) = validate(&input);
+    assert_eq!(Some(0), code, "{}", log);
+    assert!(log.contains("no problems found"));
+
+    let data = fs::read(&input).unwrap();
+    fs::write(&input, &data[..data.len() - 1]).unwrap();
+    let (code, log) = validate(&input);
+    assert_eq!(Some(65), code);
+    assert!(log.contains("Data for /second.lua"), "{}", log);
+    assert!(log.contains("Problems found: 1."));
+
+    let input = dir.path().join("level.ppf");
+    let textures = vec![
+        GameTexture::V0(game_texture("textures/odd", texture(TextureFormat::A8R8G8B8, 3, 4, 0))),
+        GameTexture::V0(game_texture("textures/fine", texture(TextureFormat::DXT1, 4, 4, 0))),
+    ];
+    fs::write(&input, ppf(textures)).unwrap();
+    let (code, log) = validate(&input);
+    assert_eq!(Some(65), code);
+    assert!(log.contains("textures/odd (frame 0): 3x4 isn't a power of two."));
+    assert!(!log.contains("textures/fine"));
+
+    // Meshes that would collide on their path are reported too.
+    fs::write(&input, ppf_with_meshes(&["meshes/rock", "meshes\\rock"])).unwrap();
+    let (code, log) = validate(&input);
+    assert_eq!(Some(65), code);
+    assert!(
+        log.contains(r#"More than one mesh has the path "meshes\\rock"."#),
+        "{}",
+        log
+    );
+
+    // A file that doesn't parse fails the way it does for the other commands, here as truncated.
+    let data = fs::read(&input).unwrap();
+    fs::write(&input, &data[..20]).unwrap();
+    assert_eq!(Some(74), validate(&input).0);
+}
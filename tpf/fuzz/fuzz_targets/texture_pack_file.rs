@@ -0,0 +1,28 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+
+use tpf::TexturePackFile;
+
+// Malformed input must come back as an error, never a panic. Whatever does parse has to survive what `repkg` does
+// with it next.
+fuzz_target!(|data: &[u8]| {
+    let Ok(tpf) = TexturePackFile::from_reader(&mut Cursor::new(data)) else {
+        return;
+    };
+
+    let game_textures = tpf
+        .game_textures
+        .iter()
+        .chain(tpf.languages.iter().flat_map(|language| &language.game_textures));
+    for game_texture in game_textures {
+        for texture in &game_texture.inner().textures {
+            let _ = texture.validate();
+            let _ = texture.to_dds();
+            let _ = texture.decode_rgba8();
+            let _ = texture.thumbnail_rgba8();
+        }
+    }
+});
@@ -0,0 +1,249 @@
+//! Decoders for the block compressed formats, BC1 to BC3 (DXT1, DXT3 and DXT5).
+//!
+//! Each function decodes `width` x `height` texels from `data` into `output` as RGBA, four bytes per texel.
+//! Blocks cover 4x4 texels, so the right and bottom edge blocks of textures whose dimensions aren't a multiple of
+//! four are only partly written.
+
+use crate::Error;
+
+type Block = [[u8; 4]; 16];
+
+fn expand_565(color: u16) -> [u8; 3] {
+    let (r, g, b) = ((color >> 11) & 0x1F, (color >> 5) & 0x3F, color & 0x1F);
+    [
+        ((r << 3) | (r >> 2)) as u8,
+        ((g << 2) | (g >> 4)) as u8,
+        ((b << 3) | (b >> 2)) as u8,
+    ]
+}
+
+fn mix(a: [u8; 3], b: [u8; 3], a_weight: u16, b_weight: u16) -> [u8; 3] {
+    let total = a_weight + b_weight;
+    let mix = |a: u8, b: u8| ((a as u16 * a_weight + b as u16 * b_weight) / total) as u8;
+    [mix(a[0], b[0]), mix(a[1], b[1]), mix(a[2], b[2])]
+}
+
+/// Decodes an 8 byte color block. BC2 and BC3 always use four colors, while BC1 switches to three colors and
+/// transparent black when the first endpoint isn't greater than the second.
+fn color_block(block: &[u8], punch_through: bool) -> Block {
+    let (c0, c1) = (
+        u16::from_le_bytes([block[0], block[1]]),
+        u16::from_le_bytes([block[2], block[3]]),
+    );
+    let (rgb0, rgb1) = (expand_565(c0), expand_565(c1));
+    let opaque = |[r, g, b]: [u8; 3]| [r, g, b, 0xFF];
+
+    let palette = if c0 > c1 || !punch_through {
+        [
+            opaque(rgb0),
+            opaque(rgb1),
+            opaque(mix(rgb0, rgb1, 2, 1)),
+            opaque(mix(rgb0, rgb1, 1, 2)),
+        ]
+    } else {
+        [opaque(rgb0), opaque(rgb1), opaque(mix(rgb0, rgb1, 1, 1)), [0; 4]]
+    };
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    let mut texels = [[0; 4]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        *texel = palette[(indices >> (i * 2)) as usize & 0b11];
+    }
+    texels
+}
+
+fn decode_bc1_block(block: &[u8]) -> Block {
+    color_block(block, true)
+}
+
+fn decode_bc2_block(block: &[u8]) -> Block {
+    let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+    let mut texels = color_block(&block[8..], false);
+    for (i, texel) in texels.iter_mut().enumerate() {
+        texel[3] = ((alpha >> (i * 4)) & 0xF) as u8 * 0x11;
+    }
+    texels
+}
+
+fn decode_bc3_block(block: &[u8]) -> Block {
+    let (a0, a1) = (block[0] as u16, block[1] as u16);
+    let mut alphas = [a0 as u8, a1 as u8, 0, 0, 0, 0, 0, 0xFF];
+    if a0 > a1 {
+        for (i, alpha) in alphas.iter_mut().enumerate().skip(2) {
+            let i = i as u16 - 1;
+            *alpha = ((a0 * (7 - i) + a1 * i) / 7) as u8;
+        }
+    } else {
+        for (i, alpha) in alphas.iter_mut().enumerate().take(6).skip(2) {
+            let i = i as u16 - 1;
+            *alpha = ((a0 * (5 - i) + a1 * i) / 5) as u8;
+        }
+    }
+
+    let mut indices = [0; 8];
+    indices[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(indices);
+    let mut texels = color_block(&block[8..], false);
+    for (i, texel) in texels.iter_mut().enumerate() {
+        texel[3] = alphas[(indices >> (i * 3)) as usize & 0b111];
+    }
+    texels
+}
+
+fn decode_blocks(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    output: &mut [u8],
+    block_size: usize,
+    decode_block: fn(&[u8]) -> Block,
+) -> Result<(), Error> {
+    let (blocks_wide, blocks_high) = (width.div_ceil(4), height.div_ceil(4));
+    let expected = blocks_wide * blocks_high * block_size;
+    if data.len() < expected {
+        return Err(Error::SizeMismatch {
+            what: "block compressed data",
+            expected,
+            found: data.len(),
+        });
+    }
+    if output.len() < width * height * 4 {
+        return Err(Error::SizeMismatch {
+            what: "RGBA output",
+            expected: width * height * 4,
+            found: output.len(),
+        });
+    }
+
+    for (index, block) in data[..expected].chunks_exact(block_size).enumerate() {
+        let (block_x, block_y) = ((index % blocks_wide) * 4, (index / blocks_wide) * 4);
+        let texels = decode_block(block);
+        for y in 0..4.min(height - block_y) {
+            for x in 0..4.min(width - block_x) {
+                let offset = ((block_y + y) * width + block_x + x) * 4;
+                output[offset..offset + 4].copy_from_slice(&texels[y * 4 + x]);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Decodes BC1 (DXT1), including its one bit punch-through alpha.
+pub fn decode_bc1(data: &[u8], width: usize, height: usize, output: &mut [u8]) -> Result<(), Error> {
+    decode_blocks(data, width, height, output, 8, decode_bc1_block)
+}
+
+/// Decodes BC2 (DXT3), with its explicit four bit alpha.
+pub fn decode_bc2(data: &[u8], width: usize, height: usize, output: &mut [u8]) -> Result<(), Error> {
+    decode_blocks(data, width, height, output, 16, decode_bc2_block)
+}
+
+/// Decodes BC3 (DXT5), with its interpolated alpha.
+pub fn decode_bc3(data: &[u8], width: usize, height: usize, output: &mut [u8]) -> Result<(), Error> {
+    decode_blocks(data, width, height, output, 16, decode_bc3_block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: u16 = 0xF800;
+    const BLUE: u16 = 0x001F;
+
+    fn color_block(c0: u16, c1: u16, indices: u32) -> Vec<u8> {
+        let mut block = c0.to_le_bytes().to_vec();
+        block.extend_from_slice(&c1.to_le_bytes());
+        block.extend_from_slice(&indices.to_le_bytes());
+        block
+    }
+
+    /// Indices 0, 1, 2 and 3 for the four texels of every row.
+    const EACH_INDEX: u32 = 0b11_10_01_00_11_10_01_00_11_10_01_00_11_10_01_00;
+
+    fn row(output: &[u8]) -> Vec<[u8; 4]> {
+        output[..16]
+            .chunks_exact(4)
+            .map(|texel| texel.try_into().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn bc1_four_colors() {
+        let mut output = [0; 64];
+        decode_bc1(&color_block(RED, BLUE, EACH_INDEX), 4, 4, &mut output).unwrap();
+        assert_eq!(
+            vec![[255, 0, 0, 255], [0, 0, 255, 255], [170, 0, 85, 255], [85, 0, 170, 255]],
+            row(&output)
+        );
+    }
+
+    #[test]
+    fn bc1_punch_through() {
+        let mut output = [0; 64];
+        decode_bc1(&color_block(BLUE, RED, EACH_INDEX), 4, 4, &mut output).unwrap();
+        assert_eq!(
+            vec![[0, 0, 255, 255], [255, 0, 0, 255], [127, 0, 127, 255], [0, 0, 0, 0]],
+            row(&output)
+        );
+    }
+
+    #[test]
+    fn bc2_alpha() {
+        let mut block = 0xFEDC_BA98_7654_3210u64.to_le_bytes().to_vec();
+        // BC2 never uses punch-through, even with the endpoints in this order.
+        block.extend(color_block(BLUE, RED, 0));
+        let mut output = [0; 64];
+        decode_bc2(&block, 4, 4, &mut output).unwrap();
+        let alphas: Vec<u8> = output.chunks_exact(4).map(|texel| texel[3]).collect();
+        assert_eq!((0..16).map(|i| i * 0x11).collect::<Vec<u8>>(), alphas);
+        assert_eq!([0, 0, 255], output[..3]);
+    }
+
+    #[test]
+    fn bc3_alpha() {
+        // Eight interpolated alphas, then six plus 0 and 255.
+        for ((a0, a1), expected) in [
+            ((255, 0), [255, 0, 218, 182, 145, 109, 72, 36]),
+            ((0, 255), [0, 255, 51, 102, 153, 204, 0, 255]),
+        ] {
+            let indices: u64 = (0..8).map(|i| i << (i * 3)).sum();
+            let mut block = vec![a0, a1];
+            block.extend_from_slice(&indices.to_le_bytes()[..6]);
+            block.extend(color_block(RED, BLUE, 0));
+            let mut output = [0; 64];
+            decode_bc3(&block, 4, 4, &mut output).unwrap();
+            let alphas: Vec<u8> = output.chunks_exact(4).take(8).map(|texel| texel[3]).collect();
+            assert_eq!(expected.to_vec(), alphas);
+        }
+    }
+
+    #[test]
+    fn partial_edge_blocks() {
+        // 5x5 texels need 2x2 blocks, of which only the first column and row of the last three are used.
+        let mut data = Vec::new();
+        for color in [RED, BLUE, BLUE, RED] {
+            data.extend(color_block(color, 0, 0));
+        }
+        let mut output = [0; 100];
+        decode_bc1(&data, 5, 5, &mut output).unwrap();
+
+        let texel = |x: usize, y: usize| &output[(y * 5 + x) * 4..][..4];
+        assert_eq!([255, 0, 0, 255], texel(3, 3));
+        assert_eq!([0, 0, 255, 255], texel(4, 0));
+        assert_eq!([0, 0, 255, 255], texel(0, 4));
+        assert_eq!([255, 0, 0, 255], texel(4, 4));
+    }
+
+    #[test]
+    fn short_data() {
+        let mut output = [0; 64];
+        assert!(matches!(
+            decode_bc3(&[0; 8], 4, 4, &mut output),
+            Err(Error::SizeMismatch {
+                expected: 16,
+                found: 8,
+                ..
+            })
+        ));
+    }
+}
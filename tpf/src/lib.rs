@@ -1,17 +1,94 @@
 use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
     fmt,
-    io::{Read, Seek, SeekFrom},
+    hash::{Hash, Hasher},
+    io,
+    io::{Read, Seek, SeekFrom, Write},
 };
 
-use binrw::{binrw, BinRead, BinReaderExt, BinResult, ReadOptions};
+use bitflags::bitflags;
 
-use common::{Path, Size};
+use binrw::{binrw, BinRead, BinReaderExt, BinResult, Endian};
+
+use common::{path_eq, PackVersion, Path, Size};
+use dds::{PitchAlignment, PixelFormat};
+
+pub mod bcn;
 
 pub const DEFAULT_LANGUAGE: LanguageId = LanguageId::English;
 
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Parse error at 0x{pos:X}: {message}")]
+    Parse { pos: u64, message: String },
+    #[error("While parsing {what}: Expected size {expected}, found size {found}.")]
+    SizeMismatch {
+        what: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    #[error("Unsupported texture format {0:?}.")]
+    UnsupportedFormat(TextureFormat),
+    #[error("Unknown texture format {0}.")]
+    UnknownFormat(u32),
+    #[error("Unsupported texture type {0:?}.")]
+    UnsupportedType(TextureType),
+    #[error("DDS pixel format has no texture format equivalent.")]
+    UnsupportedPixelFormat,
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Something about a [`Texture`] that parses fine but that the game may not handle well.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum Warning {
+    #[error("{width}x{height} isn't a power of two.")]
+    NonPowerOfTwo { width: usize, height: usize },
+    #[error("{found} mipmaps don't fit the dimensions, which allow at most {max}.")]
+    MipmapCount { max: usize, found: usize },
+    #[error("Expected {expected} bytes of data, found {found}.")]
+    DataSize { expected: usize, found: usize },
+}
+
+impl From<binrw::Error> for Error {
+    fn from(err: binrw::Error) -> Self {
+        match err {
+            binrw::Error::Io(err) => Error::Io(err),
+            binrw::Error::Custom { pos, err } => match err.downcast::<Error>() {
+                Ok(err) => *err,
+                Err(err) => Error::Parse {
+                    pos,
+                    message: err.to_string(),
+                },
+            },
+            binrw::Error::EnumErrors { pos, variant_errors } => {
+                // Surface our own error if any variant produced one, otherwise report the enum as a whole.
+                let message = format!("No variant matched ({} tried)", variant_errors.len());
+                variant_errors
+                    .into_iter()
+                    .map(|(_, err)| Error::from(err))
+                    .find(|err| !matches!(err, Error::Parse { .. }))
+                    .unwrap_or(Error::Parse { pos, message })
+            }
+            binrw::Error::BadMagic { pos, .. } | binrw::Error::NoVariantMatch { pos } => Error::Parse {
+                pos,
+                message: err.to_string(),
+            },
+            binrw::Error::AssertFail { pos, message } => Error::Parse { pos, message },
+            binrw::Error::Backtrace(backtrace) => Error::from(*backtrace.error),
+            err => Error::Parse {
+                pos: 0,
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde_repr::Serialize_repr, serde_repr::Deserialize_repr))]
 #[binrw]
 #[brw(repr = u16)]
+#[repr(u16)]
 pub enum LanguageId {
     English = 0,
     French,
@@ -19,6 +96,36 @@ pub enum LanguageId {
     Nonsense,
 }
 
+impl LanguageId {
+    /// Every language, in id order.
+    pub const ALL: [LanguageId; 4] = [
+        LanguageId::English,
+        LanguageId::French,
+        LanguageId::German,
+        LanguageId::Nonsense,
+    ];
+
+    /// The language's name in English, for listing localizations.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            LanguageId::English => "English",
+            LanguageId::French => "French",
+            LanguageId::German => "German",
+            LanguageId::Nonsense => "Nonsense (not a real language)",
+        }
+    }
+
+    /// A short lower case name for command lines and file names, unlike [`LanguageId::name`] a single word.
+    pub fn code(&self) -> &'static str {
+        match *self {
+            LanguageId::English => "english",
+            LanguageId::French => "french",
+            LanguageId::German => "german",
+            LanguageId::Nonsense => "nonsense",
+        }
+    }
+}
+
 impl Default for LanguageId {
     fn default() -> Self {
         DEFAULT_LANGUAGE
@@ -32,8 +139,10 @@ impl Size for LanguageId {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde_repr::Serialize_repr, serde_repr::Deserialize_repr))]
 #[binrw]
 #[brw(repr = u32)]
+#[repr(u32)]
 pub enum TextureFormat {
     A8R8G8B8 = 0,
     R8G8B8,
@@ -43,7 +152,7 @@ pub enum TextureFormat {
     R5G6B5,
     A8,
     L8,
-    // FIXME: This name is weird?
+    /// Four bits each of alpha and luminance, the only 8-bit layout with both.
     AL8,
     DXT1,
     DXT3,
@@ -53,15 +162,49 @@ pub enum TextureFormat {
     PAL8,
 }
 
+/// Reads a stored format, failing with [`Error::UnknownFormat`] for values past the last one known.
+impl TryFrom<u32> for TextureFormat {
+    type Error = Error;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => TextureFormat::A8R8G8B8,
+            1 => TextureFormat::R8G8B8,
+            2 => TextureFormat::A4R4G4B4,
+            3 => TextureFormat::A1R5G5B5,
+            4 => TextureFormat::X1R5G5B5,
+            5 => TextureFormat::R5G6B5,
+            6 => TextureFormat::A8,
+            7 => TextureFormat::L8,
+            8 => TextureFormat::AL8,
+            9 => TextureFormat::DXT1,
+            10 => TextureFormat::DXT3,
+            11 => TextureFormat::DXT5,
+            12 => TextureFormat::V8U8,
+            13 => TextureFormat::V16U16,
+            14 => TextureFormat::PAL8,
+            value => return Err(Error::UnknownFormat(value)),
+        })
+    }
+}
+
 impl Size for TextureFormat {
     fn size(&self) -> usize {
         4
     }
 }
 
+impl fmt::Display for TextureFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde_repr::Serialize_repr, serde_repr::Deserialize_repr))]
 #[binrw]
 #[brw(repr = u32)]
+#[repr(u32)]
 pub enum TextureType {
     Bitmap = 0,
     Cubemap,
@@ -75,9 +218,17 @@ impl Size for TextureType {
     }
 }
 
+impl fmt::Display for TextureType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde_repr::Serialize_repr, serde_repr::Deserialize_repr))]
 #[binrw]
 #[brw(repr = u32)]
+#[repr(u32)]
 pub enum PlayMode {
     Loop = 0,
     LoopOnce,
@@ -89,6 +240,37 @@ pub enum PlayMode {
     Stop,
 }
 
+impl PlayMode {
+    /// Whether the animation repeats until it's stopped.
+    pub fn is_looping(self) -> bool {
+        matches!(self, PlayMode::Loop | PlayMode::LoopTail | PlayMode::Oscillate)
+    }
+
+    /// Whether the animation plays through a single time and then stops.
+    pub fn is_once(self) -> bool {
+        matches!(
+            self,
+            PlayMode::LoopOnce | PlayMode::OscillateOnce | PlayMode::OscillateOutOnce | PlayMode::OscillateBackOnce
+        )
+    }
+
+    /// Whether the mode is one of the oscillating family, including the ones that only play half of it.
+    pub fn is_oscillating(self) -> bool {
+        matches!(
+            self,
+            PlayMode::Oscillate | PlayMode::OscillateOnce | PlayMode::OscillateOutOnce | PlayMode::OscillateBackOnce
+        )
+    }
+
+    /// Whether any frames are played from last to first. `OscillateOutOnce` only plays the forward half.
+    pub fn reverses(self) -> bool {
+        matches!(
+            self,
+            PlayMode::Oscillate | PlayMode::OscillateOnce | PlayMode::OscillateBackOnce
+        )
+    }
+}
+
 impl Size for PlayMode {
     fn size(&self) -> usize {
         4
@@ -111,6 +293,14 @@ pub enum Version {
     V1,
 }
 
+impl PackVersion for Version {
+    const VERSION_MAGIC: Option<[u8; 2]> = Some(*b"\xFD\xFD");
+
+    fn version_number(&self) -> Option<u16> {
+        Some(*self as u16)
+    }
+}
+
 impl Size for Version {
     fn size(&self) -> usize {
         4
@@ -123,7 +313,7 @@ impl Default for Version {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Size)]
 #[binrw]
 pub struct AnimationInfo {
     #[br(assert(frame_count > 0, "Invalid frame count {}", frame_count))]
@@ -139,16 +329,12 @@ pub struct AnimationInfo {
     #[br(map = |x: u8| x != 0)]
     #[bw(map = |x: &bool| *x as u8)]
     #[brw(pad_after = 3)]
+    #[size(bytes = 1, pad_after = 3)]
     pub playing: bool,
 }
 
-impl Size for AnimationInfo {
-    fn size(&self) -> usize {
-        28
-    }
-}
-
 #[binrw]
+#[derive(PartialEq, Eq)]
 pub struct Palette {
     #[br(temp)]
     #[bw(calc = data.is_some() as u16)]
@@ -170,36 +356,744 @@ impl Size for Palette {
     }
 }
 
+bitflags! {
+    /// Flags stored on each [`Texture`].
+    ///
+    /// No bit has a confirmed meaning yet, so each is named after its position, `BIT_n` being the mask `1 << n`, and
+    /// every bit is kept as read.
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct TextureFlags: u32 {
+        const BIT_0 = 1 << 0;
+        const BIT_1 = 1 << 1;
+        const BIT_2 = 1 << 2;
+        const BIT_3 = 1 << 3;
+        const BIT_4 = 1 << 4;
+        const BIT_5 = 1 << 5;
+        const BIT_6 = 1 << 6;
+        const BIT_7 = 1 << 7;
+        const BIT_8 = 1 << 8;
+        const BIT_9 = 1 << 9;
+        const BIT_10 = 1 << 10;
+        const BIT_11 = 1 << 11;
+        const BIT_12 = 1 << 12;
+        const BIT_13 = 1 << 13;
+        const BIT_14 = 1 << 14;
+        const BIT_15 = 1 << 15;
+        const BIT_16 = 1 << 16;
+        const BIT_17 = 1 << 17;
+        const BIT_18 = 1 << 18;
+        const BIT_19 = 1 << 19;
+        const BIT_20 = 1 << 20;
+        const BIT_21 = 1 << 21;
+        const BIT_22 = 1 << 22;
+        const BIT_23 = 1 << 23;
+        const BIT_24 = 1 << 24;
+        const BIT_25 = 1 << 25;
+        const BIT_26 = 1 << 26;
+        const BIT_27 = 1 << 27;
+        const BIT_28 = 1 << 28;
+        const BIT_29 = 1 << 29;
+        const BIT_30 = 1 << 30;
+        const BIT_31 = 1 << 31;
+    }
+
+    /// Flags stored on each [`GameTexture`], named and kept the same way as [`TextureFlags`].
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct GameTextureFlags: u32 {
+        const BIT_0 = 1 << 0;
+        const BIT_1 = 1 << 1;
+        const BIT_2 = 1 << 2;
+        const BIT_3 = 1 << 3;
+        const BIT_4 = 1 << 4;
+        const BIT_5 = 1 << 5;
+        const BIT_6 = 1 << 6;
+        const BIT_7 = 1 << 7;
+        const BIT_8 = 1 << 8;
+        const BIT_9 = 1 << 9;
+        const BIT_10 = 1 << 10;
+        const BIT_11 = 1 << 11;
+        const BIT_12 = 1 << 12;
+        const BIT_13 = 1 << 13;
+        const BIT_14 = 1 << 14;
+        const BIT_15 = 1 << 15;
+        const BIT_16 = 1 << 16;
+        const BIT_17 = 1 << 17;
+        const BIT_18 = 1 << 18;
+        const BIT_19 = 1 << 19;
+        const BIT_20 = 1 << 20;
+        const BIT_21 = 1 << 21;
+        const BIT_22 = 1 << 22;
+        const BIT_23 = 1 << 23;
+        const BIT_24 = 1 << 24;
+        const BIT_25 = 1 << 25;
+        const BIT_26 = 1 << 26;
+        const BIT_27 = 1 << 27;
+        const BIT_28 = 1 << 28;
+        const BIT_29 = 1 << 29;
+        const BIT_30 = 1 << 30;
+        const BIT_31 = 1 << 31;
+    }
+}
+
+#[derive(PartialEq, Eq, Size)]
 #[binrw]
 pub struct Texture {
     #[brw(pad_before = 4)]
+    #[br(try_map = |format: u32| TextureFormat::try_from(format))]
+    #[size(pad_before = 4)]
     pub format: TextureFormat,
+    #[br(assert(matches!(type_, TextureType::Bitmap | TextureType::Cubemap), Error::UnsupportedType(type_)))]
+    #[bw(assert(matches!(type_, TextureType::Bitmap | TextureType::Cubemap), Error::UnsupportedType(*type_)))]
     pub type_: TextureType,
-    pub flags: u32,
+    #[br(map = TextureFlags::from_bits_retain)]
+    #[bw(map = |x: &TextureFlags| x.bits())]
+    #[size(bytes = 4)]
+    pub flags: TextureFlags,
     #[br(try_map = |x: u32| x.try_into())]
     #[bw(map = |x: &usize| *x as u32)]
+    #[size(bytes = 4)]
     pub width: usize,
     #[br(try_map = |x: u32| x.try_into())]
     #[bw(map = |x: &usize| *x as u32)]
+    #[size(bytes = 4)]
     pub height: usize,
     #[brw(pad_after = 16)]
     #[br(try_map = |x: u32| x.try_into().map(|mipmaps| calculate_mipmaps(mipmaps, width, height)))]
     #[bw(map = |x: &usize| *x as u32)]
+    #[size(bytes = 4, pad_after = 16)]
     pub mipmaps: usize,
     #[br(if(format == TextureFormat::PAL8))]
     pub palette: Option<Palette>,
-    #[br(count = calculate_texture_size(format, type_, width, height, mipmaps))]
-    #[bw(assert(data.len() == calculate_texture_size(*format, *type_, *width, *height, *mipmaps), "While writing Texture: Expected data length {}, found {}", calculate_texture_size(*format, *type_, *width, *height, *mipmaps), data.len()))]
+    // `type_` is asserted to be one `texture_size` knows above, so the sizes here are never `None`.
+    #[br(parse_with = data_parser, args(texture_size(format, type_, width, height, mipmaps).unwrap_or(0)))]
+    #[br(assert(Some(data.len()) == texture_size(format, type_, width, height, mipmaps), "While parsing Texture: Expected {} bytes of data for a {}x{} {:?} texture with {} mipmaps, found {}.", texture_size(format, type_, width, height, mipmaps).unwrap_or(0), width, height, format, mipmaps, data.len()))]
+    #[bw(assert(Some(data.len()) == texture_size(*format, *type_, self.width, self.height, self.mipmaps), "While writing Texture: Expected data length {}, found {}", texture_size(*format, *type_, self.width, self.height, self.mipmaps).unwrap_or(0), data.len()))]
+    // The data is written as it is, so it takes exactly its own length.
+    #[size(bytes = self.data.len())]
     pub data: Vec<u8>,
 }
 
-impl Size for Texture {
-    fn size(&self) -> usize {
-        4 + self.format.size()
-            + self.type_.size()
-            + 32
-            + self.palette.size()
-            + calculate_texture_size(self.format, self.type_, self.width, self.height, self.mipmaps)
+/// Which DDS header variant a [`Texture`] is exported with, see [`Texture::to_dds_with`].
+///
+/// The game disagrees with itself and the DDS spec about two formats: `LoadTextureFromDDSStream` loads `L8` from
+/// an `A8` header, and its OpenGL path uses `X8R8G8B8` for `R8G8B8` while the DDS stream path uses `R8G8B8`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DdsCompat {
+    /// The pixel formats the DDS spec gives, as [`TextureFormat::pixel_format`] does.
+    #[default]
+    Dds,
+    /// `L8` as `A8`, and `R8G8B8` widened to `X8R8G8B8`.
+    Game,
+}
+
+/// Everything about a [`Texture`] except its pixel data.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TextureMetadata {
+    pub format: TextureFormat,
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub type_: TextureType,
+    pub flags: u32,
+    pub width: usize,
+    pub height: usize,
+    pub mipmaps: usize,
+    pub has_palette: bool,
+}
+
+impl Texture {
+    pub fn metadata(&self) -> TextureMetadata {
+        TextureMetadata {
+            format: self.format,
+            type_: self.type_,
+            flags: self.flags.bits(),
+            width: self.width,
+            height: self.height,
+            mipmaps: self.mipmaps,
+            has_palette: self.palette.is_some(),
+        }
+    }
+
+    /// Rebuilds a texture from its [`Texture::metadata`], palette and data as stored, checking what parsing would.
+    /// `palette` is only used when the metadata says there is one.
+    pub fn from_raw(metadata: TextureMetadata, palette: Option<[u32; 0x100]>, data: Vec<u8>) -> Result<Texture, Error> {
+        let expected = texture_size(
+            metadata.format,
+            metadata.type_,
+            metadata.width,
+            metadata.height,
+            metadata.mipmaps,
+        )
+        .ok_or(Error::UnsupportedType(metadata.type_))?;
+        if data.len() != expected {
+            return Err(Error::SizeMismatch {
+                what: "Texture",
+                expected,
+                found: data.len(),
+            });
+        }
+
+        Ok(Texture {
+            format: metadata.format,
+            type_: metadata.type_,
+            flags: TextureFlags::from_bits_retain(metadata.flags),
+            width: metadata.width,
+            height: metadata.height,
+            mipmaps: metadata.mipmaps,
+            palette: metadata.has_palette.then_some(Palette { data: palette }),
+            data,
+        })
+    }
+
+    /// Whether the data is exactly as long as the format, dimensions and mip count call for. Parsing already rejects
+    /// textures where it isn't, so this only catches ones built or modified in memory. Always `false` for the types
+    /// [`texture_size`] can't size.
+    pub fn data_matches_header(&self) -> bool {
+        Some(self.data.len()) == texture_size(self.format, self.type_, self.width, self.height, self.mipmaps)
+    }
+
+    /// Checks for non power of two dimensions, mip counts that don't fit them and data of the wrong length.
+    pub fn validate(&self) -> Result<(), Vec<Warning>> {
+        let mut warnings = Vec::new();
+
+        if !self.width.is_power_of_two() || !self.height.is_power_of_two() {
+            warnings.push(Warning::NonPowerOfTwo {
+                width: self.width,
+                height: self.height,
+            });
+        }
+
+        let max = mipmap_count(self.width, self.height);
+        if self.mipmaps == 0 || self.mipmaps > max {
+            warnings.push(Warning::MipmapCount {
+                max,
+                found: self.mipmaps,
+            });
+        }
+
+        if let Some(expected) = texture_size(self.format, self.type_, self.width, self.height, self.mipmaps) {
+            if self.data.len() != expected {
+                warnings.push(Warning::DataSize {
+                    expected,
+                    found: self.data.len(),
+                });
+            }
+        }
+
+        if warnings.is_empty() {
+            Ok(())
+        } else {
+            Err(warnings)
+        }
+    }
+
+    /// Decodes the top mip level of a bump map into an RGBA normal map, rebuilding Z from the signed U and V.
+    pub fn decode_normal_rgba8(&self) -> Result<Vec<u8>, Error> {
+        match self.format {
+            TextureFormat::V8U8 | TextureFormat::V16U16 => {
+                decode_normal(self.format, self.width, self.height, &self.data)
+            }
+            format => Err(Error::UnsupportedFormat(format)),
+        }
+    }
+
+    pub fn is_cubemap(&self) -> bool {
+        self.type_ == TextureType::Cubemap
+    }
+
+    pub fn is_volume(&self) -> bool {
+        self.type_ == TextureType::VolumeMap
+    }
+
+    /// The faces stored one after another in the data, each with its own mip chain: 6 for a cubemap and 1 otherwise,
+    /// as [`texture_size`] counts them.
+    pub fn face_count(&self) -> usize {
+        if self.is_cubemap() {
+            6
+        } else {
+            1
+        }
+    }
+
+    /// The depth slices of each face, or `None` for volume maps and depth buffers, whose layout is unknown and which
+    /// [`texture_size`] can't size either.
+    pub fn slice_count(&self) -> Option<usize> {
+        match self.type_ {
+            TextureType::Bitmap | TextureType::Cubemap => Some(1),
+            TextureType::VolumeMap | TextureType::DepthBuffer => None,
+        }
+    }
+
+    /// Mip level `level` of the first face, or `None` past the last level or when the data is too short for it.
+    pub fn mip_level(&self, level: usize) -> Option<MipLevel<'_>> {
+        if level >= self.mipmaps {
+            return None;
+        }
+        let size = |levels| bitmap_size(self.format, self.width, self.height, levels);
+        Some(MipLevel {
+            width: mip_dimension(self.width, level),
+            height: mip_dimension(self.height, level),
+            data: self.data.get(size(level)..size(level + 1))?,
+        })
+    }
+
+    /// Decodes the top mip level into RGBA, four bytes per texel.
+    pub fn decode_rgba8(&self) -> Result<Vec<u8>, Error> {
+        self.decode_level_rgba8(0).map(|(_, _, rgba)| rgba)
+    }
+
+    /// Decodes only the smallest mip level into RGBA, returning its width and height along with the texels. Textures
+    /// without a mip chain decode their single level.
+    pub fn thumbnail_rgba8(&self) -> Result<(usize, usize, Vec<u8>), Error> {
+        self.decode_level_rgba8(self.mipmaps.max(1) - 1)
+    }
+
+    /// Decodes the top mip level like [`Texture::decode_rgba8`], then rearranges each texel, see [`reorder_rgba8`].
+    pub fn reorder_channels(&self, order: [Channel; 4]) -> Result<Vec<u8>, Error> {
+        Ok(reorder_rgba8(&self.decode_rgba8()?, order))
+    }
+
+    /// Decodes the top mip level like [`Texture::decode_rgba8`], writing it to `out` as it goes rather than building
+    /// the whole image first. Only a row of texels is held at a time, or a row of 4x4 blocks for the compressed
+    /// formats.
+    pub fn decode_to<W: Write>(&self, out: &mut W) -> Result<(), Error> {
+        self.decode_level_rows(0, |rgba| Ok(out.write_all(rgba)?))?;
+        Ok(())
+    }
+
+    fn decode_level_rgba8(&self, level: usize) -> Result<(usize, usize, Vec<u8>), Error> {
+        let mut rgba = Vec::new();
+        let (width, height) = self.decode_level_rows(level, |rows| {
+            rgba.extend_from_slice(rows);
+            Ok(())
+        })?;
+        Ok((width, height, rgba))
+    }
+
+    /// Decodes mip level `level` a row at a time, or a row of blocks for the compressed formats, handing each to
+    /// `emit` as RGBA. Returns the level's width and height.
+    fn decode_level_rows(
+        &self,
+        level: usize,
+        mut emit: impl FnMut(&[u8]) -> Result<(), Error>,
+    ) -> Result<(usize, usize), Error> {
+        let mip = self.mip_level(level).ok_or_else(|| Error::SizeMismatch {
+            what: "Texture",
+            expected: bitmap_size(self.format, self.width, self.height, level + 1),
+            found: self.data.len(),
+        })?;
+        let (width, height, data) = (mip.width, mip.height, mip.data);
+
+        let (rows_per_chunk, chunk_size) = if self.format.compressed() {
+            (4, width.div_ceil(4) * self.format.block_size())
+        } else {
+            (1, width * self.format.bytes_per_pixel())
+        };
+        // Grown by the first chunk and reused after that. Sizing it up front from `width` would trust dimensions the
+        // data may not back.
+        let mut rgba = Vec::new();
+        for (index, chunk) in data.chunks(chunk_size).enumerate() {
+            let rows = rows_per_chunk.min(height - index * rows_per_chunk);
+            rgba.clear();
+            self.decode_texels(chunk, width, rows, &mut rgba)?;
+            emit(&rgba)?;
+        }
+        Ok((width, height))
+    }
+
+    /// Appends the RGBA of `width` x `height` texels stored in `data` to `rgba`.
+    fn decode_texels(&self, data: &[u8], width: usize, height: usize, rgba: &mut Vec<u8>) -> Result<(), Error> {
+        let expand = |value: u16, bits: u32| {
+            let max = (1 << bits) - 1;
+            ((value & max) as u32 * 0xFF / max as u32) as u8
+        };
+        let words = || data.chunks_exact(2).map(|word| u16::from_le_bytes([word[0], word[1]]));
+        match self.format {
+            TextureFormat::A8R8G8B8 => rgba.extend(data.chunks_exact(4).flat_map(|t| [t[2], t[1], t[0], t[3]])),
+            TextureFormat::R8G8B8 => rgba.extend(data.chunks_exact(3).flat_map(|t| [t[2], t[1], t[0], 0xFF])),
+            TextureFormat::R5G6B5 => {
+                rgba.extend(words().flat_map(|w| [expand(w >> 11, 5), expand(w >> 5, 6), expand(w, 5), 0xFF]))
+            }
+            TextureFormat::A1R5G5B5 | TextureFormat::X1R5G5B5 => {
+                let alpha = self.format == TextureFormat::A1R5G5B5;
+                rgba.extend(words().flat_map(|w| {
+                    let a = if !alpha || w & 0x8000 != 0 { 0xFF } else { 0 };
+                    [expand(w >> 10, 5), expand(w >> 5, 5), expand(w, 5), a]
+                }))
+            }
+            TextureFormat::A4R4G4B4 => rgba
+                .extend(words().flat_map(|w| [expand(w >> 8, 4), expand(w >> 4, 4), expand(w, 4), expand(w >> 12, 4)])),
+            TextureFormat::A8 => rgba.extend(data.iter().flat_map(|&a| [0, 0, 0, a])),
+            TextureFormat::L8 => rgba.extend(data.iter().flat_map(|&l| [l, l, l, 0xFF])),
+            TextureFormat::AL8 => rgba.extend(data.iter().flat_map(|&t| {
+                let l = (t & 0xF) * 0x11;
+                [l, l, l, (t >> 4) * 0x11]
+            })),
+            TextureFormat::PAL8 => {
+                let palette =
+                    self.palette
+                        .as_ref()
+                        .and_then(|palette| palette.data.as_ref())
+                        .ok_or(Error::SizeMismatch {
+                            what: "Palette",
+                            expected: 0x100,
+                            found: 0,
+                        })?;
+                rgba.extend(data.iter().flat_map(|&index| palette[index as usize].to_le_bytes()))
+            }
+            TextureFormat::V8U8 | TextureFormat::V16U16 => {
+                rgba.extend(decode_normal(self.format, width, height, data)?)
+            }
+            TextureFormat::DXT1 | TextureFormat::DXT3 | TextureFormat::DXT5 => {
+                let start = rgba.len();
+                rgba.resize(start + width * height * 4, 0);
+                let output = &mut rgba[start..];
+                match self.format {
+                    TextureFormat::DXT1 => bcn::decode_bc1(data, width, height, output)?,
+                    TextureFormat::DXT3 => bcn::decode_bc2(data, width, height, output)?,
+                    _ => bcn::decode_bc3(data, width, height, output)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A channel of an RGBA texel, see [`reorder_rgba8`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    /// Leaves the position out, so fewer than four bytes are written per texel.
+    Skip,
+}
+
+/// Rearranges RGBA texels, writing the channels `order` names in its order. `[Blue, Green, Red, Alpha]` gives BGRA,
+/// and `[Red, Green, Blue, Skip]` drops the alpha to give RGB.
+pub fn reorder_rgba8(rgba: &[u8], order: [Channel; 4]) -> Vec<u8> {
+    let indices: Vec<usize> = order
+        .iter()
+        .filter_map(|channel| match channel {
+            Channel::Red => Some(0),
+            Channel::Green => Some(1),
+            Channel::Blue => Some(2),
+            Channel::Alpha => Some(3),
+            Channel::Skip => None,
+        })
+        .collect();
+
+    let mut output = Vec::with_capacity(rgba.len() / 4 * indices.len());
+    for texel in rgba.chunks_exact(4) {
+        output.extend(indices.iter().map(|&index| texel[index]));
+    }
+    output
+}
+
+/// Remembers what [`Texture::decode_rgba8`] gave for each texture, so textures exported more than once are only
+/// decoded the first time.
+///
+/// The frames of an animation, and sometimes unrelated textures, share a handle, so entries are keyed by the handle
+/// and a hash of the texture's contents, and a hit is only taken when the texture it was decoded from is equal.
+#[derive(Debug, Default)]
+pub struct DecodeCache<'a> {
+    decoded: HashMap<(u32, u64), Vec<Decoded<'a>>>,
+}
+
+/// A texture [`DecodeCache`] has decoded, with its RGBA texels.
+type Decoded<'a> = (&'a Texture, Vec<u8>);
+
+impl<'a> DecodeCache<'a> {
+    pub fn new() -> DecodeCache<'a> {
+        DecodeCache::default()
+    }
+
+    /// The RGBA texels of `texture`'s top mip level, decoded now or the last time an equal texture was asked for under
+    /// `handle`.
+    pub fn get_or_decode(&mut self, handle: u32, texture: &'a Texture) -> Result<&[u8], Error> {
+        let mut hasher = DefaultHasher::new();
+        (texture.format, texture.width, texture.height, &texture.data).hash(&mut hasher);
+        texture
+            .palette
+            .as_ref()
+            .and_then(|palette| palette.data)
+            .hash(&mut hasher);
+
+        let entries = self.decoded.entry((handle, hasher.finish())).or_default();
+        let index = match entries
+            .iter()
+            .position(|(other, _)| std::ptr::eq(*other, texture) || *other == texture)
+        {
+            Some(index) => index,
+            None => {
+                entries.push((texture, texture.decode_rgba8()?));
+                entries.len() - 1
+            }
+        };
+        Ok(&entries[index].1)
+    }
+
+    /// The number of textures decoded so far.
+    pub fn len(&self) -> usize {
+        self.decoded.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// One mip level of a [`Texture`], see [`Texture::mip_level`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MipLevel<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub data: &'a [u8],
+}
+
+fn decode_normal(format: TextureFormat, width: usize, height: usize, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let (bytes_per_pixel, max) = match format {
+        TextureFormat::V8U8 => (2, i8::MAX as f32),
+        _ => (4, i16::MAX as f32),
+    };
+
+    let texels = width * height;
+    let expected = texels * bytes_per_pixel;
+    if data.len() < expected {
+        return Err(Error::SizeMismatch {
+            what: "Texture",
+            expected,
+            found: data.len(),
+        });
+    }
+
+    let to_unsigned = |x: f32| ((x * 0.5 + 0.5) * 255.0).round() as u8;
+    let mut rgba = Vec::with_capacity(texels * 4);
+    for texel in data[..expected].chunks_exact(bytes_per_pixel) {
+        let (u, v) = match format {
+            TextureFormat::V8U8 => (texel[0] as i8 as f32, texel[1] as i8 as f32),
+            _ => (
+                i16::from_le_bytes([texel[0], texel[1]]) as f32,
+                i16::from_le_bytes([texel[2], texel[3]]) as f32,
+            ),
+        };
+        let (x, y) = ((u / max).clamp(-1.0, 1.0), (v / max).clamp(-1.0, 1.0));
+        let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+        rgba.extend_from_slice(&[to_unsigned(x), to_unsigned(y), to_unsigned(z), 0xFF]);
+    }
+    Ok(rgba)
+}
+
+impl Texture {
+    pub fn dds_header(&self) -> Result<dds::Header, Error> {
+        self.dds_header_with(PitchAlignment::Byte)
+    }
+
+    /// Like [`Texture::dds_header`], with the pitch aligned as `alignment` says.
+    pub fn dds_header_with(&self, alignment: PitchAlignment) -> Result<dds::Header, Error> {
+        let pixel_format = self
+            .format
+            .pixel_format()
+            .ok_or(Error::UnsupportedFormat(self.format))?;
+        Ok(dds::Header::for_texture_with(
+            pixel_format,
+            self.width as u32,
+            self.height as u32,
+            self.mipmaps as u32,
+            self.is_cubemap(),
+            alignment,
+        ))
+    }
+
+    /// The texture as a whole DDS file, the inverse of [`Texture::from_dds`].
+    pub fn to_dds(&self) -> Result<dds::Dds, Error> {
+        self.to_dds_with(DdsCompat::Dds)
+    }
+
+    /// Like [`Texture::to_dds`], with the header variant `compat` picks. Only [`Texture::to_dds`] files can be
+    /// read back with [`Texture::from_dds`].
+    pub fn to_dds_with(&self, compat: DdsCompat) -> Result<dds::Dds, Error> {
+        self.to_dds_aligned(compat, PitchAlignment::Byte)
+    }
+
+    /// Like [`Texture::to_dds_with`], with the rows of uncompressed formats padded as `alignment` says.
+    /// [`Texture::from_dds`] reads [`PitchAlignment::Dword`] files back too.
+    pub fn to_dds_aligned(&self, compat: DdsCompat, alignment: PitchAlignment) -> Result<dds::Dds, Error> {
+        let mut header = self.dds_header_with(alignment)?;
+        let mut texels = self.data.clone();
+
+        if compat == DdsCompat::Game {
+            match self.format {
+                TextureFormat::L8 => header.pixel_format = PixelFormat::A8,
+                TextureFormat::R8G8B8 => {
+                    header.pixel_format = PixelFormat::X8R8G8B8;
+                    header.pitch_or_linear_size = self.width as u32 * 4;
+                    texels = texels
+                        .chunks_exact(3)
+                        .flat_map(|bgr| [bgr[0], bgr[1], bgr[2], 0xFF])
+                        .collect();
+                }
+                _ => {}
+            }
+        }
+        if !self.format.compressed() {
+            texels = self.realign_rows(
+                &texels,
+                header.pixel_format.bits_per_pixel(),
+                PitchAlignment::Byte,
+                alignment,
+            )?;
+        }
+
+        let mut data = Vec::with_capacity(texels.len() + 0x400);
+        if let Some(Palette { data: Some(palette) }) = &self.palette {
+            data.extend(palette.iter().flat_map(|entry| entry.to_le_bytes()));
+        }
+        data.extend(texels);
+
+        Ok(dds::Dds {
+            header,
+            header10: None,
+            data,
+        })
+    }
+
+    /// Builds a texture from a DDS header and the data following it, palette included for PAL8.
+    pub fn from_dds(header: &dds::Header, data: &[u8]) -> Result<Texture, Error> {
+        let format = TextureFormat::from_pixel_format(&header.pixel_format).ok_or(Error::UnsupportedPixelFormat)?;
+        let type_ = if header.caps2.contains(dds::Caps2::VOLUME) {
+            return Err(Error::UnsupportedType(TextureType::VolumeMap));
+        } else if header.caps2.contains(dds::Caps2::CUBEMAP) {
+            if !header.caps2.contains(dds::CUBEMAP_ALLFACES) {
+                return Err(Error::UnsupportedType(TextureType::Cubemap));
+            }
+            TextureType::Cubemap
+        } else {
+            TextureType::Bitmap
+        };
+
+        let (width, height) = (header.width as usize, header.height as usize);
+        let mipmaps = if header.header_flags.contains(dds::HEADER_FLAGS_MIPMAP) {
+            (header.mip_map_count as usize).max(1)
+        } else {
+            1
+        };
+
+        let top_level = bitmap_size(format, width, height, 1);
+        let mut alignment = PitchAlignment::Byte;
+        if format.compressed() && header.header_flags.contains(dds::HEADER_FLAGS_LINEARSIZE) {
+            if header.pitch_or_linear_size as usize != top_level {
+                return Err(Error::SizeMismatch {
+                    what: "DDS linear size",
+                    expected: top_level,
+                    found: header.pitch_or_linear_size as usize,
+                });
+            }
+        } else if !format.compressed() && header.header_flags.contains(dds::HEADER_FLAGS_PITCH) {
+            let pitch = |alignment: PitchAlignment| alignment.pitch(width as u32, header.pixel_format.rgb_bit_count);
+            if header.pitch_or_linear_size == pitch(PitchAlignment::Dword) {
+                alignment = PitchAlignment::Dword;
+            } else if header.pitch_or_linear_size != pitch(PitchAlignment::Byte) {
+                return Err(Error::SizeMismatch {
+                    what: "DDS pitch",
+                    expected: pitch(PitchAlignment::Byte) as usize,
+                    found: header.pitch_or_linear_size as usize,
+                });
+            }
+        }
+
+        let (palette, data) = if format == TextureFormat::PAL8 {
+            if data.len() < 0x400 {
+                return Err(Error::SizeMismatch {
+                    what: "DDS palette",
+                    expected: 0x400,
+                    found: data.len(),
+                });
+            }
+            let (entries, data) = data.split_at(0x400);
+            let mut palette = [0; 0x100];
+            for (entry, bytes) in palette.iter_mut().zip(entries.chunks_exact(4)) {
+                *entry = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            }
+            (Some(Palette { data: Some(palette) }), data)
+        } else {
+            (None, data)
+        };
+
+        let mut texture = Texture {
+            format,
+            type_,
+            flags: TextureFlags::empty(),
+            width,
+            height,
+            mipmaps,
+            palette,
+            data: Vec::new(),
+        };
+        texture.data = match alignment {
+            PitchAlignment::Byte => data.to_vec(),
+            PitchAlignment::Dword => {
+                texture.realign_rows(data, header.pixel_format.rgb_bit_count, alignment, PitchAlignment::Byte)?
+            }
+        };
+
+        let expected = texture_size(format, type_, width, height, mipmaps).ok_or(Error::UnsupportedType(type_))?;
+        if texture.data.len() != expected {
+            return Err(Error::SizeMismatch {
+                what: "DDS data",
+                expected,
+                found: texture.data.len(),
+            });
+        }
+        Ok(texture)
+    }
+
+    /// Copies the rows of every face and mip level of uncompressed `data` from one pitch alignment to another,
+    /// failing unless `data` holds exactly the rows `from` pads.
+    fn realign_rows(
+        &self,
+        data: &[u8],
+        bits_per_pixel: u32,
+        from: PitchAlignment,
+        to: PitchAlignment,
+    ) -> Result<Vec<u8>, Error> {
+        if from == to {
+            return Ok(data.to_vec());
+        }
+
+        let levels = (0..self.face_count())
+            .flat_map(|_| 0..self.mipmaps.max(1))
+            .map(|level| {
+                let (width, height) = (
+                    mip_dimension(self.width, level) as u32,
+                    mip_dimension(self.height, level),
+                );
+                let row = PitchAlignment::Byte.pitch(width, bits_per_pixel) as usize;
+                let (from, to) = (
+                    from.pitch(width, bits_per_pixel) as usize,
+                    to.pitch(width, bits_per_pixel) as usize,
+                );
+                (height, row, from, to)
+            });
+        let expected = levels.clone().map(|(height, _, from, _)| height * from).sum();
+        if data.len() != expected {
+            return Err(Error::SizeMismatch {
+                what: "DDS data",
+                expected,
+                found: data.len(),
+            });
+        }
+
+        let mut output = Vec::with_capacity(levels.clone().map(|(height, _, _, to)| height * to).sum());
+        let mut offset = 0;
+        for (height, row, from, to) in levels {
+            for _ in 0..height {
+                output.extend_from_slice(&data[offset..offset + row]);
+                output.resize(output.len() + to - row, 0);
+                offset += from;
+            }
+        }
+        Ok(output)
     }
 }
 
@@ -208,6 +1102,7 @@ impl fmt::Debug for Texture {
         f.debug_struct("Texture")
             .field("format", &self.format)
             .field("type", &self.type_)
+            .field("flags", &self.flags)
             .field("width", &self.width)
             .field("height", &self.height)
             .field("mipmaps", &self.mipmaps)
@@ -234,7 +1129,9 @@ pub mod v0 {
         pub visual_importance: u32,
         pub memory_importance: u32,
         pub unknown0: u32,
-        pub flags: u32,
+        #[br(map = GameTextureFlags::from_bits_retain)]
+        #[bw(map = |x: &GameTextureFlags| x.bits())]
+        pub flags: GameTextureFlags,
         #[br(if(path_pointer != 0))]
         pub path: Option<Path>,
         #[br(if(animation_info_pointer != 0))]
@@ -247,6 +1144,7 @@ pub mod v0 {
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             f.debug_struct("GameTexture")
                 .field("path", &self.path)
+                .field("flags", &self.flags)
                 .field("animation_info", &self.animation_info)
                 .field("textures", &self.textures)
                 .finish()
@@ -264,7 +1162,7 @@ pub mod v1 {
     use super::*;
 
     #[binrw]
-    #[br(assert(size == game_texture.size() as u32, "While parsing v1::GameTexture: Expected size {}, found size {}.", size, game_texture.size()))]
+    #[br(assert(size == game_texture.size() as u32, Error::SizeMismatch { what: "v1::GameTexture", expected: size as usize, found: game_texture.size() }))]
     pub struct GameTexture {
         #[br(temp)]
         #[bw(calc = game_texture.size() as u32)]
@@ -294,6 +1192,30 @@ pub enum GameTexture {
     V1(v1::GameTexture),
 }
 
+impl GameTexture {
+    /// The texture itself, which is the same for both versions; only the framing around it differs.
+    pub fn inner(&self) -> &v0::GameTexture {
+        match self {
+            GameTexture::V0(game_texture) => game_texture,
+            GameTexture::V1(game_texture) => &game_texture.game_texture,
+        }
+    }
+
+    pub fn inner_mut(&mut self) -> &mut v0::GameTexture {
+        match self {
+            GameTexture::V0(game_texture) => game_texture,
+            GameTexture::V1(game_texture) => &mut game_texture.game_texture,
+        }
+    }
+
+    pub fn version(&self) -> Version {
+        match self {
+            GameTexture::V0(_) => Version::V0,
+            GameTexture::V1(_) => Version::V1,
+        }
+    }
+}
+
 impl Size for GameTexture {
     fn size(&self) -> usize {
         match self {
@@ -304,7 +1226,7 @@ impl Size for GameTexture {
 }
 
 #[binrw]
-#[br(assert(size == game_textures.iter().map(|x| x.size()).sum::<usize>() as u32 + 2, "While parsing Language: Expected size {}, found {}.", size, game_textures.size()))]
+#[br(assert(size == game_textures.iter().map(|x| x.size()).sum::<usize>() as u32 + 2, Error::SizeMismatch { what: "Language", expected: size as usize, found: game_textures.size() + 2 }))]
 pub struct Language {
     pub id: LanguageId,
 
@@ -320,6 +1242,13 @@ pub struct Language {
     pub game_textures: Vec<GameTexture>,
 }
 
+impl Size for Language {
+    fn size(&self) -> usize {
+        // The id, size and count, but not the marker in front, which belongs to the list of languages.
+        8 + self.game_textures.size()
+    }
+}
+
 impl fmt::Debug for Language {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Language")
@@ -344,6 +1273,72 @@ pub struct TexturePackFile {
     pub game_textures: Vec<GameTexture>,
 }
 
+impl TexturePackFile {
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<TexturePackFile, Error> {
+        Ok(TexturePackFile::read(reader)?)
+    }
+
+    /// Reads packs written back to back from the current position to the end of `reader`, failing if the last one
+    /// is cut short.
+    pub fn read_all<R: Read + Seek>(reader: &mut R) -> Result<Vec<TexturePackFile>, Error> {
+        let start = reader.stream_position()?;
+        let end = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(start))?;
+
+        let mut packs = Vec::new();
+        while reader.stream_position()? < end {
+            packs.push(TexturePackFile::from_reader(reader)?);
+        }
+        Ok(packs)
+    }
+
+    /// Appends `game_texture` to the textures shared by every language. The path and animation pointers, which are
+    /// only checked for being non-zero, are set to match the fields they guard, and the frame count to the textures
+    /// present, so the pack can be written straight away.
+    pub fn push_texture(&mut self, mut game_texture: GameTexture) {
+        let inner = game_texture.inner_mut();
+        inner.path_pointer = inner.path.is_some() as u32;
+        inner.animation_info_pointer = inner.animation_info.is_some() as u32;
+        if let Some(animation_info) = &mut inner.animation_info {
+            animation_info.frame_count = inner.textures.len() as u32;
+        }
+        self.game_textures.push(game_texture);
+    }
+
+    /// Removes the first shared texture at `path`, compared with [`path_eq`], if any. Per-language textures are
+    /// left alone.
+    pub fn remove_by_path(&mut self, path: &str) -> Option<GameTexture> {
+        let index = self.game_textures.iter().position(|game_texture| {
+            game_texture
+                .inner()
+                .path
+                .as_deref()
+                .is_some_and(|texture_path| path_eq(texture_path, path))
+        })?;
+        Some(self.game_textures.remove(index))
+    }
+
+    /// The languages with their own textures, in file order. Textures outside of them are shared by every language.
+    pub fn languages_present(&self) -> Vec<LanguageId> {
+        let mut languages = Vec::with_capacity(self.languages.len());
+        for language in &self.languages {
+            if !languages.contains(&language.id) {
+                languages.push(language.id);
+            }
+        }
+        languages
+    }
+}
+
+impl PackVersion for TexturePackFile {
+    const VERSION_MAGIC: Option<[u8; 2]> = Version::VERSION_MAGIC;
+
+    /// `None` for files written before versions were added.
+    fn version_number(&self) -> Option<u16> {
+        self.version.as_ref().and_then(Version::version_number)
+    }
+}
+
 impl fmt::Debug for TexturePackFile {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Tpf")
@@ -355,18 +1350,79 @@ impl fmt::Debug for TexturePackFile {
 }
 
 impl TextureFormat {
+    /// A short, human readable summary of the layout.
+    pub fn description(&self) -> &'static str {
+        match *self {
+            TextureFormat::A8R8G8B8 => "32-bit ARGB, 8 bits per channel",
+            TextureFormat::R8G8B8 => "24-bit RGB, 8 bits per channel",
+            TextureFormat::A4R4G4B4 => "16-bit ARGB, 4 bits per channel",
+            TextureFormat::A1R5G5B5 => "16-bit ARGB, 1-bit alpha and 5 bits per color",
+            TextureFormat::X1R5G5B5 => "16-bit RGB, 5 bits per channel",
+            TextureFormat::R5G6B5 => "16-bit RGB, 5/6/5 bits per channel",
+            TextureFormat::A8 => "8-bit alpha",
+            TextureFormat::L8 => "8-bit luminance",
+            TextureFormat::AL8 => "8-bit luminance and alpha",
+            TextureFormat::DXT1 => "BC1 compressed, 8 bytes per 4x4 block, 1-bit alpha",
+            TextureFormat::DXT3 => "BC2 compressed, 16 bytes per 4x4 block, explicit 4-bit alpha",
+            TextureFormat::DXT5 => "BC3 compressed, 16 bytes per 4x4 block, interpolated alpha",
+            TextureFormat::V8U8 => "16-bit signed bump map, 8 bits per channel",
+            TextureFormat::V16U16 => "32-bit signed bump map, 16 bits per channel",
+            TextureFormat::PAL8 => "8-bit indices into a 256 color palette",
+        }
+    }
+
+    /// The channels a texel decodes to: 4 for ARGB, 3 for RGB, 2 for luminance and alpha or a bump map's U and V, 1
+    /// for lone alpha or luminance. Palette entries carry alpha, so PAL8 counts as 4.
+    pub fn channel_count(&self) -> u8 {
+        match *self {
+            TextureFormat::A8R8G8B8
+            | TextureFormat::A4R4G4B4
+            | TextureFormat::A1R5G5B5
+            | TextureFormat::DXT1
+            | TextureFormat::DXT3
+            | TextureFormat::DXT5
+            | TextureFormat::PAL8 => 4,
+            TextureFormat::R8G8B8 | TextureFormat::X1R5G5B5 | TextureFormat::R5G6B5 => 3,
+            TextureFormat::AL8 | TextureFormat::V8U8 | TextureFormat::V16U16 => 2,
+            TextureFormat::A8 | TextureFormat::L8 => 1,
+        }
+    }
+
+    /// Whether texels can be transparent, even if only with DXT1's or A1R5G5B5's single bit.
+    pub fn has_alpha(&self) -> bool {
+        matches!(
+            *self,
+            TextureFormat::A8R8G8B8
+                | TextureFormat::A4R4G4B4
+                | TextureFormat::A1R5G5B5
+                | TextureFormat::A8
+                | TextureFormat::AL8
+                | TextureFormat::DXT1
+                | TextureFormat::DXT3
+                | TextureFormat::DXT5
+                | TextureFormat::PAL8
+        )
+    }
+
+    /// Whether the color is a single gray level rather than red, green and blue.
+    pub fn is_luminance(&self) -> bool {
+        matches!(*self, TextureFormat::L8 | TextureFormat::AL8)
+    }
+
     pub fn compressed(&self) -> bool {
         matches!(*self, TextureFormat::DXT1 | TextureFormat::DXT3 | TextureFormat::DXT5)
     }
 
+    /// The size of each 4x4 block of the [`TextureFormat::compressed`] formats, `0` for the others.
     pub fn block_size(&self) -> usize {
         match *self {
             TextureFormat::DXT1 => 8,
             TextureFormat::DXT3 | TextureFormat::DXT5 => 16,
-            _ => unimplemented!(),
+            _ => 0,
         }
     }
 
+    /// The size of each texel, `0` for the [`TextureFormat::compressed`] formats, see [`TextureFormat::block_size`].
     pub fn bytes_per_pixel(&self) -> usize {
         match *self {
             TextureFormat::A8R8G8B8 | TextureFormat::V16U16 => 4,
@@ -377,64 +1433,1048 @@ impl TextureFormat {
             | TextureFormat::R5G6B5
             | TextureFormat::V8U8 => 2,
             TextureFormat::L8 | TextureFormat::A8 | TextureFormat::AL8 | TextureFormat::PAL8 => 1,
-            _ => unimplemented!(),
+            TextureFormat::DXT1 | TextureFormat::DXT3 | TextureFormat::DXT5 => 0,
         }
     }
+
+    /// The DDS pixel format this texture format is exported as, if there is one.
+    pub fn pixel_format(&self) -> Option<PixelFormat> {
+        Some(match *self {
+            TextureFormat::A8R8G8B8 => PixelFormat::A8R8G8B8,
+            TextureFormat::R8G8B8 => PixelFormat::R8G8B8, // The game's OpenGL path differs, see DdsCompat.
+            TextureFormat::A4R4G4B4 => PixelFormat::A4R4G4B4,
+            TextureFormat::A1R5G5B5 => PixelFormat::A1R5G5B5,
+            TextureFormat::X1R5G5B5 => PixelFormat::X1R5G5B5,
+            TextureFormat::R5G6B5 => PixelFormat::R5G6B5,
+            TextureFormat::A8 => PixelFormat::A8,
+            TextureFormat::L8 => PixelFormat::L8, // The game's loader differs, see DdsCompat.
+            // A8L8_ALT claims 8 bits but masks 16, A4L4 is the one that fits in a byte.
+            TextureFormat::AL8 => PixelFormat::A4L4,
+            TextureFormat::DXT1 => PixelFormat::DXT1,
+            TextureFormat::DXT3 => PixelFormat::DXT3,
+            TextureFormat::DXT5 => PixelFormat::DXT5,
+            TextureFormat::V8U8 => PixelFormat::V8U8,
+            TextureFormat::V16U16 => PixelFormat::V16U16,
+            TextureFormat::PAL8 => PixelFormat::from_tuple((dds::PAL8, 0, 0, 0, 0, 0)), // FIXME: This is wrong, according to the game generated PAL8 textures.
+        })
+    }
+
+    /// The inverse of [`TextureFormat::pixel_format`].
+    pub fn from_pixel_format(pixel_format: &PixelFormat) -> Option<TextureFormat> {
+        [
+            TextureFormat::A8R8G8B8,
+            TextureFormat::R8G8B8,
+            TextureFormat::A4R4G4B4,
+            TextureFormat::A1R5G5B5,
+            TextureFormat::X1R5G5B5,
+            TextureFormat::R5G6B5,
+            TextureFormat::A8,
+            TextureFormat::L8,
+            TextureFormat::AL8,
+            TextureFormat::DXT1,
+            TextureFormat::DXT3,
+            TextureFormat::DXT5,
+            TextureFormat::V8U8,
+            TextureFormat::V16U16,
+            TextureFormat::PAL8,
+        ]
+        .into_iter()
+        .find(|format| {
+            format
+                .pixel_format()
+                .is_some_and(|other| other.same_format(pixel_format))
+        })
+    }
 }
 
-fn calculate_mipmaps(mipmaps: usize, width: usize, height: usize) -> usize {
-    let mut mipmaps = mipmaps;
-    if mipmaps == 0 {
-        let (mut width, mut height) = (width, height);
-        while width > 0 && height > 0 {
-            width >>= 1;
-            height >>= 1;
-            mipmaps += 1;
-        }
+/// Number of levels in a full mip chain, halving both dimensions until either reaches zero.
+///
+/// A texture stored with a mip count of `0` uses this many levels.
+///
+/// ```
+/// assert_eq!(8, tpf::mipmap_count(128, 128));
+/// assert_eq!(7, tpf::mipmap_count(128, 64));
+/// ```
+pub fn mipmap_count(width: usize, height: usize) -> usize {
+    let (mut width, mut height) = (width, height);
+    let mut mipmaps = 0;
+    while width > 0 && height > 0 {
+        width >>= 1;
+        height >>= 1;
+        mipmaps += 1;
     }
     mipmaps
 }
 
-fn calculate_texture_size(
+/// Width or height of mip level `level`, which is never smaller than 1, even for the levels of absurd mip counts
+/// past where a shift would overflow.
+fn mip_dimension(dimension: usize, level: usize) -> usize {
+    u32::try_from(level)
+        .ok()
+        .and_then(|level| dimension.checked_shr(level))
+        .unwrap_or(0)
+        .max(1)
+}
+
+fn calculate_mipmaps(mipmaps: usize, width: usize, height: usize) -> usize {
+    match mipmaps {
+        0 => mipmap_count(width, height),
+        mipmaps => mipmaps,
+    }
+}
+
+/// Size in bytes of the pixel data for a texture with `mipmap_levels` levels, all faces included, or `None` for
+/// [`TextureType::DepthBuffer`] and [`TextureType::VolumeMap`], whose layout is unknown.
+///
+/// `mipmap_levels` is taken as-is, so a stored count of `0` has to be resolved with [`mipmap_count`] first. Sizes too
+/// large to address saturate at `usize::MAX`.
+///
+/// ```
+/// use tpf::{TextureFormat, TextureType};
+///
+/// let mipmaps = tpf::mipmap_count(128, 128);
+/// assert_eq!(Some(21872), tpf::texture_size(TextureFormat::DXT5, TextureType::Bitmap, 128, 128, mipmaps));
+/// assert_eq!(None, tpf::texture_size(TextureFormat::DXT5, TextureType::VolumeMap, 128, 128, mipmaps));
+/// ```
+pub fn texture_size(
     format: TextureFormat,
     type_: TextureType,
     width: usize,
     height: usize,
     mipmap_levels: usize,
-) -> usize {
+) -> Option<usize> {
     match type_ {
-        TextureType::Bitmap => {
-            let mut size = 0;
-            let mut width = width;
-            let mut height = height;
-            let compressed = format.compressed();
-
-            for _ in 0..mipmap_levels {
-                let mipmap_size = if compressed {
-                    ((width + 3) >> 2).max(1) * ((height + 3) >> 2).max(1) * format.block_size()
-                } else {
-                    width * height * format.bytes_per_pixel()
-                };
-
-                width >>= 1;
-                height >>= 1;
-                size += mipmap_size;
-            }
-            size
+        TextureType::Bitmap => Some(bitmap_size(format, width, height, mipmap_levels)),
+        TextureType::Cubemap => Some(bitmap_size(format, width, height, mipmap_levels).saturating_mul(6)),
+        TextureType::DepthBuffer | TextureType::VolumeMap => None,
+    }
+}
+
+/// [`texture_size`] of a single face.
+fn bitmap_size(format: TextureFormat, width: usize, height: usize, mipmap_levels: usize) -> usize {
+    let mut size = 0;
+    let mut width = width;
+    let mut height = height;
+    let compressed = format.compressed();
+
+    for level in 0..mipmap_levels {
+        if width == 0 && height == 0 {
+            // Every level past here is the smallest one, which only takes space when compressed.
+            let smallest = if compressed { format.block_size() } else { 0 };
+            size = (mipmap_levels - level).saturating_mul(smallest).saturating_add(size);
+            break;
         }
-        TextureType::Cubemap => 6 * calculate_texture_size(format, TextureType::Bitmap, width, height, mipmap_levels),
-        TextureType::DepthBuffer => unimplemented!(),
-        TextureType::VolumeMap => unimplemented!(),
+
+        let mipmap_size = if compressed {
+            ((width + 3) >> 2)
+                .max(1)
+                .saturating_mul(((height + 3) >> 2).max(1))
+                .saturating_mul(format.block_size())
+        } else {
+            width.saturating_mul(height).saturating_mul(format.bytes_per_pixel())
+        };
+
+        width >>= 1;
+        height >>= 1;
+        size = size.saturating_add(mipmap_size);
     }
+    size
 }
 
-fn languages_parser<R: Read + Seek>(reader: &mut R, ro: &ReadOptions, _: ()) -> BinResult<Vec<Language>> {
+fn data_parser<R: Read + Seek>(reader: &mut R, _: Endian, (size,): (usize,)) -> BinResult<Vec<u8>> {
+    // The size comes straight from the file, so let the buffer grow with what is actually there. A short read is
+    // left to the assertion on `Texture::data`, which can say which texture came up short.
+    let mut data = Vec::new();
+    reader.by_ref().take(size as u64).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn languages_parser<R: Read + Seek>(reader: &mut R, endian: Endian, _: ()) -> BinResult<Vec<Language>> {
     let mut languages = Vec::with_capacity(LanguageId::Nonsense as usize);
     let mut magic: u16 = reader.read_be()?;
     while magic == 0xFFFF {
-        languages.push(Language::read_options(reader, ro, ())?);
+        languages.push(Language::read_options(reader, endian, ())?);
         magic = reader.read_be()?;
     }
+    // That wasn't a language marker but the top-level texture count, so put it back.
     reader.seek(SeekFrom::Current(-2))?;
     Ok(languages)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use binrw::BinWrite;
+
+    use super::*;
+
+    fn texture(format: TextureFormat, width: usize, height: usize, mipmaps: usize) -> Texture {
+        Texture {
+            format,
+            type_: TextureType::Bitmap,
+            flags: TextureFlags::empty(),
+            width,
+            height,
+            mipmaps,
+            palette: (format == TextureFormat::PAL8).then_some(Palette { data: Some([0; 0x100]) }),
+            data: vec![0; bitmap_size(format, width, height, mipmaps)],
+        }
+    }
+
+    fn hand_written_size(texture: &Texture) -> usize {
+        4 + texture.format.size()
+            + texture.type_.size()
+            + 32
+            + texture.palette.size()
+            + texture_size(
+                texture.format,
+                texture.type_,
+                texture.width,
+                texture.height,
+                texture.mipmaps,
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn derived_texture_size() {
+        for texture in [
+            texture(TextureFormat::DXT1, 8, 8, 4),
+            texture(TextureFormat::A8R8G8B8, 16, 4, 1),
+            texture(TextureFormat::PAL8, 4, 4, 3),
+        ] {
+            let mut writer = Cursor::new(Vec::new());
+            texture.write_le(&mut writer).unwrap();
+
+            assert_eq!(hand_written_size(&texture), texture.size());
+            assert_eq!(writer.into_inner().len(), texture.size());
+        }
+    }
+
+    #[test]
+    fn derived_animation_info_size() {
+        let animation_info = AnimationInfo {
+            frame_count: 2,
+            start_frame: 0.0,
+            loop_frame: 0.0,
+            start_time: 0.0,
+            frame_rate: 15.0,
+            play_mode: PlayMode::Loop,
+            playing: true,
+        };
+
+        let mut writer = Cursor::new(Vec::new());
+        animation_info.write_le(&mut writer).unwrap();
+
+        assert_eq!(28, animation_info.size());
+        assert_eq!(writer.into_inner().len(), animation_info.size());
+    }
+
+    #[test]
+    fn truncated_input() {
+        let result = TexturePackFile::from_reader(&mut Cursor::new(vec![0xFD]));
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn language_size_mismatch() {
+        let data = vec![
+            0xFD, 0xFD, 0x01, 0x00, // version
+            0xFF, 0xFF, 0x00, 0x00, // language marker and id
+            0x03, 0x00, 0x00, 0x00, // size, should be 2 for an empty language
+            0x00, 0x00, 0x00, 0x00, // language and file texture counts
+        ];
+
+        let result = TexturePackFile::from_reader(&mut Cursor::new(data));
+        assert!(matches!(
+            result,
+            Err(Error::SizeMismatch {
+                what: "Language",
+                expected: 3,
+                found: 2
+            })
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn metadata_json_round_trip() {
+        let metadata = texture(TextureFormat::PAL8, 4, 2, 1).metadata();
+
+        let json = serde_json::to_string(&metadata).unwrap();
+        assert_eq!(
+            r#"{"format":14,"type":0,"flags":0,"width":4,"height":2,"mipmaps":1,"has_palette":true}"#,
+            json
+        );
+        assert_eq!(metadata, serde_json::from_str(&json).unwrap());
+    }
+
+    #[test]
+    fn from_raw() {
+        let mut original = texture(TextureFormat::PAL8, 4, 2, 1);
+        original.flags = TextureFlags::BIT_4;
+        original.palette = Some(Palette {
+            data: Some([0xFF00_00FF; 0x100]),
+        });
+        let palette = original.palette.as_ref().and_then(|palette| palette.data);
+        let rebuilt = Texture::from_raw(original.metadata(), palette, original.data.clone()).unwrap();
+        assert_eq!(original, rebuilt);
+
+        assert!(matches!(
+            Texture::from_raw(original.metadata(), palette, vec![0; 7]),
+            Err(Error::SizeMismatch {
+                expected: 8,
+                found: 7,
+                ..
+            })
+        ));
+        let mut metadata = original.metadata();
+        metadata.type_ = TextureType::VolumeMap;
+        assert!(matches!(
+            Texture::from_raw(metadata, palette, original.data.clone()),
+            Err(Error::UnsupportedType(TextureType::VolumeMap))
+        ));
+    }
+
+    #[test]
+    fn al8_dds_header() {
+        let header = texture(TextureFormat::AL8, 8, 4, 1).dds_header().unwrap();
+        assert!(header.pixel_format.same_format(&PixelFormat::A4L4));
+        assert_eq!(8, header.pitch_or_linear_size);
+        assert_eq!(
+            Some(TextureFormat::AL8),
+            TextureFormat::from_pixel_format(&header.pixel_format)
+        );
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!("DXT5", format!("{}", TextureFormat::DXT5));
+        assert_eq!("A8R8G8B8", TextureFormat::A8R8G8B8.to_string());
+        assert_eq!("Cubemap", TextureType::Cubemap.to_string());
+    }
+
+    #[test]
+    fn face_and_slice_counts() {
+        for (type_, cubemap, volume, faces, slices) in [
+            (TextureType::Bitmap, false, false, 1, Some(1)),
+            (TextureType::Cubemap, true, false, 6, Some(1)),
+            (TextureType::VolumeMap, false, true, 1, None),
+            (TextureType::DepthBuffer, false, false, 1, None),
+        ] {
+            let mut texture = texture(TextureFormat::DXT1, 8, 8, 2);
+            texture.type_ = type_;
+            assert_eq!(
+                (cubemap, volume, faces, slices),
+                (
+                    texture.is_cubemap(),
+                    texture.is_volume(),
+                    texture.face_count(),
+                    texture.slice_count()
+                ),
+                "{}",
+                type_
+            );
+            assert_eq!(
+                slices.map(|slices| bitmap_size(texture.format, 8, 8, 2) * faces * slices),
+                texture_size(texture.format, type_, 8, 8, 2)
+            );
+        }
+    }
+
+    #[test]
+    fn unsized_types() {
+        let mut volume = texture(TextureFormat::A8R8G8B8, 4, 4, 1);
+        volume.type_ = TextureType::VolumeMap;
+        assert!(!volume.data_matches_header());
+        assert_eq!(
+            hand_written_size(&texture(TextureFormat::A8R8G8B8, 4, 4, 1)),
+            volume.size()
+        );
+        assert!(volume.write_le(&mut Cursor::new(Vec::new())).is_err());
+        // Its data length can't be checked, so there's nothing to warn about.
+        assert_eq!(Ok(()), volume.validate());
+    }
+
+    #[test]
+    fn validate() {
+        assert_eq!(Ok(()), texture(TextureFormat::DXT1, 128, 64, 7).validate());
+        assert_eq!(
+            Err(vec![Warning::NonPowerOfTwo {
+                width: 100,
+                height: 100
+            }]),
+            texture(TextureFormat::A8R8G8B8, 100, 100, 1).validate()
+        );
+
+        let mut mismatched = texture(TextureFormat::DXT5, 4, 4, 5);
+        mismatched.data.pop();
+        assert_eq!(
+            Err(vec![
+                Warning::MipmapCount { max: 3, found: 5 },
+                Warning::DataSize {
+                    expected: 80,
+                    found: 79
+                }
+            ]),
+            mismatched.validate()
+        );
+    }
+
+    #[test]
+    fn decode_normal_v8u8() {
+        let mut bump = texture(TextureFormat::V8U8, 2, 1, 1);
+        bump.data = vec![64, 0, 0x81, 0];
+
+        assert_eq!(
+            vec![192, 128, 238, 0xFF, 0, 128, 128, 0xFF],
+            bump.decode_normal_rgba8().unwrap()
+        );
+        assert!(matches!(
+            texture(TextureFormat::DXT1, 4, 4, 1).decode_normal_rgba8(),
+            Err(Error::UnsupportedFormat(TextureFormat::DXT1))
+        ));
+    }
+
+    #[test]
+    fn thumbnail_rgba8() {
+        let mut full = texture(TextureFormat::A8R8G8B8, 256, 256, mipmap_count(256, 256));
+        let len = full.data.len();
+        full.data[len - 4..].copy_from_slice(&[1, 2, 3, 4]);
+        assert_eq!((1, 1, vec![3, 2, 1, 4]), full.thumbnail_rgba8().unwrap());
+
+        let compressed = texture(TextureFormat::DXT1, 256, 256, mipmap_count(256, 256));
+        let (width, height, rgba) = compressed.thumbnail_rgba8().unwrap();
+        assert_eq!((1, 1, 4), (width, height, rgba.len()));
+
+        let single = texture(TextureFormat::L8, 4, 2, 1);
+        assert_eq!((4, 2, [0, 0, 0, 0xFF].repeat(8)), single.thumbnail_rgba8().unwrap());
+
+        let mut short = texture(TextureFormat::A8R8G8B8, 4, 4, 3);
+        short.data.truncate(64);
+        assert_eq!(Some(4), short.mip_level(0).map(|mip| mip.width));
+        assert!(short.mip_level(2).is_none());
+        assert!(matches!(short.thumbnail_rgba8(), Err(Error::SizeMismatch { .. })));
+    }
+
+    fn texture_header(type_: u32, width: u32, height: u32) -> Vec<u8> {
+        let mut data = vec![0; 4];
+        for value in [TextureFormat::A8R8G8B8 as u32, type_, 0, width, height, 1] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.resize(data.len() + 16, 0);
+        data
+    }
+
+    #[test]
+    fn unsupported_texture_type() {
+        let result = Cursor::new(texture_header(TextureType::VolumeMap as u32, 4, 4)).read_le::<Texture>();
+        assert!(matches!(
+            result.map_err(Error::from),
+            Err(Error::UnsupportedType(TextureType::VolumeMap))
+        ));
+    }
+
+    #[test]
+    fn unknown_texture_format() {
+        let mut header = texture_header(0, 4, 4);
+        header[4..8].copy_from_slice(&99u32.to_le_bytes());
+        let result = Cursor::new(header).read_le::<Texture>();
+        let err = result.map_err(Error::from).unwrap_err();
+        assert!(matches!(err, Error::UnknownFormat(99)), "{:?}", err);
+        assert_eq!("Unknown texture format 99.", err.to_string());
+
+        assert_eq!(
+            Ok(TextureFormat::PAL8),
+            TextureFormat::try_from(14).map_err(|err| err.to_string())
+        );
+    }
+
+    #[test]
+    fn oversized_texture() {
+        let result = Cursor::new(texture_header(0, u32::MAX, u32::MAX)).read_le::<Texture>();
+        assert!(matches!(result.map_err(Error::from), Err(Error::Parse { .. })));
+    }
+
+    /// Inputs the fuzzer found. A zero dimension leaves these textures without data, so they parse, but then what is
+    /// done with them panicked or aborted.
+    #[test]
+    fn degenerate_dimensions() {
+        for (format, width, height, mipmaps) in [
+            // The DDS pitch overflowed.
+            (TextureFormat::A8R8G8B8, 0x3F00_0000, 0, 0),
+            // The decode buffer was sized from the width alone.
+            (TextureFormat::V8U8, 0x9900_0000, 0, 256),
+            // The mip levels past 64 shifted the dimensions out of range.
+            (TextureFormat::A8R8G8B8, 0, 0xD900_0000, 0xD9D9_D9D9),
+        ] {
+            let mut data = vec![0; 4];
+            for value in [format as u32, 0, 0, width, height, mipmaps] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            data.resize(data.len() + 16, 0);
+            let texture: Texture = Cursor::new(data).read_le().unwrap();
+            assert!(texture.data.is_empty());
+
+            let _ = texture.validate();
+            let _ = texture.to_dds();
+            let _ = texture.decode_rgba8();
+            let _ = texture.thumbnail_rgba8();
+        }
+    }
+
+    #[test]
+    fn truncated_texture_data() {
+        let mut data = texture_header(0, 4, 4);
+        data.extend_from_slice(&[0; 10]);
+        let err = Error::from(Cursor::new(data).read_le::<Texture>().unwrap_err());
+        assert!(matches!(err, Error::Parse { .. }), "{:?}", err);
+        assert!(
+            err.to_string()
+                .contains("Expected 64 bytes of data for a 4x4 A8R8G8B8 texture with 1 mipmaps, found 10"),
+            "{}",
+            err
+        );
+
+        let mut texture = texture(TextureFormat::A8R8G8B8, 4, 4, 1);
+        assert!(texture.data_matches_header());
+        texture.data.pop();
+        assert!(!texture.data_matches_header());
+    }
+
+    fn game_texture() -> v0::GameTexture {
+        v0::GameTexture {
+            element_id: 0,
+            texture_handle: 1,
+            palette_handle: 0,
+            path_pointer: 0,
+            animation_info_pointer: 0,
+            density: 1.0,
+            visual_importance: 0,
+            memory_importance: 0,
+            unknown0: 0,
+            flags: GameTextureFlags::empty(),
+            path: None,
+            animation_info: None,
+            textures: vec![texture(TextureFormat::DXT1, 4, 4, 1)],
+        }
+    }
+
+    #[test]
+    fn languages_without_top_level_textures() {
+        let game_texture = GameTexture::V0(game_texture());
+        let mut writer = Cursor::new(Vec::new());
+        game_texture.write_le(&mut writer).unwrap();
+        let game_texture = writer.into_inner();
+
+        let mut data = vec![0xFD, 0xFD, 0x01, 0x00];
+        for id in [LanguageId::English, LanguageId::French] {
+            data.extend_from_slice(&[0xFF, 0xFF]);
+            data.extend_from_slice(&(id as u16).to_le_bytes());
+            data.extend_from_slice(&(game_texture.len() as u32 + 2).to_le_bytes());
+            data.extend_from_slice(&1u16.to_le_bytes());
+            data.extend_from_slice(&game_texture);
+        }
+        data.extend_from_slice(&0u16.to_le_bytes());
+
+        let mut reader = Cursor::new(data);
+        let tpf = TexturePackFile::from_reader(&mut reader).unwrap();
+
+        assert_eq!(Some(Version::V1), tpf.version);
+        assert_eq!(
+            vec![LanguageId::English, LanguageId::French],
+            tpf.languages.iter().map(|language| language.id).collect::<Vec<_>>()
+        );
+        assert!(tpf.languages.iter().all(|language| language.game_textures.len() == 1));
+        assert!(tpf.game_textures.is_empty());
+        assert_eq!(reader.get_ref().len() as u64, reader.position());
+    }
+
+    /// Writes `$value` and checks that its `size()` matches the bytes written. A macro rather than a function so the
+    /// inherent `size()` of the versioned game textures works too.
+    macro_rules! assert_size {
+        ($value:expr, $what:expr) => {{
+            let value = $value;
+            let mut writer = Cursor::new(Vec::new());
+            value.write_le(&mut writer).unwrap();
+            assert_eq!(writer.into_inner().len(), value.size(), "size of {}", $what);
+        }};
+    }
+
+    /// Checks the `Size` invariant for every language, game texture, texture and palette of `tpf`.
+    fn assert_sizes(tpf: &TexturePackFile) {
+        let game_textures = tpf
+            .game_textures
+            .iter()
+            .chain(tpf.languages.iter().flat_map(|language| &language.game_textures));
+        for language in &tpf.languages {
+            assert_size!(language, "Language");
+        }
+        for game_texture in game_textures {
+            assert_size!(game_texture, "GameTexture");
+            let game_texture = match game_texture {
+                GameTexture::V0(game_texture) => game_texture,
+                GameTexture::V1(game_texture) => {
+                    assert_size!(game_texture, "v1::GameTexture");
+                    &game_texture.game_texture
+                }
+            };
+            assert_size!(game_texture, "v0::GameTexture");
+            for texture in &game_texture.textures {
+                assert_size!(texture, "Texture");
+                if let Some(palette) = &texture.palette {
+                    assert_size!(palette, "Palette");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn size_matches_written_length() {
+        let animated = || {
+            let mut animated = game_texture();
+            animated.path_pointer = 1;
+            animated.path = Some(Path::new("textures/animated"));
+            animated.animation_info_pointer = 1;
+            animated.animation_info = Some(AnimationInfo {
+                frame_count: 2,
+                start_frame: 0.0,
+                loop_frame: 0.0,
+                start_time: 0.0,
+                frame_rate: 15.0,
+                play_mode: PlayMode::Loop,
+                playing: true,
+            });
+            animated.textures = vec![
+                texture(TextureFormat::PAL8, 4, 4, 3),
+                texture(TextureFormat::A8R8G8B8, 4, 4, 1),
+            ];
+            animated
+        };
+        let cubemap = || {
+            let mut cubemap = texture(TextureFormat::DXT5, 8, 8, 2);
+            cubemap.type_ = TextureType::Cubemap;
+            cubemap.data = vec![0; bitmap_size(TextureFormat::DXT5, 8, 8, 2) * 6];
+            let mut game_texture = game_texture();
+            game_texture.textures = vec![cubemap];
+            game_texture
+        };
+
+        for version in [None, Some(Version::V1)] {
+            let tpf = TexturePackFile {
+                version,
+                languages: vec![],
+                game_textures: vec![
+                    GameTexture::V0(game_texture()),
+                    GameTexture::V1(v1::GameTexture {
+                        game_texture: animated(),
+                    }),
+                    GameTexture::V0(cubemap()),
+                ],
+            };
+            assert_sizes(&tpf);
+
+            // And again on what reads back.
+            let mut writer = Cursor::new(Vec::new());
+            tpf.write_le(&mut writer).unwrap();
+            let tpf = TexturePackFile::from_reader(&mut Cursor::new(writer.into_inner())).unwrap();
+            assert_sizes(&tpf);
+        }
+
+        // Languages can't be written as part of a tpf, so read one in.
+        let mut writer = Cursor::new(Vec::new());
+        GameTexture::V1(v1::GameTexture {
+            game_texture: animated(),
+        })
+        .write_le(&mut writer)
+        .unwrap();
+        let game_texture = writer.into_inner();
+        let mut data = vec![0xFD, 0xFD, 0x01, 0x00, 0xFF, 0xFF, 0x01, 0x00];
+        data.extend_from_slice(&(game_texture.len() as u32 + 2).to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&game_texture);
+        data.extend_from_slice(&0u16.to_le_bytes());
+        assert_sizes(&TexturePackFile::from_reader(&mut Cursor::new(data)).unwrap());
+    }
+
+    #[test]
+    fn stops_at_section_end() {
+        let mut writer = Cursor::new(Vec::new());
+        GameTexture::V0(game_texture()).write_le(&mut writer).unwrap();
+        let game_texture = writer.into_inner();
+
+        let plain = {
+            let mut data = 1u16.to_le_bytes().to_vec();
+            data.extend_from_slice(&game_texture);
+            data
+        };
+        let with_language = {
+            let mut data = vec![0xFD, 0xFD, 0x00, 0x00, 0xFF, 0xFF];
+            data.extend_from_slice(&(LanguageId::German as u16).to_le_bytes());
+            data.extend_from_slice(&(game_texture.len() as u32 + 2).to_le_bytes());
+            data.extend_from_slice(&1u16.to_le_bytes());
+            data.extend_from_slice(&game_texture);
+            data.extend_from_slice(&plain);
+            data
+        };
+
+        for (tpf, textures) in [(vec![0, 0], 0), (plain, 1), (with_language, 2)] {
+            // A ppf carries straight on with its mesh section.
+            let mut data = tpf.clone();
+            data.extend_from_slice(b"MPAK\0\0");
+            let mut reader = Cursor::new(data);
+            let parsed = TexturePackFile::from_reader(&mut reader).unwrap();
+
+            let languages: usize = parsed
+                .languages
+                .iter()
+                .map(|language| language.game_textures.len())
+                .sum();
+            assert_eq!(textures, parsed.game_textures.len() + languages);
+            assert_eq!(tpf.len() as u64, reader.position());
+        }
+    }
+
+    #[test]
+    fn dds_round_trip() {
+        let mut cubemap = texture(TextureFormat::A8R8G8B8, 4, 4, 3);
+        cubemap.type_ = TextureType::Cubemap;
+        cubemap.data = vec![0; bitmap_size(TextureFormat::A8R8G8B8, 4, 4, 3) * 6];
+
+        for mut texture in [
+            texture(TextureFormat::DXT1, 8, 8, 4),
+            texture(TextureFormat::R5G6B5, 4, 2, 1),
+            cubemap,
+        ] {
+            for (index, byte) in texture.data.iter_mut().enumerate() {
+                *byte = index as u8;
+            }
+
+            let mut dds = bincode::serialize(&texture.dds_header().unwrap()).unwrap();
+            dds.extend_from_slice(&texture.data);
+
+            let header: dds::Header = bincode::deserialize(&dds[..124]).unwrap();
+            assert_eq!(texture, Texture::from_dds(&header, &dds[124..]).unwrap());
+        }
+    }
+
+    #[test]
+    fn to_dds() {
+        let mut texture = texture(TextureFormat::PAL8, 4, 4, 3);
+        texture.palette = Some(Palette {
+            data: Some(std::array::from_fn(|index| index as u32 * 0x0101_0101)),
+        });
+
+        let mut file = Vec::new();
+        texture.to_dds().unwrap().write(&mut file).unwrap();
+        let dds = dds::Dds::read(&mut Cursor::new(file)).unwrap();
+        assert_eq!(texture, Texture::from_dds(&dds.header, &dds.data).unwrap());
+    }
+
+    #[test]
+    fn dds_compat() {
+        let l8 = texture(TextureFormat::L8, 4, 4, 7);
+        let dds = l8.to_dds().unwrap();
+        assert!(dds.header.pixel_format.same_format(&PixelFormat::L8));
+        let game = l8.to_dds_with(DdsCompat::Game).unwrap();
+        assert!(game.header.pixel_format.same_format(&PixelFormat::A8));
+        assert_eq!(dds.data, game.data);
+
+        let mut r8g8b8 = texture(TextureFormat::R8G8B8, 2, 1, 0);
+        r8g8b8.data = vec![1, 2, 3, 4, 5, 6];
+        let dds = r8g8b8.to_dds().unwrap();
+        assert!(dds.header.pixel_format.same_format(&PixelFormat::R8G8B8));
+        assert_eq!((6, &r8g8b8.data), (dds.header.pitch_or_linear_size, &dds.data));
+        let game = r8g8b8.to_dds_with(DdsCompat::Game).unwrap();
+        assert!(game.header.pixel_format.same_format(&PixelFormat::X8R8G8B8));
+        assert_eq!(8, game.header.pitch_or_linear_size);
+        assert_eq!(vec![1, 2, 3, 0xFF, 4, 5, 6, 0xFF], game.data);
+    }
+
+    #[test]
+    fn dds_pitch_alignment() {
+        let mut texture = texture(TextureFormat::R8G8B8, 17, 2, 2);
+        for (index, byte) in texture.data.iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+
+        let byte = texture.to_dds_aligned(DdsCompat::Dds, PitchAlignment::Byte).unwrap();
+        assert_eq!(51, byte.header.pitch_or_linear_size);
+        assert_eq!(texture.data, byte.data);
+
+        let dword = texture.to_dds_aligned(DdsCompat::Dds, PitchAlignment::Dword).unwrap();
+        assert_eq!(52, dword.header.pitch_or_linear_size);
+        // Two padded rows of the top level, then the 8x1 level, which is already a whole number of dwords.
+        assert_eq!(2 * 52 + 24, dword.data.len());
+        assert_eq!(&texture.data[..51], &dword.data[..51]);
+        assert_eq!([0, 51, 52], [dword.data[51], dword.data[52], dword.data[53]]);
+
+        for dds in [byte, dword] {
+            let mut file = Vec::new();
+            dds.write(&mut file).unwrap();
+            let dds = dds::Dds::read(&mut Cursor::new(file)).unwrap();
+            assert_eq!(texture, Texture::from_dds(&dds.header, &dds.data).unwrap());
+        }
+    }
+
+    #[test]
+    fn dds_import_errors() {
+        let texture = texture(TextureFormat::DXT5, 8, 8, 1);
+        let mut header = texture.dds_header().unwrap();
+
+        assert!(matches!(
+            Texture::from_dds(&header, &texture.data[1..]),
+            Err(Error::SizeMismatch { what: "DDS data", .. })
+        ));
+
+        header.pixel_format = PixelFormat::X8R8G8B8;
+        assert!(matches!(
+            Texture::from_dds(&header, &texture.data),
+            Err(Error::UnsupportedPixelFormat)
+        ));
+    }
+
+    #[test]
+    fn flags_round_trip() {
+        let mut texture = texture(TextureFormat::A8R8G8B8, 1, 1, 1);
+        texture.flags = TextureFlags::BIT_4 | TextureFlags::BIT_31;
+
+        let mut writer = Cursor::new(Vec::new());
+        texture.write_le(&mut writer).unwrap();
+        let data = writer.into_inner();
+
+        let read: Texture = Cursor::new(&data).read_le().unwrap();
+        assert_eq!(0x8000_0010, read.flags.bits());
+        assert!(read.flags.contains(TextureFlags::BIT_31));
+        assert_eq!("TextureFlags(BIT_4 | BIT_31)", format!("{:?}", read.flags));
+
+        let mut writer = Cursor::new(Vec::new());
+        read.write_le(&mut writer).unwrap();
+        assert_eq!(data, writer.into_inner());
+
+        let mut game_texture = game_texture();
+        game_texture.flags = GameTextureFlags::BIT_0 | GameTextureFlags::BIT_17;
+        let mut writer = Cursor::new(Vec::new());
+        game_texture.write_le(&mut writer).unwrap();
+        let read: v0::GameTexture = Cursor::new(writer.into_inner()).read_le().unwrap();
+        assert_eq!(0x0002_0001, read.flags.bits());
+        assert_eq!(GameTextureFlags::BIT_0 | GameTextureFlags::BIT_17, read.flags);
+    }
+
+    #[test]
+    fn inner_of_either_version() {
+        let named = || {
+            let mut game_texture = game_texture();
+            game_texture.path_pointer = 1;
+            game_texture.path = Some(Path::new("textures/test"));
+            game_texture
+        };
+        let v0 = GameTexture::V0(named());
+        let v1 = GameTexture::V1(v1::GameTexture { game_texture: named() });
+
+        assert_eq!(Version::V0, v0.version());
+        assert_eq!(Version::V1, v1.version());
+        assert_eq!(format!("{:?}", v0.inner()), format!("{:?}", v1.inner()));
+        assert_eq!(v0.inner().textures, v1.inner().textures);
+        assert_eq!(Some("textures/test"), v1.inner().path.as_deref());
+    }
+
+    #[test]
+    fn play_mode_classification() {
+        use PlayMode::*;
+
+        // (mode, looping, once, oscillating, reverses)
+        for (mode, expected) in [
+            (Loop, (true, false, false, false)),
+            (LoopOnce, (false, true, false, false)),
+            (LoopTail, (true, false, false, false)),
+            (Oscillate, (true, false, true, true)),
+            (OscillateOnce, (false, true, true, true)),
+            (OscillateOutOnce, (false, true, true, false)),
+            (OscillateBackOnce, (false, true, true, true)),
+            (Stop, (false, false, false, false)),
+        ] {
+            assert_eq!(
+                expected,
+                (
+                    mode.is_looping(),
+                    mode.is_once(),
+                    mode.is_oscillating(),
+                    mode.reverses()
+                ),
+                "{}",
+                mode
+            );
+        }
+    }
+
+    #[test]
+    fn format_channels() {
+        assert_eq!(4, TextureFormat::DXT1.channel_count());
+        assert!(TextureFormat::DXT1.has_alpha());
+        assert!(!TextureFormat::DXT1.is_luminance());
+
+        assert_eq!(3, TextureFormat::R8G8B8.channel_count());
+        assert!(!TextureFormat::R8G8B8.has_alpha());
+
+        assert_eq!(1, TextureFormat::L8.channel_count());
+        assert!(TextureFormat::L8.is_luminance());
+        assert!(!TextureFormat::L8.has_alpha());
+        assert!(TextureFormat::AL8.is_luminance() && TextureFormat::AL8.has_alpha());
+    }
+
+    #[test]
+    fn pack_version() {
+        let tpf = TexturePackFile::from_reader(&mut Cursor::new(vec![0xFD, 0xFD, 0x01, 0x00, 0x00, 0x00])).unwrap();
+        assert_eq!(Some(1), tpf.version_number());
+        assert_eq!(Some([0xFD, 0xFD]), TexturePackFile::VERSION_MAGIC);
+
+        let tpf = TexturePackFile::from_reader(&mut Cursor::new(vec![0x00, 0x00])).unwrap();
+        assert_eq!(None, tpf.version_number());
+    }
+
+    #[test]
+    fn push_and_remove_textures() {
+        let mut tpf = TexturePackFile {
+            version: Some(Version::V1),
+            languages: vec![],
+            game_textures: vec![GameTexture::V0(game_texture())],
+        };
+        let mut added = game_texture();
+        added.texture_handle = 2;
+        added.path = Some(Path::new("textures/added"));
+        tpf.push_texture(GameTexture::V1(v1::GameTexture { game_texture: added }));
+
+        let mut writer = Cursor::new(Vec::new());
+        tpf.write_le(&mut writer).unwrap();
+        let mut read = TexturePackFile::from_reader(&mut Cursor::new(writer.into_inner())).unwrap();
+        assert_eq!(2, read.game_textures.len());
+        assert_eq!(Some("textures/added"), read.game_textures[1].inner().path.as_deref());
+        assert_eq!(2, read.game_textures[1].inner().texture_handle);
+
+        assert!(read.remove_by_path("textures/missing").is_none());
+        let removed = read.remove_by_path("/textures\\added").unwrap();
+        assert_eq!(Version::V1, removed.version());
+        assert_eq!(1, read.game_textures.len());
+    }
+
+    #[test]
+    fn read_all() {
+        let mut writer = Cursor::new(Vec::new());
+        for count in [1, 2] {
+            let tpf = TexturePackFile {
+                version: Some(Version::V1),
+                languages: vec![],
+                game_textures: (0..count).map(|_| GameTexture::V0(game_texture())).collect(),
+            };
+            tpf.write_le(&mut writer).unwrap();
+        }
+        let mut data = writer.into_inner();
+
+        let packs = TexturePackFile::read_all(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(
+            vec![1, 2],
+            packs.iter().map(|tpf| tpf.game_textures.len()).collect::<Vec<_>>()
+        );
+        assert!(TexturePackFile::read_all(&mut Cursor::new(Vec::new()))
+            .unwrap()
+            .is_empty());
+
+        data.push(0);
+        assert!(TexturePackFile::read_all(&mut Cursor::new(&data)).is_err());
+    }
+
+    #[test]
+    fn languages_present() {
+        let mut writer = Cursor::new(Vec::new());
+        GameTexture::V0(game_texture()).write_le(&mut writer).unwrap();
+        let game_texture = writer.into_inner();
+
+        let mut data = vec![0xFD, 0xFD, 0x01, 0x00];
+        for id in [LanguageId::German, LanguageId::French] {
+            data.extend_from_slice(&[0xFF, 0xFF]);
+            data.extend_from_slice(&(id as u16).to_le_bytes());
+            data.extend_from_slice(&(game_texture.len() as u32 + 2).to_le_bytes());
+            data.extend_from_slice(&1u16.to_le_bytes());
+            data.extend_from_slice(&game_texture);
+        }
+        data.extend_from_slice(&0u16.to_le_bytes());
+
+        let tpf = TexturePackFile::from_reader(&mut Cursor::new(data)).unwrap();
+        assert_eq!(vec![LanguageId::German, LanguageId::French], tpf.languages_present());
+        assert_eq!("German", tpf.languages_present()[0].name());
+        assert!(LanguageId::Nonsense.name().starts_with("Nonsense"));
+        assert_eq!("nonsense", LanguageId::Nonsense.code());
+    }
+
+    #[test]
+    fn decode_to_matches_decode_rgba8() {
+        for format in [
+            TextureFormat::A8R8G8B8,
+            TextureFormat::R5G6B5,
+            TextureFormat::PAL8,
+            TextureFormat::V8U8,
+            TextureFormat::DXT1,
+            TextureFormat::DXT5,
+        ] {
+            // 6x7 leaves partial block rows and columns for the compressed formats.
+            let mut texture = texture(format, 6, 7, 2);
+            for (i, byte) in texture.data.iter_mut().enumerate() {
+                *byte = (i * 37) as u8;
+            }
+
+            let mut streamed = Vec::new();
+            texture.decode_to(&mut streamed).unwrap();
+            assert_eq!(texture.decode_rgba8().unwrap(), streamed, "{:?}", format);
+            assert_eq!(6 * 7 * 4, streamed.len(), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn decode_cache() {
+        let mut frames = [texture(TextureFormat::L8, 2, 2, 1), texture(TextureFormat::L8, 2, 2, 1)];
+        frames[1].data.fill(7);
+        let copy = texture(TextureFormat::L8, 2, 2, 1);
+        let mut bad = texture(TextureFormat::L8, 2, 2, 1);
+        bad.data.clear();
+
+        let mut cache = DecodeCache::new();
+        let first = cache.get_or_decode(1, &frames[0]).unwrap().to_vec();
+        assert_eq!(frames[0].decode_rgba8().unwrap(), first);
+        assert_eq!(first, cache.get_or_decode(1, &frames[0]).unwrap());
+        assert_eq!(first, cache.get_or_decode(1, &copy).unwrap());
+        assert_eq!(1, cache.len());
+
+        // Another texture under the same handle gets its own texels.
+        assert_eq!([7, 7, 7, 0xFF].repeat(4), cache.get_or_decode(1, &frames[1]).unwrap());
+        assert_eq!(2, cache.len());
+
+        assert!(cache.get_or_decode(2, &bad).is_err());
+        assert_eq!(2, cache.len());
+    }
+
+    #[test]
+    fn reorder_channels() {
+        use Channel::*;
+
+        let rgba = [1, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(
+            vec![3, 2, 1, 4, 7, 6, 5, 8],
+            reorder_rgba8(&rgba, [Blue, Green, Red, Alpha])
+        );
+        assert_eq!(vec![1, 2, 3, 5, 6, 7], reorder_rgba8(&rgba, [Red, Green, Blue, Skip]));
+        assert_eq!(vec![4, 4, 8, 8], reorder_rgba8(&rgba, [Alpha, Skip, Alpha, Skip]));
+
+        // A8R8G8B8 is stored as BGRA, so asking for BGRA gives the stored bytes back.
+        let texture = texture(TextureFormat::A8R8G8B8, 1, 1, 1);
+        let texture = Texture {
+            data: vec![10, 20, 30, 40],
+            ..texture
+        };
+        assert_eq!(
+            texture.data,
+            texture.reorder_channels([Blue, Green, Red, Alpha]).unwrap()
+        );
+    }
+}